@@ -245,10 +245,18 @@ impl<T, const CAPACITY: usize> MruArena<T, CAPACITY> {
     }
 
     pub fn init(&mut self) {
+        self.set_capacity(CAPACITY);
+    }
+
+    /// Use only the first `capacity` entries. No entry may be referenced.
+    pub fn set_capacity(&mut self, capacity: usize) {
         self.head.init();
 
-        for entry in &mut self.entries {
-            self.head.prepend(&mut entry.list_entry);
+        for (i, entry) in self.entries.iter_mut().enumerate() {
+            assert_eq!(entry.refcnt, 0, "set_capacity: entry in use");
+            if i < capacity {
+                self.head.prepend(&mut entry.list_entry);
+            }
         }
     }
 }
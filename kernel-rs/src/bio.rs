@@ -13,7 +13,7 @@
 
 use crate::{
     arena::{Arena, ArenaObject, MruArena, MruEntry, Rc},
-    param::{BSIZE, NBUF},
+    param::{BSIZE, MAXNBUF},
     proc::WaitChannel,
     sleeplock::Sleeplock,
     spinlock::Spinlock,
@@ -23,7 +23,7 @@ use core::mem;
 use core::ops::{Deref, DerefMut};
 
 pub struct BufEntry {
-    dev: u32,
+    pub dev: u32,
     pub blockno: u32,
 
     /// WaitChannel saying virtio_disk request is done.
@@ -90,7 +90,8 @@ impl BufInner {
     }
 }
 
-pub type Bcache = Spinlock<MruArena<BufEntry, NBUF>>;
+/// The cache has room for MAXNBUF buffers, but uses only as many as the superblock asks for.
+pub type Bcache = Spinlock<MruArena<BufEntry, MAXNBUF>>;
 
 pub type BufUnlocked<'s> = Rc<Bcache, &'s Bcache>;
 
@@ -141,7 +142,7 @@ impl Bcache {
     pub const fn zero() -> Self {
         Spinlock::new(
             "BCACHE",
-            MruArena::new(array![_ => MruEntry::new(BufEntry::zero()); NBUF]),
+            MruArena::new(array![_ => MruEntry::new(BufEntry::zero()); MAXNBUF]),
         )
     }
 
@@ -160,13 +161,6 @@ impl Bcache {
 
         unsafe { Rc::from_unchecked(self, inner) }
     }
-
-    /// Retrieves BufUnlocked without increasing reference count.
-    pub fn buf_unforget(&self, dev: u32, blockno: u32) -> Option<BufUnlocked<'_>> {
-        let inner = self.unforget(|buf| buf.dev == dev && buf.blockno == blockno)?;
-
-        Some(unsafe { Rc::from_unchecked(self, inner) })
-    }
 }
 
 impl<'s> BufUnlocked<'s> {
@@ -1,16 +1,17 @@
 #![allow(clippy::unit_arg)]
 
 use crate::{
-    fs::{InodeGuard, Path},
+    fs::{Access, InodeGuard, InodeType, Path},
     kernel::Kernel,
     ok_or,
-    param::MAXARG,
+    param::{MAXARG, MAXINTERP, MAXPATH},
     proc::{myproc, proc_freepagetable, proc_pagetable, Proc},
     riscv::PGSIZE,
+    stat::{S_ISGID, S_ISUID},
     string::{safestrcpy, strlen},
     vm::{KVAddr, PageTable, UVAddr, VAddr},
 };
-use core::{cmp, mem, slice};
+use core::{cmp, mem, ptr, slice};
 
 /// "\x7FELF" in little endian
 const ELF_MAGIC: u32 = 0x464c457f;
@@ -88,8 +89,131 @@ impl ProgHdr {
     }
 }
 
+/// The first line of a "#!" script, which names its interpreter and an optional argument, as
+/// ranges of the line.
+struct Shebang {
+    interp: (usize, usize),
+    arg: Option<(usize, usize)>,
+}
+
+impl Shebang {
+    /// Parse the first line of a script, which begins with "#!", and nul-terminate the
+    /// interpreter and its argument in place. `len` is the number of bytes read into `line`, and
+    /// `eof` is true if they are the whole file.
+    fn parse(line: &mut [u8], len: usize, eof: bool) -> Result<Self, ()> {
+        let end = match line[..len].iter().position(|&c| c == b'\n') {
+            Some(end) => end,
+            None if eof && len < line.len() => len,
+            None => return Err(()),
+        };
+        let is_blank = |c: u8| c == b' ' || c == b'\t';
+
+        // Skip blanks after "#!".
+        let start = 2 + line[2..end].iter().position(|&c| !is_blank(c)).ok_or(())?;
+        let interp_end = start
+            + line[start..end]
+                .iter()
+                .position(|&c| is_blank(c))
+                .unwrap_or(end - start);
+        let interp = (start, interp_end);
+
+        // The rest of the line, without surrounding blanks, is a single argument.
+        let rest = &line[interp_end..end];
+        let arg = rest.iter().position(|&c| !is_blank(c)).map(|i| {
+            let last = rest.iter().rposition(|&c| !is_blank(c)).unwrap();
+            (interp_end + i, interp_end + last + 1)
+        });
+
+        // The interpreter and its argument are passed on as nul-terminated strings, so a nul in
+        // the line would cut them short.
+        if line[..end].contains(&0) {
+            return Err(());
+        }
+        line[interp.1] = 0;
+        if let Some((_, arg_end)) = arg {
+            line[arg_end] = 0;
+        }
+        Ok(Self { interp, arg })
+    }
+}
+
+/// What exec_file() found.
+enum Executable {
+    /// A program, which now runs in the process with argc as given.
+    Program(usize),
+    /// A "#!" script, whose interpreter is to be run instead.
+    Script(Shebang),
+}
+
+// The page exec() allocates holds the path and the first line of each script.
+const_assert!((MAXINTERP + 2) * MAXPATH <= PGSIZE);
+
 impl Kernel {
+    /// Execute the program at `path`. If it is a "#!" script, its interpreter runs instead with
+    /// the script's path and `argv[1..]` as its arguments, and so on for at most MAXINTERP nested
+    /// scripts. The chain is followed in a loop, since the kernel stack cannot hold a frame per
+    /// script.
     pub unsafe fn exec(&self, path: &Path, argv: &[*mut u8]) -> Result<usize, ()> {
+        // Holds the path and the first line of each script, which the arguments point into.
+        let mut page = scopeguard::guard(self.alloc().ok_or(())?, |page| self.free(page));
+        page.write_bytes(0);
+        let mut lines = page.chunks_exact_mut(MAXPATH);
+
+        let len = path.as_bytes().len();
+        if len >= MAXPATH {
+            return Err(());
+        }
+        let script = lines.next().unwrap();
+        script[..len].copy_from_slice(path.as_bytes());
+        let mut script = script.as_mut_ptr();
+
+        let mut argc = argv.iter().position(|arg| arg.is_null()).ok_or(())?;
+        let mut argv_buf = [ptr::null_mut(); MAXARG];
+        argv_buf[..argc].copy_from_slice(&argv[..argc]);
+
+        let mut path = path;
+        for depth in 0..=MAXINTERP {
+            let line = lines.next().unwrap();
+            let shebang = match self.exec_file(path, &argv_buf, line)? {
+                Executable::Program(argc) => return Ok(argc),
+                Executable::Script(shebang) => shebang,
+            };
+            if depth == MAXINTERP {
+                return Err(());
+            }
+
+            // The arguments become the interpreter, its argument if any, the script's path and
+            // argv[1..].
+            let prefix = if shebang.arg.is_some() { 3 } else { 2 };
+            let new_argc = argc - 1 + prefix;
+            // Leave room for the terminating null pointer.
+            if new_argc >= MAXARG {
+                return Err(());
+            }
+            argv_buf.copy_within(1..argc + 1, prefix);
+            argv_buf[0] = line.as_mut_ptr().add(shebang.interp.0);
+            if let Some((start, _)) = shebang.arg {
+                argv_buf[1] = line.as_mut_ptr().add(start);
+            }
+            argv_buf[prefix - 1] = script;
+            argc = new_argc;
+
+            script = argv_buf[0];
+            let line: &[u8] = line;
+            let (start, end) = shebang.interp;
+            path = Path::from_bytes(&line[start..end]);
+        }
+        unreachable!()
+    }
+
+    /// Execute the program at `path`, or if it is a "#!" script, read its first line into
+    /// `line`, which holds MAXPATH bytes, and return what it names.
+    unsafe fn exec_file(
+        &self,
+        path: &Path,
+        argv: &[*mut u8],
+        line: &mut [u8],
+    ) -> Result<Executable, ()> {
         let sz: usize = 0;
         let mut ustack = [0usize; MAXARG + 1];
         let mut elf: ElfHdr = Default::default();
@@ -108,6 +232,17 @@ impl Kernel {
             return Err(());
         });
         let mut ip = ptr.lock();
+        if ip.deref_inner().typ != InodeType::File {
+            return Err(());
+        }
+        ip.permission(Access::EXEC)?;
+
+        // Is it a script?
+        let bytes_read = ip.read(KVAddr::new(line.as_mut_ptr() as _), 0, line.len() as _)?;
+        if line[..bytes_read].starts_with(b"#!") {
+            let eof = ip.deref_inner().size as usize == bytes_read;
+            return Ok(Executable::Script(Shebang::parse(line, bytes_read, eof)?));
+        }
 
         // Check ELF header
         let bytes_read = ip.read(
@@ -160,6 +295,17 @@ impl Kernel {
                 )?;
             }
         }
+        let inner = ip.deref_inner();
+        let euid = if inner.mode & S_ISUID != 0 {
+            inner.uid
+        } else {
+            data.euid
+        };
+        let egid = if inner.mode & S_ISGID != 0 {
+            inner.gid
+        } else {
+            data.egid
+        };
         drop(ip);
 
         p = myproc();
@@ -240,6 +386,10 @@ impl Kernel {
             let mut oldpagetable = mem::replace(&mut data.pagetable, pt);
             data.sz = sz;
 
+            // Run a setuid or setgid executable with the owner's privileges.
+            data.euid = euid;
+            data.egid = egid;
+
             // initial program counter = main
             (*data.trapframe).epc = elf.entry;
 
@@ -248,7 +398,7 @@ impl Kernel {
             proc_freepagetable(&mut oldpagetable, oldsz);
 
             // this ends up in a0, the first argument to main(argc, argv)
-            return Ok(argc);
+            return Ok(Executable::Program(argc));
         }
         Err(())
     }
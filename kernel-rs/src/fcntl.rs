@@ -5,5 +5,7 @@ bitflags! {
         const O_RDWR = 0x2;
         const O_CREATE = 0x200;
         const O_TRUNC = 0x400;
+        const O_NOFOLLOW = 0x800;
+        const O_APPEND = 0x1000;
     }
 }
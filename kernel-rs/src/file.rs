@@ -2,11 +2,12 @@
 
 use crate::{
     arena::{Arena, ArenaObject, ArrayArena, ArrayEntry, Rc},
-    fs::RcInode,
+    fs::{InodeTimes, InodeType, RcInode},
     kernel::kernel,
-    param::{BSIZE, MAXOPBLOCKS, NFILE},
+    ok_or,
+    param::{BSIZE, MAXATOMICAPPEND, MAXOPBLOCKS, NFILE},
     pipe::AllocatedPipe,
-    proc::{myproc, Proc},
+    proc::{myproc, FdStat, Proc},
     spinlock::Spinlock,
     stat::Stat,
     vm::UVAddr,
@@ -21,6 +22,8 @@ pub enum FileType {
     Inode {
         ip: RcInode<'static>,
         off: UnsafeCell<u32>,
+        /// Opened with O_APPEND: every write goes to the end of the file.
+        append: bool,
     },
     Device {
         ip: RcInode<'static>,
@@ -87,6 +90,28 @@ impl File {
         }
     }
 
+    /// Describe self for procfs.
+    pub fn fdstat(&self) -> FdStat {
+        match &self.typ {
+            FileType::Pipe { .. } => FdStat {
+                kind: "pipe",
+                inum: 0,
+            },
+            FileType::Inode { ip, .. } => FdStat {
+                kind: "inode",
+                inum: ip.inum,
+            },
+            FileType::Device { ip, .. } => FdStat {
+                kind: "device",
+                inum: ip.inum,
+            },
+            FileType::None => FdStat {
+                kind: "none",
+                inum: 0,
+            },
+        }
+    }
+
     /// Read from file self.
     /// addr is a user virtual address.
     pub unsafe fn read(&self, addr: UVAddr, n: i32) -> Result<usize, ()> {
@@ -96,16 +121,7 @@ impl File {
 
         match &self.typ {
             FileType::Pipe { pipe } => pipe.read(addr, usize::try_from(n).unwrap_or(0)),
-            FileType::Inode { ip, off } => {
-                let mut ip = ip.deref().lock();
-                let curr_off = *off.get();
-                let ret = ip.read(addr, curr_off, n as u32);
-                if let Ok(v) = ret {
-                    *off.get() = curr_off.wrapping_add(v as u32);
-                }
-                drop(ip);
-                ret
-            }
+            FileType::Inode { ip, off, .. } => read_inode(ip, addr, n as u32, off.get()),
             FileType::Device { major, .. } => kernel()
                 .devsw
                 .get(*major as usize)
@@ -114,6 +130,99 @@ impl File {
             FileType::None => panic!("File::read"),
         }
     }
+    /// Read entries of directory self into user address addr as DirEntry records, as many as
+    /// fit in n bytes. Returns 0 at the end of the directory.
+    pub unsafe fn getdents(&self, addr: UVAddr, n: i32) -> Result<usize, ()> {
+        if !self.readable {
+            return Err(());
+        }
+        let n = usize::try_from(n).map_err(|_| ())?;
+
+        match &self.typ {
+            FileType::Inode { ip, off, .. } => {
+                let mut ip = ip.deref().lock();
+                if ip.deref_inner().typ != InodeType::Dir {
+                    return Err(());
+                }
+                ip.getdents(addr, &mut *off.get(), n)
+            }
+            _ => Err(()),
+        }
+    }
+
+    /// Read from file self at offset off, without using or changing the file offset.
+    pub unsafe fn pread(&self, addr: UVAddr, n: i32, off: u32) -> Result<usize, ()> {
+        if !self.readable {
+            return Err(());
+        }
+        let mut off = off;
+        match &self.typ {
+            FileType::Inode { ip, .. } => read_inode(ip, addr, n as u32, &mut off),
+            _ => Err(()),
+        }
+    }
+
+    /// Write to file self at offset off, without using or changing the file offset.
+    /// O_APPEND is ignored.
+    pub unsafe fn pwrite(&self, addr: UVAddr, n: i32, off: u32) -> Result<usize, ()> {
+        if !self.writable {
+            return Err(());
+        }
+        let mut off = off;
+        match &self.typ {
+            FileType::Inode { ip, .. } => write_inode(
+                ip,
+                addr,
+                usize::try_from(n).map_err(|_| ())?,
+                &mut off,
+                false,
+            ),
+            _ => Err(()),
+        }
+    }
+
+    /// Set the offset of file self to off bytes from the start (SEEK_SET), the current offset
+    /// (SEEK_CUR) or the end (SEEK_END), and return the new offset. The offset of a directory
+    /// can only be set back to 0, since getdents() must start at an entry.
+    pub unsafe fn lseek(&self, off: i32, whence: Whence) -> Result<usize, ()> {
+        match &self.typ {
+            FileType::Inode { ip, off: cur, .. } => {
+                let ip = ip.deref().lock();
+                let inner = ip.deref_inner();
+                let new = seek_offset(off, whence, *cur.get(), inner.size)?;
+                if inner.typ == InodeType::Dir && new != 0 {
+                    return Err(());
+                }
+                *cur.get() = new;
+                Ok(new as usize)
+            }
+            _ => Err(()),
+        }
+    }
+
+    /// Set the size of file self, which must be a regular file open for writing, to len.
+    pub unsafe fn truncate(&self, len: u32) -> Result<(), ()> {
+        if !self.writable {
+            return Err(());
+        }
+        match &self.typ {
+            FileType::Inode { ip, .. } => ip.truncate(len),
+            _ => Err(()),
+        }
+    }
+
+    /// Wait until all updates to the inode of file self are durable.
+    pub fn fsync(&self) -> Result<(), ()> {
+        match &self.typ {
+            FileType::Inode { ip, .. } | FileType::Device { ip, .. } => {
+                let seq = ip.lock().deref_inner().seq;
+                ip.fs().sync(ip.dev, seq);
+                Ok(())
+            }
+            _ => Err(()),
+        }
+    }
+
     /// Write to file self.
     /// addr is a user virtual address.
     pub unsafe fn write(&self, addr: UVAddr, n: i32) -> Result<usize, ()> {
@@ -123,46 +232,13 @@ impl File {
 
         match &self.typ {
             FileType::Pipe { pipe } => pipe.write(addr, usize::try_from(n).unwrap_or(0)),
-            FileType::Inode { ip, off } => {
-                // write a few blocks at a time to avoid exceeding
-                // the maximum log transaction size, including
-                // i-node, indirect block, allocation blocks,
-                // and 2 blocks of slop for non-aligned writes.
-                // this really belongs lower down, since write()
-                // might be writing a device like the console.
-                let max = (MAXOPBLOCKS - 1 - 1 - 2) / 2 * BSIZE;
-
-                // TODO(@kimjungwow) : To pass copyin() usertest, I reflect the commit on Nov 5, 2020 (below link).
-                // https://github.com/mit-pdos/xv6-riscv/commit/5e392531c07966fd8a6bee50e3e357c553fb2a2f
-                // This comment will be removed as we fetch upstream(mit-pdos)
-                let mut bytes_written: usize = 0;
-                while bytes_written < n as usize {
-                    let bytes_to_write = cmp::min(n as usize - bytes_written, max);
-                    let tx = kernel().file_system.begin_transaction();
-                    let mut ip = ip.deref().lock();
-                    let curr_off = *off.get();
-                    let r = ip
-                        .write(
-                            addr + bytes_written as usize,
-                            curr_off,
-                            bytes_to_write as u32,
-                            &tx,
-                        )
-                        .map(|v| {
-                            *off.get() = curr_off.wrapping_add(v as u32);
-                            v
-                        })?;
-                    if r != bytes_to_write as usize {
-                        // error from InodeGuard::write
-                        break;
-                    }
-                    bytes_written += r;
-                }
-                if bytes_written != n as usize {
-                    return Err(());
-                }
-                Ok(n as usize)
-            }
+            FileType::Inode { ip, off, append } => write_inode(
+                ip,
+                addr,
+                usize::try_from(n).map_err(|_| ())?,
+                off.get(),
+                *append,
+            ),
             FileType::Device { major, .. } => kernel()
                 .devsw
                 .get(*major as usize)
@@ -173,6 +249,119 @@ impl File {
     }
 }
 
+/// Whence of lseek().
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Whence {
+    Set,
+    Cur,
+    End,
+}
+
+impl Whence {
+    pub fn from_i32(whence: i32) -> Option<Self> {
+        match whence {
+            0 => Some(Self::Set),
+            1 => Some(Self::Cur),
+            2 => Some(Self::End),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the offset `off` bytes from `whence`, or Err if it is negative or too large.
+fn seek_offset(off: i32, whence: Whence, cur: u32, size: u32) -> Result<u32, ()> {
+    let base = match whence {
+        Whence::Set => 0,
+        Whence::Cur => cur,
+        Whence::End => size,
+    };
+    let new = base as i64 + off as i64;
+    u32::try_from(new).map_err(|_| ())
+}
+
+/// Read up to n bytes of the file ip, starting at offset `*off`, into user address addr, and
+/// advance `*off`. `*off` is only accessed while ip is locked.
+unsafe fn read_inode(
+    ip: &RcInode<'static>,
+    addr: UVAddr,
+    n: u32,
+    off: *mut u32,
+) -> Result<usize, ()> {
+    let mut guard = ip.deref().lock();
+    // Directories are read with getdents().
+    if guard.deref_inner().typ == InodeType::Dir {
+        return Err(());
+    }
+    let curr_off = *off;
+    let ret = guard.read(addr, curr_off, n)?;
+    *off = curr_off.wrapping_add(ret as u32);
+    if guard.atime_stale() {
+        // A transaction must begin before the inode is locked.
+        drop(guard);
+        let tx = kernel().file_system.begin_transaction();
+        let mut guard = ip.deref().lock();
+        guard.touch(InodeTimes::ACCESS);
+        guard.update(&tx);
+    }
+    Ok(ret)
+}
+
+// An append of MAXATOMICAPPEND bytes fits in one transaction of write_inode(), since every log
+// holds at least MAXOPBLOCKS blocks.
+const_assert!((MAXOPBLOCKS - 1 - 3 - 2) / 2 * BSIZE >= MAXATOMICAPPEND);
+
+/// Write n bytes from user address addr to the file ip at offset `*off`, or at its end if
+/// `append` is true, and advance `*off`. `*off` is only accessed while ip is locked.
+/// Returns the number of bytes written, which is less than n only if a write fails after
+/// some bytes were written.
+unsafe fn write_inode(
+    ip: &RcInode<'static>,
+    addr: UVAddr,
+    n: usize,
+    off: *mut u32,
+    append: bool,
+) -> Result<usize, ()> {
+    // Each transaction writes as many blocks as the log can hold. Writing a data block may
+    // also write an allocation block, and a transaction additionally writes the i-node, one
+    // indirect block per level of indirection, and 2 blocks of slop for non-aligned writes.
+    let max = (kernel().file_system.max_transaction_blocks() - 1 - 3 - 2) / 2 * BSIZE;
+
+    // TODO(@kimjungwow) : To pass copyin() usertest, I reflect the commit on Nov 5, 2020 (below link).
+    // https://github.com/mit-pdos/xv6-riscv/commit/5e392531c07966fd8a6bee50e3e357c553fb2a2f
+    // This comment will be removed as we fetch upstream(mit-pdos)
+    let mut bytes_written: usize = 0;
+    while bytes_written < n {
+        let bytes_to_write = cmp::min(n - bytes_written, max);
+        let nblocks = (bytes_to_write + BSIZE - 1) / BSIZE;
+        let tx = kernel()
+            .file_system
+            .begin_transaction_reserving(2 * nblocks + 1 + 3 + 2);
+        let mut guard = ip.deref().lock();
+        // Since the size is read under the inode lock, concurrent appends never overlap, and
+        // an append of at most max bytes, such as one of at most MAXATOMICAPPEND bytes, is
+        // not interleaved with other writes. The lock cannot be held across transactions.
+        let curr_off = if append {
+            guard.deref_inner().size
+        } else {
+            *off
+        };
+        let r = ok_or!(
+            guard.write(addr + bytes_written, curr_off, bytes_to_write as u32, &tx),
+            break
+        );
+        *off = curr_off.wrapping_add(r as u32);
+        bytes_written += r;
+        if r != bytes_to_write {
+            // error from InodeGuard::write
+            break;
+        }
+    }
+    if bytes_written == 0 && n != 0 {
+        return Err(());
+    }
+    Ok(bytes_written)
+}
+
 impl ArenaObject for File {
     fn finalize<'s, A: Arena>(&'s mut self, guard: &'s mut A::Guard<'_>) {
         A::reacquire_after(guard, || {
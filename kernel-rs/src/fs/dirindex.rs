@@ -0,0 +1,359 @@
+//! Hashed directory indexes.
+//!
+//! A directory that outgrows its first block is converted to an indexed directory. Its block 0
+//! then holds only the entries of "." and "..", followed by a free record whose body is the
+//! index root: a table of (hash, block) pairs sorted by hash. Every other block is a leaf
+//! holding the entries whose name hashes fall between the hash of its index entry and the hash
+//! of the next one. Since the root hides in a free record and leaves are ordinary directory
+//! blocks, code that scans a directory linearly, such as isdirempty() and getdents(), still
+//! works.
+//!
+//! A lookup in an indexed directory hashes the name, searches the root and scans a single
+//! leaf. When a leaf is full, the upper half of its entries by hash moves to a new leaf.
+//! Directories without the INDEX flag, which are small or were made by mkfs, are scanned
+//! linearly.
+//!
+//! A getdents() offset saved in an open file must stay usable while entries move. Entries
+//! only move to blocks past the end of the directory or to higher offsets in their block, so
+//! an entry that is neither added nor removed is never skipped, although it may be listed
+//! twice. An offset that ends up inside a record, such as the index root, resumes at the next
+//! record.
+
+use core::convert::TryInto;
+
+use crate::{bio::Buf, kernel::kernel, param::BSIZE};
+
+use super::{
+    inode::{InodeFlags, DIRENT_HDRSIZE},
+    Dirent, FileName, FsTransaction, InodeGuard, ALLOC_BLOCKS, NDIRECT, NINDIRECT,
+};
+
+/// Offset of the record holding the index root in block 0, after the records of "." and "..".
+const ROOT_OFF: usize = Dirent::rec_size(1) + Dirent::rec_size(2);
+
+/// Offset of the number of index entries in block 0.
+const COUNT_OFF: usize = ROOT_OFF + DIRENT_HDRSIZE;
+
+/// Offset of the first index entry, a (hash, block) pair of u32s, in block 0.
+const ENTRIES_OFF: usize = COUNT_OFF + 4;
+
+/// Maximum number of leaves of an indexed directory.
+pub(super) const MAXLEAVES: usize = (BSIZE - ENTRIES_OFF) / 8;
+
+// Allocating a leaf writes at most ALLOC_BLOCKS blocks.
+const_assert!(MAXLEAVES < NDIRECT + NINDIRECT);
+
+/// Maximum number of leaves split to make room for one entry. If the entry still does not fit,
+/// dirlink() fails.
+const MAXSPLITS: usize = 2;
+
+/// Most blocks that dirlink() writes: the block of the new entry, and the block allocated for
+/// it, the root and the inode when the directory is converted, and the new and old leaves, the
+/// root and the inode for each split.
+pub const DIRLINK_BLOCKS: usize = 1 + (ALLOC_BLOCKS + 2) + MAXSPLITS * (ALLOC_BLOCKS + 3);
+
+/// Maximum number of entries in a directory block.
+const MAXBLOCKENTRIES: usize = BSIZE / Dirent::rec_size(1);
+
+/// FNV-1a hash of a file name.
+pub(super) fn name_hash(name: &[u8]) -> u32 {
+    name.iter().fold(0x811c_9dc5, |hash, &c| {
+        (hash ^ c as u32).wrapping_mul(0x0100_0193)
+    })
+}
+
+fn get_u32(data: &[u8], off: usize) -> u32 {
+    u32::from_ne_bytes(data[off..off + 4].try_into().unwrap())
+}
+
+fn put_u32(data: &mut [u8], off: usize, value: u32) {
+    data[off..off + 4].copy_from_slice(&value.to_ne_bytes());
+}
+
+fn entry_off(i: usize) -> usize {
+    ENTRIES_OFF + 8 * i
+}
+
+/// Returns (inum, reclen, namelen) of the record at `off` in a directory block.
+fn header(data: &[u8], off: usize) -> (u32, usize, usize) {
+    let inum = get_u32(data, off);
+    let reclen = u16::from_ne_bytes(data[off + 4..off + 6].try_into().unwrap()) as usize;
+    let namelen = data[off + 6] as usize;
+    assert!(
+        reclen >= Dirent::rec_size(namelen) && reclen % 4 == 0 && off + reclen <= BSIZE,
+        "dirindex: bad record"
+    );
+    (inum, reclen, namelen)
+}
+
+fn set_reclen(data: &mut [u8], off: usize, reclen: usize) {
+    data[off + 4..off + 6].copy_from_slice(&(reclen as u16).to_ne_bytes());
+}
+
+fn record_name(data: &[u8], off: usize, namelen: usize) -> &[u8] {
+    &data[off + DIRENT_HDRSIZE..off + DIRENT_HDRSIZE + namelen]
+}
+
+fn put_record(data: &mut [u8], off: usize, inum: u32, reclen: usize, name: &[u8]) {
+    put_u32(data, off, inum);
+    set_reclen(data, off, reclen);
+    data[off + 6] = name.len() as u8;
+    data[off + 7] = 0;
+    data[off + DIRENT_HDRSIZE..off + DIRENT_HDRSIZE + name.len()].copy_from_slice(name);
+}
+
+/// Returns the (hash, block) entries of the index root in block 0 of an indexed directory, or
+/// None if the root record is not where dx_convert() puts it or the number of entries is out of
+/// range. Unlike dx_find(), it trusts nothing else in the block, so that fsck can use it.
+pub(super) fn index_root(data: &[u8]) -> Option<impl Iterator<Item = (u32, u32)> + '_> {
+    let reclen = u16::from_ne_bytes(data[ROOT_OFF + 4..ROOT_OFF + 6].try_into().unwrap());
+    let count = get_u32(data, COUNT_OFF) as usize;
+    if get_u32(data, ROOT_OFF) != 0
+        || reclen as usize != BSIZE - ROOT_OFF
+        || count < 1
+        || count > MAXLEAVES
+    {
+        return None;
+    }
+    Some((0..count).map(move |i| (get_u32(data, entry_off(i)), get_u32(data, entry_off(i) + 4))))
+}
+
+/// Move the records in use of a directory block for which `keep` returns true to the end of
+/// the block, in order, dropping the others, and leave the free space in an unused record at
+/// the start. No record moves to a lower offset.
+fn pack<F: Fn(&[u8]) -> bool>(data: &mut [u8], keep: F) {
+    let mut kept = [(0, 0); MAXBLOCKENTRIES];
+    let mut n = 0;
+    let mut off = 0;
+    while off < BSIZE {
+        let (inum, reclen, namelen) = header(data, off);
+        if inum != 0 && keep(record_name(data, off, namelen)) {
+            kept[n] = (off, Dirent::rec_size(namelen));
+            n += 1;
+        }
+        off += reclen;
+    }
+
+    // Going backwards, each record moves up over space that no earlier record uses.
+    let mut start = BSIZE;
+    for &(off, len) in kept[..n].iter().rev() {
+        start -= len;
+        data.copy_within(off..off + len, start);
+        set_reclen(data, start, len);
+    }
+    if start > 0 {
+        assert!(start >= DIRENT_HDRSIZE, "pack: no room for a free record");
+        put_record(data, 0, 0, start, &[]);
+    }
+}
+
+impl InodeGuard<'_> {
+    pub(super) fn is_indexed(&self) -> bool {
+        self.deref_inner().flags.contains(InodeFlags::INDEX)
+    }
+
+    /// Read block `bn` of the directory.
+    fn dir_block(&mut self, bn: usize) -> Buf<'static> {
+        let addr = self.bmap(bn);
+        kernel().file_system.disk.read(self.dev, addr)
+    }
+
+    /// Returns the position in the index and the block number of the leaf for `hash`.
+    fn dx_find(&mut self, hash: u32) -> (usize, usize) {
+        let mut bp = self.dir_block(0);
+        let data = &bp.deref_mut_inner().data[..];
+        let count = get_u32(data, COUNT_OFF) as usize;
+        assert!(count >= 1 && count <= MAXLEAVES, "dx_find: bad index");
+
+        // Find the last entry whose hash is not above `hash`. The first entry's hash is 0.
+        let (mut lo, mut hi) = (0, count);
+        while hi - lo > 1 {
+            let mid = (lo + hi) / 2;
+            if get_u32(data, entry_off(mid)) <= hash {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        (lo, get_u32(data, entry_off(lo) + 4) as usize)
+    }
+
+    /// Look for `name` in block `bn` of the directory.
+    /// If found, return its inode number and the byte offset of its entry.
+    fn scan_block(&mut self, bn: usize, name: &[u8]) -> Option<(u32, u32)> {
+        let mut bp = self.dir_block(bn);
+        let data = &bp.deref_mut_inner().data[..];
+        let mut off = 0;
+        while off < BSIZE {
+            let (inum, reclen, namelen) = header(data, off);
+            if inum != 0 && record_name(data, off, namelen) == name {
+                return Some((inum, (bn * BSIZE + off) as u32));
+            }
+            off += reclen;
+        }
+        None
+    }
+
+    /// dirlookup() for an indexed directory.
+    pub(super) fn dx_lookup(&mut self, name: &FileName) -> Option<(u32, u32)> {
+        let name = name.as_bytes();
+        if name == b"." || name == b".." {
+            return self.scan_block(0, name);
+        }
+        let (_, bn) = self.dx_find(name_hash(name));
+        self.scan_block(bn, name)
+    }
+
+    /// dirlink() for an indexed directory. Fails if a full leaf cannot be split, or if the entry
+    /// does not fit after MAXSPLITS splits.
+    pub(super) fn dx_link(
+        &mut self,
+        name: &FileName,
+        inum: u32,
+        tx: &FsTransaction<'_>,
+    ) -> Result<(), ()> {
+        let name = name.as_bytes();
+        let hash = name_hash(name);
+        for _ in 0..MAXSPLITS {
+            let (slot, bn) = self.dx_find(hash);
+            if self.leaf_insert(bn, name, inum, tx) {
+                return Ok(());
+            }
+            self.dx_split(slot, bn, tx)?;
+        }
+        let (_, bn) = self.dx_find(hash);
+        if self.leaf_insert(bn, name, inum, tx) {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
+    /// Write (name, inum) into block `bn` if a record there has enough unused space.
+    fn leaf_insert(&mut self, bn: usize, name: &[u8], inum: u32, tx: &FsTransaction<'_>) -> bool {
+        let needed = Dirent::rec_size(name.len());
+        let mut bp = self.dir_block(bn);
+        let data = &mut bp.deref_mut_inner().data[..];
+        let mut off = 0;
+        while off < BSIZE {
+            let (de_inum, reclen, namelen) = header(data, off);
+            let used = if de_inum == 0 {
+                0
+            } else {
+                Dirent::rec_size(namelen)
+            };
+            if reclen - used >= needed {
+                if used != 0 {
+                    set_reclen(data, off, used);
+                }
+                put_record(data, off + used, inum, reclen - used, name);
+                unsafe { tx.write(bp) };
+                return true;
+            }
+            off += reclen;
+        }
+        false
+    }
+
+    /// Move the entries of leaf `bn`, the `slot`th leaf in the index, with the upper half of the
+    /// hashes to a new leaf.
+    fn dx_split(&mut self, slot: usize, bn: usize, tx: &FsTransaction<'_>) -> Result<(), ()> {
+        let mut root = self.dir_block(0);
+        let count = get_u32(&root.deref_mut_inner().data[..], COUNT_OFF) as usize;
+        if count == MAXLEAVES {
+            return Err(());
+        }
+
+        let mut old = self.dir_block(bn);
+        let mut hashes = [0u32; MAXBLOCKENTRIES];
+        let mut n = 0;
+        let old_data = &mut old.deref_mut_inner().data[..];
+        let mut off = 0;
+        while off < BSIZE {
+            let (inum, reclen, namelen) = header(old_data, off);
+            if inum != 0 {
+                hashes[n] = name_hash(record_name(old_data, off, namelen));
+                n += 1;
+            }
+            off += reclen;
+        }
+        let hashes = &mut hashes[..n];
+        hashes.sort_unstable();
+
+        // Entries with equal hashes must stay in the same leaf, so split at the first change of
+        // hash from the middle up, or else from the middle down.
+        let mid = n / 2;
+        let split = (mid..n)
+            .chain((1..mid).rev())
+            .find(|&i| i > 0 && hashes[i] != hashes[i - 1])
+            .ok_or(())?;
+        let split_hash = hashes[split];
+
+        let new_bn = self.deref_inner().size as usize / BSIZE;
+        let addr = self.bmap_or_alloc(new_bn, tx)?;
+        let mut new = kernel().file_system.disk.read(self.dev, addr);
+        let new_data = &mut new.deref_mut_inner().data[..];
+        new_data.copy_from_slice(old_data);
+        pack(new_data, |name| name_hash(name) >= split_hash);
+        pack(old_data, |name| name_hash(name) < split_hash);
+
+        // Insert (split_hash, new_bn) after the old leaf's entry.
+        let root_data = &mut root.deref_mut_inner().data[..];
+        root_data.copy_within(entry_off(slot + 1)..entry_off(count), entry_off(slot + 2));
+        put_u32(root_data, entry_off(slot + 1), split_hash);
+        put_u32(root_data, entry_off(slot + 1) + 4, new_bn as u32);
+        put_u32(root_data, COUNT_OFF, count as u32 + 1);
+
+        unsafe {
+            tx.write(new);
+            tx.write(old);
+            tx.write(root);
+        }
+        self.deref_inner_mut().size = ((new_bn + 1) * BSIZE) as u32;
+        unsafe { self.update(tx) };
+        Ok(())
+    }
+
+    /// Index a directory of a single block, moving all its entries other than "." and ".." to
+    /// the first leaf.
+    pub(super) fn dx_convert(&mut self, tx: &FsTransaction<'_>) -> Result<(), ()> {
+        assert_eq!(self.deref_inner().size as usize, BSIZE, "dx_convert");
+        // "." and ".." keep their offsets.
+        let (dot, dot_off) = self.scan_block(0, b".").ok_or(())?;
+        let (dotdot, dotdot_off) = self.scan_block(0, b"..").ok_or(())?;
+        if dot_off != 0 || dotdot_off as usize != Dirent::rec_size(1) {
+            return Err(());
+        }
+
+        let mut root = self.dir_block(0);
+        let addr = self.bmap_or_alloc(1, tx)?;
+        let mut leaf = kernel().file_system.disk.read(self.dev, addr);
+        let root_data = &mut root.deref_mut_inner().data[..];
+        let leaf_data = &mut leaf.deref_mut_inner().data[..];
+        leaf_data.copy_from_slice(root_data);
+        pack(leaf_data, |name| name != b"." && name != b"..");
+
+        put_record(root_data, 0, dot, Dirent::rec_size(1), b".");
+        put_record(
+            root_data,
+            Dirent::rec_size(1),
+            dotdot,
+            Dirent::rec_size(2),
+            b"..",
+        );
+        put_record(root_data, ROOT_OFF, 0, BSIZE - ROOT_OFF, &[]);
+        put_u32(root_data, COUNT_OFF, 1);
+        put_u32(root_data, entry_off(0), 0);
+        put_u32(root_data, entry_off(0) + 4, 1);
+
+        unsafe {
+            tx.write(leaf);
+            tx.write(root);
+        }
+        let inner = self.deref_inner_mut();
+        inner.size = 2 * BSIZE as u32;
+        inner.flags.insert(InodeFlags::INDEX);
+        unsafe { self.update(tx) };
+        Ok(())
+    }
+}
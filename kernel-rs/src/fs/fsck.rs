@@ -0,0 +1,610 @@
+//! Consistency check of the file system, which does in the kernel what mkfs/fscheck.c does for
+//! an image on the host.
+//!
+//! The check runs as a transaction that reserves the whole log, so that no other file system
+//! call is in progress and the cached blocks hold a consistent file system. It only reports
+//! what it finds: repairing blocks under the inode cache would leave the cache out of date, so
+//! repairs are left to mkfs/fsck, which works on an image that is not in use.
+//!
+//! The checker never holds a buffer while it reads a block that a corrupt file system could
+//! make the same one, since locking a buffer twice would deadlock.
+
+use core::{cmp, fmt, mem, ptr, slice, str};
+
+use crate::{kernel::kernel, param::BSIZE, println, riscv::PGSIZE, some_or};
+
+use super::{
+    dirindex::{index_root, name_hash, MAXLEAVES},
+    inode::{DInodeType, InodeFlags},
+    Dinode, Dirent, Extent, FileSystem, Superblock, BPB, DIRENT_HDRSIZE, IPB, NDIRECT, NEXTENT,
+    NEXTENT_BLOCK, NINDIRECT, ROOTINO,
+};
+
+// check_index() numbers leaves with u8s.
+const_assert!(MAXLEAVES < u8::MAX as usize);
+
+struct Checker<'a> {
+    fs: &'a FileSystem,
+    dev: u32,
+    sb: Superblock,
+
+    /// First data block
+    datastart: u32,
+
+    /// Bit b is set once an inode uses block b.
+    used: &'a mut [u8],
+
+    /// Number of directory entries naming each inode
+    refs: &'a mut [u16],
+
+    /// Blocks used by the inode being walked
+    nused: u32,
+
+    /// Number of problems found
+    problems: usize,
+}
+
+impl FileSystem {
+    /// Check the file system on `dev`, printing each problem found, and return the number of
+    /// problems. Returns `Err(())` if the file system is too big to check.
+    pub fn check(&self, dev: u32) -> Result<usize, ()> {
+        let tx = self.begin_transaction_reserving(self.max_transaction_blocks());
+        let sb = *self.superblock(dev);
+        if sb.size as usize > PGSIZE * 8 || sb.ninodes as usize > PGSIZE / mem::size_of::<u16>() {
+            return Err(());
+        }
+
+        let mut used = unsafe { kernel().alloc() }.ok_or(())?;
+        let mut refs = some_or!(unsafe { kernel().alloc() }, {
+            unsafe { kernel().free(used) };
+            return Err(());
+        });
+        used.write_bytes(0);
+        refs.write_bytes(0);
+
+        let mut checker = Checker {
+            fs: self,
+            dev,
+            sb,
+            datastart: sb.bmapstart + sb.size / BPB + 1,
+            used: &mut used[..],
+            // SAFETY: a page is aligned and holds PGSIZE / 2 u16s.
+            refs: unsafe {
+                slice::from_raw_parts_mut(
+                    refs.as_mut_ptr() as *mut u16,
+                    PGSIZE / mem::size_of::<u16>(),
+                )
+            },
+            nused: 0,
+            problems: 0,
+        };
+        checker.run();
+        let problems = checker.problems;
+
+        unsafe {
+            kernel().free(used);
+            kernel().free(refs);
+        }
+        drop(tx);
+        Ok(problems)
+    }
+}
+
+impl Checker<'_> {
+    fn problem(&mut self, args: fmt::Arguments<'_>) {
+        println!("fsck: {}", args);
+        self.problems += 1;
+    }
+
+    fn run(&mut self) {
+        let sb = self.sb;
+        if sb.logstart != 2
+            || sb.inodestart != sb.logstart + sb.nlog
+            || sb.bmapstart != sb.inodestart + sb.ninodes / IPB as u32 + 1
+            || self.datastart >= sb.size
+        {
+            self.problem(format_args!(
+                "bad layout: log {} inodes {} bitmap {} size {}",
+                sb.logstart, sb.inodestart, sb.bmapstart, sb.size
+            ));
+            return;
+        }
+
+        // The blocks of each inode.
+        for inum in 1..sb.ninodes {
+            let dip = match self.dinode(inum) {
+                Ok(Some(dip)) => dip,
+                Ok(None) => continue,
+                Err(typ) => {
+                    self.problem(format_args!("inode {}: bad type {}", inum, typ));
+                    continue;
+                }
+            };
+            self.nused = 0;
+            if sb.has_extents() {
+                self.walk_extents(inum, &dip);
+            } else {
+                self.walk_blocks(inum, &dip);
+            }
+            if self.nused != dip.blocks {
+                let nused = self.nused;
+                self.problem(format_args!(
+                    "inode {}: uses {} blocks but counts {}",
+                    inum, nused, dip.blocks
+                ));
+            }
+        }
+
+        match self.dinode(ROOTINO) {
+            Ok(Some(dip)) if dip.typ == DInodeType::Dir => (),
+            _ => {
+                self.problem(format_args!("root inode is not a directory"));
+                return;
+            }
+        }
+
+        // The entries of each directory, and then its "..". Those of a directory that has been
+        // removed but is still open do not count.
+        for inum in 1..sb.ninodes {
+            if let Ok(Some(dip)) = self.dinode(inum) {
+                if dip.typ == DInodeType::Dir && dip.nlink > 0 {
+                    self.check_dir(inum, &dip);
+                }
+            }
+        }
+        for inum in 1..sb.ninodes {
+            if let Ok(Some(dip)) = self.dinode(inum) {
+                if dip.typ == DInodeType::Dir && dip.nlink > 0 {
+                    self.check_dotdot(inum, &dip);
+                }
+            }
+        }
+
+        // An inode that was removed while open has no links and no entries.
+        for inum in 1..sb.ninodes {
+            if let Ok(Some(dip)) = self.dinode(inum) {
+                let refs = self.refs[inum as usize];
+                if dip.nlink as i32 != refs as i32 {
+                    self.problem(format_args!(
+                        "inode {}: nlink {} but {} entries",
+                        inum, dip.nlink, refs
+                    ));
+                }
+            }
+        }
+
+        self.check_bitmap();
+    }
+
+    /// Returns a copy of inode inum, or `Ok(None)` if it is free, or `Err` with its type if that
+    /// is not a valid one.
+    fn dinode(&self, inum: u32) -> Result<Option<Dinode>, i16> {
+        let bp = self.fs.disk.read(self.dev, self.sb.iblock(inum));
+        // SAFETY: a block holds IPB Dinodes.
+        let dip =
+            unsafe { (bp.deref_inner().data.as_ptr() as *const Dinode).add(inum as usize % IPB) };
+        // The type is read as an integer first, since not every value is a DInodeType.
+        let typ = unsafe { ptr::read(dip as *const i16) };
+        match typ {
+            0 => Ok(None),
+            t if t <= DInodeType::Symlink as i16 && t > 0 => Ok(Some(unsafe { ptr::read(dip) })),
+            t => Err(t),
+        }
+    }
+
+    fn in_data(&self, b: u32) -> bool {
+        b >= self.datastart && b < self.sb.size
+    }
+
+    /// Note that inode inum uses block b. Returns true if b may be read.
+    fn use_block(&mut self, inum: u32, b: u32) -> bool {
+        self.nused += 1;
+        if !self.in_data(b) {
+            self.problem(format_args!("inode {}: block {} out of range", inum, b));
+            return false;
+        }
+        let (byte, bit) = (b as usize / 8, 1 << (b % 8));
+        if self.used[byte] & bit != 0 {
+            self.problem(format_args!(
+                "inode {}: block {} is also used by another inode",
+                inum, b
+            ));
+            return false;
+        }
+        self.used[byte] |= bit;
+        true
+    }
+
+    fn walk_blocks(&mut self, inum: u32, dip: &Dinode) {
+        // SAFETY: the file system does not have extents.
+        let addrs = unsafe { dip.addrs.blocks };
+        for &addr in addrs.addr_direct.iter().filter(|&&addr| addr != 0) {
+            let _ = self.use_block(inum, addr);
+        }
+        let indirect = [
+            addrs.addr_indirect,
+            addrs.addr_dindirect,
+            addrs.addr_tindirect,
+        ];
+        for (level, &addr) in (1..).zip(indirect.iter()) {
+            if addr != 0 {
+                self.walk_indirect(inum, addr, level);
+            }
+        }
+    }
+
+    /// Walk the `level`-indirect block at `addr`. Since every block on the way has been marked
+    /// used, a block that is reached twice is not read again.
+    fn walk_indirect(&mut self, inum: u32, addr: u32, level: usize) {
+        if !self.use_block(inum, addr) {
+            return;
+        }
+        let bp = self.fs.disk.read(self.dev, addr);
+        let (prefix, data, _) = unsafe { bp.deref_inner().data.align_to::<u32>() };
+        debug_assert_eq!(prefix.len(), 0, "fsck: Buf data unaligned");
+        for &a in data.iter().filter(|&&a| a != 0) {
+            if level > 1 {
+                self.walk_indirect(inum, a, level - 1);
+            } else {
+                let _ = self.use_block(inum, a);
+            }
+        }
+    }
+
+    fn walk_extents(&mut self, inum: u32, dip: &Dinode) {
+        // SAFETY: the file system has extents.
+        let addrs = unsafe { dip.addrs.extents };
+        let bp = if addrs.addr_extent != 0 && self.use_block(inum, addrs.addr_extent) {
+            Some(self.fs.disk.read(self.dev, addrs.addr_extent))
+        } else {
+            None
+        };
+        let block: &[Extent] = match &bp {
+            Some(bp) => unsafe { bp.deref_inner().data.align_to::<Extent>().1 },
+            None => &[],
+        };
+        // The file's blocks that the extents so far reach.
+        let mut end = 0;
+        for e in addrs
+            .extents
+            .iter()
+            .chain(block.iter())
+            .take_while(|e| e.len != 0)
+        {
+            if e.lbn < end {
+                self.problem(format_args!(
+                    "inode {}: extent at block {} out of order",
+                    inum, e.lbn
+                ));
+            }
+            end = e.lbn.saturating_add(e.len);
+            if !self.in_data(e.start) || e.len > self.sb.size - e.start {
+                self.problem(format_args!(
+                    "inode {}: extent {}+{} out of range",
+                    inum, e.start, e.len
+                ));
+                self.nused += e.len;
+                continue;
+            }
+            for b in e.start..e.start + e.len {
+                let _ = self.use_block(inum, b);
+            }
+        }
+    }
+
+    /// Returns the disk block of block `fbn` of `dip`, or 0 for a hole or a block that cannot be
+    /// read.
+    fn block_addr(&self, dip: &Dinode, fbn: usize) -> u32 {
+        if self.sb.has_extents() {
+            // SAFETY: the file system has extents.
+            let addrs = unsafe { dip.addrs.extents };
+            for n in 0..NEXTENT + NEXTENT_BLOCK {
+                let e = if n < NEXTENT {
+                    addrs.extents[n]
+                } else if self.in_data(addrs.addr_extent) {
+                    let bp = self.fs.disk.read(self.dev, addrs.addr_extent);
+                    unsafe { bp.deref_inner().data.align_to::<Extent>().1[n - NEXTENT] }
+                } else {
+                    break;
+                };
+                if e.len == 0 || fbn < e.lbn as usize {
+                    break;
+                }
+                if fbn < e.lbn as usize + e.len as usize {
+                    return e.start + (fbn - e.lbn as usize) as u32;
+                }
+            }
+            return 0;
+        }
+
+        // SAFETY: the file system does not have extents.
+        let addrs = unsafe { dip.addrs.blocks };
+        if fbn < NDIRECT {
+            return addrs.addr_direct[fbn];
+        }
+        let mut fbn = fbn - NDIRECT;
+        let mut nblocks = NINDIRECT;
+        for &addr in &[
+            addrs.addr_indirect,
+            addrs.addr_dindirect,
+            addrs.addr_tindirect,
+        ] {
+            if fbn >= nblocks {
+                fbn -= nblocks;
+                nblocks *= NINDIRECT;
+                continue;
+            }
+
+            // Walk down the indirect blocks.
+            let mut addr = addr;
+            while nblocks > 1 {
+                if !self.in_data(addr) {
+                    return 0;
+                }
+                nblocks /= NINDIRECT;
+                let bp = self.fs.disk.read(self.dev, addr);
+                let (_, data, _) = unsafe { bp.deref_inner().data.align_to::<u32>() };
+                addr = data[fbn / nblocks];
+                fbn %= nblocks;
+            }
+            return addr;
+        }
+        0
+    }
+
+    /// Call `f` with the block number and each entry of directory inum, reporting bad records if
+    /// `report` is set. `f` returns false to stop.
+    fn entries(
+        &mut self,
+        inum: u32,
+        dip: &Dinode,
+        report: bool,
+        mut f: impl FnMut(&mut Self, usize, &Dirent) -> bool,
+    ) {
+        let mut de: Dirent = Default::default();
+        for fbn in 0..dip.size as usize / BSIZE {
+            let addr = self.block_addr(dip, fbn);
+            if !self.in_data(addr) {
+                if report {
+                    self.problem(format_args!("directory {}: block {} is missing", inum, fbn));
+                }
+                continue;
+            }
+            let bp = self.fs.disk.read(self.dev, addr);
+            let data = &bp.deref_inner().data;
+            let mut off = 0;
+            while off < BSIZE {
+                if off + DIRENT_HDRSIZE > BSIZE {
+                    break;
+                }
+                // SAFETY: Dirent is repr(C), and its header fits in the rest of the block.
+                unsafe {
+                    ptr::copy_nonoverlapping(
+                        data[off..].as_ptr(),
+                        &mut de as *mut _ as *mut u8,
+                        DIRENT_HDRSIZE,
+                    )
+                };
+                let reclen = de.reclen as usize;
+                if reclen < DIRENT_HDRSIZE || reclen % 4 != 0 || off + reclen > BSIZE {
+                    break;
+                }
+                off += reclen;
+                if de.inum == 0 {
+                    continue;
+                }
+                let namelen = de.namelen as usize;
+                if namelen == 0 || Dirent::rec_size(namelen) > reclen {
+                    if report {
+                        self.problem(format_args!(
+                            "directory {}: bad name length {} at {}",
+                            inum,
+                            namelen,
+                            fbn * BSIZE + off - reclen
+                        ));
+                    }
+                    continue;
+                }
+                let start = off - reclen + DIRENT_HDRSIZE;
+                de.name[..namelen].copy_from_slice(&data[start..start + namelen]);
+                if !f(self, fbn, &de) {
+                    return;
+                }
+            }
+            if off < BSIZE && report {
+                self.problem(format_args!(
+                    "directory {}: bad record length at {}",
+                    inum,
+                    fbn * BSIZE + off
+                ));
+            }
+        }
+    }
+
+    /// Check the entries of directory inum, and count those naming each inode in refs. The
+    /// ".." of a directory is counted by check_dotdot().
+    fn check_dir(&mut self, inum: u32, dip: &Dinode) {
+        if dip.size as usize % BSIZE != 0 {
+            self.problem(format_args!(
+                "directory {}: size {} is not a whole number of blocks",
+                inum, dip.size
+            ));
+            return;
+        }
+        let mut dot = false;
+        self.entries(inum, dip, true, |this, _, de| {
+            let name = &de.name[..de.namelen as usize];
+            if de.inum >= this.sb.ninodes || !matches!(this.dinode(de.inum), Ok(Some(_))) {
+                this.problem(format_args!(
+                    "directory {}: an entry names free inode {}",
+                    inum, de.inum
+                ));
+            } else if name == b"." {
+                if de.inum != inum {
+                    this.problem(format_args!(
+                        "directory {}: \".\" names inode {}",
+                        inum, de.inum
+                    ));
+                }
+                dot = true;
+            } else if name != b".." {
+                this.refs[de.inum as usize] += 1;
+            }
+            true
+        });
+        if !dot {
+            self.problem(format_args!("directory {}: no \".\"", inum));
+        }
+        if InodeFlags::from_bits_truncate(dip.flags).contains(InodeFlags::INDEX) {
+            self.check_index(inum, dip);
+        }
+    }
+
+    /// Check the index of indexed directory inum: every block past block 0 is a leaf, the hashes
+    /// start at 0 and increase, and every entry is in the leaf of its name's hash.
+    fn check_index(&mut self, inum: u32, dip: &Dinode) {
+        let nblocks = dip.size as usize / BSIZE;
+        let addr = self.block_addr(dip, 0);
+        if nblocks < 2 || !self.in_data(addr) {
+            self.problem(format_args!("directory {}: index root is missing", inum));
+            return;
+        }
+        // The hash of each index entry, and the position in the index of each leaf plus one, or
+        // 0 for block 0. They are kept small, since they are on the kernel stack.
+        let mut hashes = [0; MAXLEAVES];
+        let mut slots = [0u8; MAXLEAVES + 1];
+        let mut count = 0;
+        let bp = self.fs.disk.read(self.dev, addr);
+        let entries = some_or!(index_root(&bp.deref_inner().data[..]), {
+            self.problem(format_args!("directory {}: bad index root", inum));
+            return;
+        });
+        for (hash, b) in entries {
+            let ordered = if count == 0 {
+                hash == 0
+            } else {
+                hash > hashes[count - 1]
+            };
+            if !ordered {
+                self.problem(format_args!(
+                    "directory {}: index hash {:x} out of order",
+                    inum, hash
+                ));
+                return;
+            }
+            let b = b as usize;
+            if b == 0 || b >= nblocks || b > MAXLEAVES || slots[b] != 0 {
+                self.problem(format_args!(
+                    "directory {}: index leaf {} out of range or repeated",
+                    inum, b
+                ));
+                return;
+            }
+            hashes[count] = hash;
+            count += 1;
+            slots[b] = count as u8;
+        }
+        drop(bp);
+        if count != nblocks - 1 {
+            self.problem(format_args!(
+                "directory {}: index has {} leaves for {} blocks",
+                inum,
+                count,
+                nblocks - 1
+            ));
+            return;
+        }
+
+        self.entries(inum, dip, false, |this, fbn, de| {
+            let name = &de.name[..de.namelen as usize];
+            let placed = match slots[fbn] as usize {
+                0 => name == b"." || name == b"..",
+                slot => {
+                    let hash = name_hash(name);
+                    hash >= hashes[slot - 1] && (slot == count || hash < hashes[slot])
+                }
+            };
+            if !placed {
+                this.problem(format_args!(
+                    "directory {}: entry {} is not in the leaf of its hash",
+                    inum,
+                    str::from_utf8(name).unwrap_or("?")
+                ));
+            }
+            placed
+        });
+    }
+
+    /// Check that the ".." of directory inum names a directory with an entry for it, or the root
+    /// itself for the root, and count it in refs.
+    fn check_dotdot(&mut self, inum: u32, dip: &Dinode) {
+        let mut parent = None;
+        self.entries(inum, dip, false, |_, _, de| {
+            if &de.name[..de.namelen as usize] == b".." {
+                parent = Some(de.inum);
+            }
+            parent.is_none()
+        });
+        let parent = match parent {
+            Some(parent) if parent < self.sb.ninodes => parent,
+            _ => {
+                self.problem(format_args!("directory {}: no \"..\"", inum));
+                return;
+            }
+        };
+        self.refs[parent as usize] += 1;
+
+        let named = if inum == ROOTINO {
+            parent == ROOTINO
+        } else if let Ok(Some(pdip)) = self.dinode(parent) {
+            let mut named = false;
+            if pdip.typ == DInodeType::Dir {
+                self.entries(parent, &pdip, false, |_, _, de| {
+                    let name = &de.name[..de.namelen as usize];
+                    named = de.inum == inum && name != b"." && name != b"..";
+                    !named
+                });
+            }
+            named
+        } else {
+            false
+        };
+        if !named {
+            self.problem(format_args!(
+                "directory {}: \"..\" names inode {}, which has no entry for it",
+                inum, parent
+            ));
+        }
+    }
+
+    /// Check that a block is marked allocated in the bitmap if and only if it is a metadata block
+    /// or used by an inode.
+    fn check_bitmap(&mut self) {
+        let mut b = 0;
+        while b < self.sb.size {
+            let bp = self.fs.disk.read(self.dev, self.sb.bblock(b));
+            let mut wrong = [0u32; 2];
+            let end = cmp::min(self.sb.size, (b / BPB + 1) * BPB);
+            for blockno in b..end {
+                let bi = (blockno % BPB) as usize;
+                let marked = bp.deref_inner().data[bi / 8] & (1 << (bi % 8)) != 0;
+                let used = blockno < self.datastart
+                    || self.used[blockno as usize / 8] & (1 << (blockno % 8)) != 0;
+                if marked != used {
+                    wrong[used as usize] += 1;
+                }
+            }
+            drop(bp);
+            if wrong[0] > 0 {
+                self.problem(format_args!(
+                    "{} unused blocks are marked allocated",
+                    wrong[0]
+                ));
+            }
+            if wrong[1] > 0 {
+                self.problem(format_args!("{} used blocks are marked free", wrong[1]));
+            }
+            b = end;
+        }
+    }
+}
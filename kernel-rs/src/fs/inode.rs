@@ -67,26 +67,38 @@
 //! dev, and inum.  One must hold ip->lock in order to
 //! read or write that inode's ip->valid, ip->size, ip->type, &c.
 
-use core::{mem, ops::Deref, ptr};
+use core::{cmp, mem, ops::Deref, ptr, slice};
 
 use crate::{
     arena::{Arena, ArenaObject, ArrayArena, ArrayEntry, Rc},
+    bio::Buf,
     fs::FsTransaction,
     kernel::kernel,
-    param::{BSIZE, NINODE},
+    ok_or,
+    param::{BSIZE, MAXOPBLOCKS, NINODE, PROCFSDEV, TMPFSDEV},
+    proc::myproc,
     sleeplock::Sleeplock,
+    some_or,
     spinlock::Spinlock,
     stat::Stat,
+    time,
     vm::{KVAddr, VAddr},
 };
 
-use super::{FileName, IPB, MAXFILE, NDIRECT, NINDIRECT};
+use super::{
+    DiskFs, FileName, InodeOps, SuperOps, BPB, IPB, MAXFILE, NDIRECT, NEXTENT, NEXTENT_BLOCK,
+    NINDIRECT,
+};
+
+/// Maximum length of a file name.
+pub const DIRSIZ: usize = 255;
 
-/// Directory is a file containing a sequence of Dirent structures.
-pub const DIRSIZ: usize = 14;
+/// Size of the fixed part of a Dirent, which precedes the name.
+pub const DIRENT_HDRSIZE: usize = 8;
 
-/// dirent size
-pub const DIRENT_SIZE: usize = mem::size_of::<Dirent>();
+/// Most blocks that allocating one of the first NDIRECT + NINDIRECT blocks of a file writes:
+/// the block, the indirect or extent block that maps it, and the bitmap blocks of both.
+pub const ALLOC_BLOCKS: usize = 4;
 
 #[derive(Copy, Clone, PartialEq, Debug)]
 #[repr(i16)]
@@ -95,6 +107,7 @@ pub enum InodeType {
     Dir,
     File,
     Device { major: u16, minor: u16 },
+    Symlink,
 }
 #[derive(Copy, Clone, PartialEq, Debug)]
 #[repr(i16)]
@@ -103,8 +116,52 @@ pub enum DInodeType {
     Dir,
     File,
     Device,
+    Symlink,
+}
+
+bitflags! {
+    /// Access to an inode requested by a process.
+    pub struct Access: u32 {
+        const READ = 0o4;
+        const WRITE = 0o2;
+        const EXEC = 0o1;
+    }
+}
+
+bitflags! {
+    pub struct InodeFlags: u32 {
+        /// The directory has a hash index. See dirindex.rs.
+        const INDEX = 1;
+    }
+}
+
+bitflags! {
+    /// Timestamps of an inode.
+    pub struct InodeTimes: u32 {
+        const ACCESS = 1;
+        const MODIFY = 2;
+        const CHANGE = 4;
+    }
 }
 
+/// Most data blocks Inode::truncate() frees in a transaction.
+const TRUNCATE_STEP: usize = NINDIRECT;
+
+/// Blocks a transaction of Inode::truncate() writes besides bitmap blocks: the inode, the zeroed
+/// last block, and at most six partly freed indirect blocks or one extent block.
+const TRUNCATE_FIXED_BLOCKS: usize = 8;
+
+/// Indirect or extent blocks that freeing a range of at most NINDIRECT data blocks frees along
+/// with them.
+const TRUNCATE_FREED_INDIRECT: usize = 6;
+
+/// What holes read as.
+static ZEROES: [u8; BSIZE] = [0; BSIZE];
+
+/// A read updates the access time only if it is not after the modification or change time, or
+/// is older than this many seconds, so that reading files rarely writes to the disk.
+const RELATIME_SECS: u32 = 24 * 60 * 60;
+
 pub struct InodeInner {
     /// inode has been read from disk?
     pub valid: bool,
@@ -112,8 +169,36 @@ pub struct InodeInner {
     pub typ: InodeType,
     pub nlink: i16,
     pub size: u32,
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub flags: InodeFlags,
+    /// Times of last access, modification, inode change and creation, in seconds
+    pub atime: u32,
+    pub mtime: u32,
+    pub ctime: u32,
+    pub crtime: u32,
+    /// Number of disk blocks allocated, including indirect and extent blocks
+    pub blocks: u32,
+    /// Sequence number of the log transaction group that last updated the inode
+    pub seq: u64,
     pub addr_direct: [u32; NDIRECT],
     pub addr_indirect: u32,
+    pub addr_dindirect: u32,
+    pub addr_tindirect: u32,
+    /// Used instead of the block addresses above if the file system has extents.
+    pub extents: [Extent; NEXTENT],
+    pub addr_extent: u32,
+}
+
+/// A run of `len` consecutive disk blocks starting at block `start`, which hold the blocks of a
+/// file starting at its block `lbn`.
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct Extent {
+    pub lbn: u32,
+    pub start: u32,
+    pub len: u32,
 }
 
 /// in-memory copy of an inode
@@ -135,7 +220,7 @@ pub struct Inode {
 #[repr(C)]
 pub struct Dinode {
     /// File type
-    typ: DInodeType,
+    pub(super) typ: DInodeType,
 
     /// Major device number (T_DEVICE only)
     major: u16,
@@ -144,18 +229,89 @@ pub struct Dinode {
     minor: u16,
 
     /// Number of links to inode in file system
-    nlink: i16,
+    pub(super) nlink: i16,
 
     /// Size of file (bytes)
-    size: u32,
+    pub(super) size: u32,
+
+    /// Permission bits
+    mode: u32,
+
+    /// Owner's user ID
+    uid: u32,
+
+    /// Owner's group ID
+    gid: u32,
+
+    /// Data block addresses, or extents if the file system has extents
+    pub(super) addrs: DinodeAddrs,
+
+    /// InodeFlags
+    pub(super) flags: u32,
 
+    /// Time of last access
+    atime: u32,
+
+    /// Time of last modification of the contents
+    mtime: u32,
+
+    /// Time of last change of the inode
+    ctime: u32,
+
+    /// Time of creation
+    crtime: u32,
+
+    /// Number of disk blocks allocated, including indirect and extent blocks
+    pub(super) blocks: u32,
+
+    /// Unused; pads Dinode so that a block holds a whole number of inodes.
+    spare: [u32; 5],
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub(super) union DinodeAddrs {
+    pub(super) blocks: BlockAddrs,
+    pub(super) extents: ExtentAddrs,
+}
+
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub(super) struct BlockAddrs {
     /// Direct data block addresses
-    addr_direct: [u32; NDIRECT],
+    pub(super) addr_direct: [u32; NDIRECT],
 
     /// Indirect data block address
-    addr_indirect: u32,
+    pub(super) addr_indirect: u32,
+
+    /// Doubly-indirect data block address
+    pub(super) addr_dindirect: u32,
+
+    /// Triply-indirect data block address
+    pub(super) addr_tindirect: u32,
+}
+
+/// The data blocks of a file are listed by its extents, first those in the inode, and then those
+/// in its extent block. The extents are sorted by `lbn` and do not overlap, and the first extent
+/// of length 0 ends the list. A block of the file that no extent holds is a hole.
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub(super) struct ExtentAddrs {
+    pub(super) extents: [Extent; NEXTENT],
+
+    /// Address of the block holding the following NEXTENT_BLOCK extents
+    pub(super) addr_extent: u32,
+
+    /// Unused; pads ExtentAddrs to the size of BlockAddrs.
+    spare: [u32; 2],
 }
 
+// Both formats occupy the same space in Dinode.
+const_assert!(mem::size_of::<BlockAddrs>() == mem::size_of::<ExtentAddrs>());
+
+// A block must hold a whole number of `Dinode`s.
+const_assert!(BSIZE % mem::size_of::<Dinode>() == 0);
+
 pub type Itable = Spinlock<ArrayArena<Inode, NINODE>>;
 
 pub type RcInode<'s> = Rc<Itable, &'s Itable>;
@@ -176,47 +332,135 @@ pub struct InodeGuard<'a> {
     pub inode: &'a Inode,
 }
 
-#[derive(Default)]
+/// Directory is a file containing a sequence of variable-length Dirent records.
+/// Records do not cross block boundaries, and every block of a directory is
+/// covered by records: the last record of a block extends to its end, and
+/// space freed by unlink stays in the record until dirlink reuses it.
+/// Only the first `reclen` bytes of a Dirent are stored, and the name is not
+/// NUL-terminated. A directory that outgrows its first block gets a hash
+/// index, which is kept in a free record of block 0 (see dirindex.rs).
+#[repr(C)]
 pub struct Dirent {
-    pub inum: u16,
-    name: [u8; DIRSIZ],
+    /// Inode number, or 0 if the record is free.
+    pub inum: u32,
+
+    /// Length of the record, including the header, name and padding.
+    pub(super) reclen: u16,
+
+    /// Length of name.
+    pub(super) namelen: u8,
+
+    pad: u8,
+
+    /// One byte longer than the longest name, so that Dirent has no padding.
+    pub(super) name: [u8; DIRSIZ + 1],
+}
+
+impl Default for Dirent {
+    fn default() -> Self {
+        Self {
+            inum: 0,
+            reclen: 0,
+            namelen: 0,
+            pad: 0,
+            name: [0; DIRSIZ + 1],
+        }
+    }
 }
 
 impl Dirent {
-    /// Fill in name. If name is shorter than DIRSIZ, NUL character is appended as
-    /// terminator.
-    ///
-    /// `name` must contains no NUL characters, but this is not a safety invariant.
+    /// Returns a record just long enough for (inum, name).
+    /// `name` must not be longer than DIRSIZ.
+    pub fn new(inum: u32, name: &FileName) -> Self {
+        let mut de: Self = Default::default();
+        de.inum = inum;
+        de.set_name(name);
+        de.reclen = Self::rec_size(de.namelen as usize) as u16;
+        de
+    }
+
+    /// Size of the smallest record holding a name of length `namelen`.
+    /// Records are 4-byte aligned.
+    pub const fn rec_size(namelen: usize) -> usize {
+        (DIRENT_HDRSIZE + namelen + 3) & !3
+    }
+
+    /// Fill in name.
     fn set_name(&mut self, name: &FileName) {
         let name = name.as_bytes();
-        if name.len() == DIRSIZ {
-            self.name.copy_from_slice(&name);
-        } else {
-            self.name[..name.len()].copy_from_slice(&name);
-            self.name[name.len()] = 0;
-        }
+        assert!(name.len() <= DIRSIZ, "set_name");
+        self.name[..name.len()].copy_from_slice(&name);
+        self.namelen = name.len() as u8;
     }
 
     /// Returns slice which exactly contains `name`.
     ///
     /// It contains no NUL characters.
     fn get_name(&self) -> &FileName {
-        let len = self.name.iter().position(|ch| *ch == 0).unwrap_or(DIRSIZ);
-        // Safety: self.name[..len] doesn't contain '\0', and len must be <= DIRSIZ.
-        unsafe { FileName::from_bytes(&self.name[..len]) }
+        // Safety: a name in a directory doesn't contain '\0'.
+        unsafe { FileName::from_bytes(&self.name[..self.namelen as usize]) }
     }
 
-    // TODO: Use iterator
-    fn read_entry(&mut self, ip: &mut InodeGuard<'_>, off: u32, panic_msg: &'static str) {
+    /// The bytes of the record up to the end of the name, which is all that needs to be written
+    /// when the record is modified.
+    fn as_bytes(&self) -> &[u8] {
+        let len = DIRENT_HDRSIZE + self.namelen as usize;
+        // SAFETY: Dirent is repr(C) and has no padding, and len <= size_of::<Dirent>().
+        unsafe { slice::from_raw_parts(self as *const Self as *const u8, len) }
+    }
+
+    /// Read the record at offset off of the directory ip. Fails if there is no well-formed
+    /// record there.
+    fn read_record(&mut self, ip: &mut InodeGuard<'_>, off: u32) -> Result<(), ()> {
         let bytes_read = ip.read(
             KVAddr::new(self as *mut Dirent as usize),
             off,
-            DIRENT_SIZE as u32,
+            DIRENT_HDRSIZE as u32,
+        )?;
+        let namelen = self.namelen as usize;
+        let reclen = self.reclen as usize;
+        if bytes_read != DIRENT_HDRSIZE
+            || reclen < Self::rec_size(namelen)
+            || reclen % 4 != 0
+            || (off as usize % BSIZE) + reclen > BSIZE
+        {
+            return Err(());
+        }
+        let bytes_read = ip.read(
+            KVAddr::new(self.name.as_mut_ptr() as usize),
+            off + DIRENT_HDRSIZE as u32,
+            namelen as u32,
+        )?;
+        if bytes_read != namelen {
+            return Err(());
+        }
+        Ok(())
+    }
+
+    // TODO: Use iterator
+    fn read_entry(&mut self, ip: &mut InodeGuard<'_>, off: u32, panic_msg: &'static str) {
+        assert!(
+            self.read_record(ip, off).is_ok(),
+            "{}: bad record",
+            panic_msg
+        );
+    }
+
+    fn write_entry(&self, ip: &mut InodeGuard<'_>, off: u32, tx: &FsTransaction<'_>) {
+        let bytes = self.as_bytes();
+        let bytes_write = ip.write(
+            KVAddr::new(bytes.as_ptr() as usize),
+            off,
+            bytes.len() as u32,
+            tx,
         );
-        assert_eq!(bytes_read, Ok(DIRENT_SIZE), "{}", panic_msg)
+        assert_eq!(bytes_write, Ok(bytes.len()), "write_entry");
     }
 }
 
+// A record with the longest name fits in a Dirent.
+const_assert!(Dirent::rec_size(DIRSIZ) == mem::size_of::<Dirent>());
+
 impl Deref for InodeGuard<'_> {
     type Target = Inode;
 
@@ -233,6 +477,45 @@ impl InodeGuard<'_> {
     pub fn deref_inner_mut(&mut self) -> &mut InodeInner {
         unsafe { self.inner.get_mut_unchecked() }
     }
+
+    /// Check whether the current process may access this inode as requested.
+    /// The process's effective IDs are used.
+    /// The owner's, group's or others' permission bits are used depending on who the
+    /// process is. The superuser may read and write anything, and may execute a file
+    /// if anybody can. Nobody may write to a read-only file system.
+    pub fn permission(&self, access: Access) -> Result<(), ()> {
+        let (uid, gid) = unsafe {
+            let data = &*(*myproc()).data.get();
+            (data.euid, data.egid)
+        };
+        let inner = self.deref_inner();
+
+        if access.contains(Access::WRITE) && self.fs().read_only() {
+            return Err(());
+        }
+        if uid == 0 {
+            if !access.contains(Access::EXEC)
+                || inner.typ == InodeType::Dir
+                || inner.mode & 0o111 != 0
+            {
+                return Ok(());
+            }
+            return Err(());
+        }
+
+        let granted = if uid == inner.uid {
+            inner.mode >> 6
+        } else if gid == inner.gid {
+            inner.mode >> 3
+        } else {
+            inner.mode
+        };
+        if Access::from_bits_truncate(granted).contains(access) {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
 }
 
 /// Unlock and put the given inode.
@@ -244,289 +527,835 @@ impl Drop for InodeGuard<'_> {
     }
 }
 
-// Directories
-impl InodeGuard<'_> {
-    /// Write a new directory entry (name, inum) into the directory dp.
-    pub fn dirlink(
-        &mut self,
-        name: &FileName,
-        inum: u32,
-        tx: &FsTransaction<'_>,
-    ) -> Result<(), ()> {
-        let mut de: Dirent = Default::default();
-
-        // Check that name is not present.
-        if let Ok((_ip, _)) = self.dirlookup(name) {
-            return Err(());
+impl SuperOps for DiskFs {
+    fn read_inode(&self, ip: &Inode, inner: &mut InodeInner) {
+        let mut bp = kernel().file_system.disk.read(
+            ip.dev,
+            kernel().file_system.superblock(ip.dev).iblock(ip.inum),
+        );
+        let dip: &mut Dinode = unsafe {
+            &mut *((bp.deref_mut_inner().data.as_mut_ptr() as *mut Dinode)
+                .add((ip.inum as usize).wrapping_rem(IPB)))
         };
-
-        // Look for an empty Dirent.
-        let mut off: u32 = 0;
-        while off < self.deref_inner().size {
-            de.read_entry(self, off, "dirlink read");
-            if de.inum == 0 {
-                break;
+        match (*dip).typ {
+            DInodeType::None => inner.typ = InodeType::None,
+            DInodeType::Dir => inner.typ = InodeType::Dir,
+            DInodeType::File => inner.typ = InodeType::File,
+            DInodeType::Symlink => inner.typ = InodeType::Symlink,
+            DInodeType::Device => {
+                inner.typ = InodeType::Device {
+                    major: (*dip).major,
+                    minor: (*dip).minor,
+                }
             }
-            off = (off as usize).wrapping_add(DIRENT_SIZE) as u32
         }
-        de.inum = inum as u16;
-        de.set_name(name);
-        let bytes_write = self.write(
-            KVAddr::new(&mut de as *mut Dirent as usize),
-            off,
-            DIRENT_SIZE as u32,
-            tx,
-        );
-        assert_eq!(bytes_write, Ok(DIRENT_SIZE), "dirlink");
-        Ok(())
-    }
-
-    /// Look for a directory entry in a directory.
-    /// If found, return the entry and byte offset of entry.
-    pub fn dirlookup(&mut self, name: &FileName) -> Result<(RcInode<'static>, u32), ()> {
-        let mut de: Dirent = Default::default();
-
-        assert_eq!(self.deref_inner().typ, InodeType::Dir, "dirlookup not DIR");
-
-        for off in (0..self.deref_inner().size).step_by(DIRENT_SIZE) {
-            de.read_entry(self, off, "dirlookup read");
-            if de.inum != 0 && name == de.get_name() {
-                // entry matches path element
-                return Ok((kernel().itable.get_inode(self.dev, de.inum as u32), off));
-            }
+        inner.nlink = (*dip).nlink;
+        inner.size = (*dip).size;
+        inner.mode = (*dip).mode;
+        inner.uid = (*dip).uid;
+        inner.gid = (*dip).gid;
+        inner.flags = InodeFlags::from_bits_truncate((*dip).flags);
+        inner.atime = (*dip).atime;
+        inner.mtime = (*dip).mtime;
+        inner.ctime = (*dip).ctime;
+        inner.crtime = (*dip).crtime;
+        inner.blocks = (*dip).blocks;
+        // The inode may have been updated in a transaction group that is not yet on disk.
+        inner.seq = u64::MAX;
+        // SAFETY: the superblock tells which format the inodes of the file system use.
+        if kernel().file_system.superblock(ip.dev).has_extents() {
+            let addrs = unsafe { dip.addrs.extents };
+            inner.extents = addrs.extents;
+            inner.addr_extent = addrs.addr_extent;
+        } else {
+            let addrs = unsafe { dip.addrs.blocks };
+            inner.addr_direct = addrs.addr_direct;
+            inner.addr_indirect = addrs.addr_indirect;
+            inner.addr_dindirect = addrs.addr_dindirect;
+            inner.addr_tindirect = addrs.addr_tindirect;
         }
-        Err(())
     }
-}
 
-impl InodeGuard<'_> {
-    /// Copy a modified in-memory inode to disk.
-    /// Must be called after every change to an ip->xxx field
-    /// that lives on disk.
-    pub unsafe fn update(&self, tx: &FsTransaction<'_>) {
+    fn write_inode(&self, ip: &InodeGuard<'_>, tx: &FsTransaction<'_>) {
         let mut bp = kernel().file_system.disk.read(
-            self.dev,
-            kernel().file_system.superblock().iblock(self.inum),
+            ip.dev,
+            kernel().file_system.superblock(ip.dev).iblock(ip.inum),
         );
-        let mut dip = (bp.deref_mut_inner().data.as_mut_ptr() as *mut Dinode)
-            .add((self.inum as usize).wrapping_rem(IPB));
-        let inner = self.deref_inner();
+        let dip = unsafe {
+            &mut *((bp.deref_mut_inner().data.as_mut_ptr() as *mut Dinode)
+                .add((ip.inum as usize).wrapping_rem(IPB)))
+        };
+        let inner = ip.deref_inner();
         match inner.typ {
             InodeType::Device { major, minor } => {
-                (*dip).typ = DInodeType::Device;
-                (*dip).major = major;
-                (*dip).minor = minor;
+                dip.typ = DInodeType::Device;
+                dip.major = major;
+                dip.minor = minor;
             }
             InodeType::None => {
-                (*dip).typ = DInodeType::None;
-                (*dip).major = 0;
-                (*dip).minor = 0;
+                dip.typ = DInodeType::None;
+                dip.major = 0;
+                dip.minor = 0;
             }
             InodeType::Dir => {
-                (*dip).typ = DInodeType::Dir;
-                (*dip).major = 0;
-                (*dip).minor = 0;
+                dip.typ = DInodeType::Dir;
+                dip.major = 0;
+                dip.minor = 0;
             }
             InodeType::File => {
-                (*dip).typ = DInodeType::File;
-                (*dip).major = 0;
-                (*dip).minor = 0;
+                dip.typ = DInodeType::File;
+                dip.major = 0;
+                dip.minor = 0;
+            }
+            InodeType::Symlink => {
+                dip.typ = DInodeType::Symlink;
+                dip.major = 0;
+                dip.minor = 0;
             }
         }
 
-        (*dip).nlink = inner.nlink;
-        (*dip).size = inner.size;
-        (*dip).addr_direct.copy_from_slice(&inner.addr_direct);
-        (*dip).addr_indirect = inner.addr_indirect;
-        tx.write(bp);
-    }
-
-    /// Truncate inode (discard contents).
-    /// This function is called with Inode's lock is held.
-    pub unsafe fn itrunc(&mut self, tx: &FsTransaction<'_>) {
-        let dev = self.dev;
-        for addr in &mut self.deref_inner_mut().addr_direct {
-            if *addr != 0 {
-                tx.bfree(dev, *addr);
-                *addr = 0;
-            }
+        dip.nlink = inner.nlink;
+        dip.size = inner.size;
+        dip.mode = inner.mode;
+        dip.uid = inner.uid;
+        dip.gid = inner.gid;
+        dip.flags = inner.flags.bits();
+        dip.atime = inner.atime;
+        dip.mtime = inner.mtime;
+        dip.ctime = inner.ctime;
+        dip.crtime = inner.crtime;
+        dip.blocks = inner.blocks;
+        if kernel().file_system.superblock(ip.dev).has_extents() {
+            dip.addrs.extents = ExtentAddrs {
+                extents: inner.extents,
+                addr_extent: inner.addr_extent,
+                spare: [0; 2],
+            };
+        } else {
+            dip.addrs.blocks = BlockAddrs {
+                addr_direct: inner.addr_direct,
+                addr_indirect: inner.addr_indirect,
+                addr_dindirect: inner.addr_dindirect,
+                addr_tindirect: inner.addr_tindirect,
+            };
         }
+        unsafe {
+            ip.inner.get_mut_unchecked().seq = tx.seq(ip.dev);
+            tx.write(bp);
+        }
+    }
 
-        if self.deref_inner().addr_indirect != 0 {
+    /// Takes the first inode whose type on disk is zero.
+    fn alloc_inode(&self, dev: u32, typ: InodeType, tx: &FsTransaction<'_>) -> Result<u32, ()> {
+        for inum in 1..kernel().file_system.superblock(dev).ninodes {
             let mut bp = kernel()
                 .file_system
                 .disk
-                .read(dev, self.deref_inner().addr_indirect);
-            let a = bp.deref_mut_inner().data.as_mut_ptr() as *mut u32;
-            for j in 0..NINDIRECT {
-                if *a.add(j) != 0 {
-                    tx.bfree(dev, *a.add(j));
+                .read(dev, kernel().file_system.superblock(dev).iblock(inum));
+            let dip = unsafe {
+                &mut *((bp.deref_mut_inner().data.as_mut_ptr() as *mut Dinode)
+                    .add((inum as usize).wrapping_rem(IPB)))
+            };
+
+            // a free inode
+            if dip.typ == DInodeType::None {
+                unsafe { ptr::write_bytes(dip as *mut Dinode, 0, 1) };
+                match typ {
+                    InodeType::None => dip.typ = DInodeType::None,
+                    InodeType::Dir => dip.typ = DInodeType::Dir,
+                    InodeType::File => dip.typ = DInodeType::File,
+                    InodeType::Symlink => dip.typ = DInodeType::Symlink,
+                    InodeType::Device { major, minor } => {
+                        dip.typ = DInodeType::Device;
+                        dip.major = major;
+                        dip.minor = minor
+                    }
                 }
+                let now = time::now();
+                dip.atime = now;
+                dip.mtime = now;
+                dip.ctime = now;
+                dip.crtime = now;
+
+                // mark it allocated on the disk
+                unsafe { tx.write(bp) };
+                return Ok(inum);
             }
-            drop(bp);
-            tx.bfree(dev, self.deref_inner().addr_indirect);
-            self.deref_inner_mut().addr_indirect = 0
         }
+        Err(())
+    }
 
-        self.deref_inner_mut().size = 0;
-        self.update(tx);
+    fn free_inode(&self, ip: &mut InodeGuard<'_>, tx: &FsTransaction<'_>) {
+        unsafe { ip.free_from(0, tx) };
+        let inner = ip.deref_inner_mut();
+        inner.typ = InodeType::None;
+        inner.size = 0;
+        inner.flags = InodeFlags::empty();
+        self.write_inode(ip, tx);
     }
 
-    /// Read data from inode.
-    pub fn read<A: VAddr>(&mut self, mut dst: A, mut off: u32, mut n: u32) -> Result<usize, ()> {
-        let inner = self.deref_inner();
-        if off > inner.size || off.wrapping_add(n) < off {
-            return Ok(0);
+    fn mount(&self, dev: u32) -> Result<(), ()> {
+        if !kernel().file_system.disk.exists(dev) {
+            return Err(());
+        }
+        kernel().file_system.init(dev)
+    }
+
+    fn unmount(&self, dev: u32) {
+        // No transaction can write to the log any more.
+        self.sync(dev, u64::MAX);
+    }
+
+    fn sync(&self, dev: u32, seq: u64) {
+        kernel().file_system.sync(dev, seq);
+    }
+}
+
+// Directories and file contents
+impl InodeOps for DiskFs {
+    fn lookup(
+        &self,
+        dp: &mut InodeGuard<'_>,
+        name: &FileName,
+    ) -> Result<(RcInode<'static>, u32), ()> {
+        let mut de: Dirent = Default::default();
+
+        if dp.is_indexed() {
+            let (inum, off) = dp.dx_lookup(name).ok_or(())?;
+            return Ok((kernel().itable.get_inode(dp.dev, inum), off));
+        }
+
+        let mut off: u32 = 0;
+        while off < dp.deref_inner().size {
+            de.read_entry(dp, off, "dirlookup read");
+            if de.inum != 0 && name == de.get_name() {
+                // entry matches path element
+                return Ok((kernel().itable.get_inode(dp.dev, de.inum), off));
+            }
+            off += de.reclen as u32;
+        }
+        Err(())
+    }
+
+    /// Adds the record of (name, inum) to the directory.
+    fn link(
+        &self,
+        dp: &mut InodeGuard<'_>,
+        name: &FileName,
+        inum: u32,
+        tx: &FsTransaction<'_>,
+    ) -> Result<(), ()> {
+        let mut de: Dirent = Default::default();
+
+        if dp.is_indexed() {
+            return dp.dx_link(name, inum, tx);
+        }
+
+        let mut new = Dirent::new(inum, name);
+        let needed = new.reclen as usize;
+
+        // Look for a record with enough unused space, and split it.
+        let mut off: u32 = 0;
+        while off < dp.deref_inner().size {
+            de.read_entry(dp, off, "dirlink read");
+            let used = if de.inum == 0 {
+                0
+            } else {
+                Dirent::rec_size(de.namelen as usize)
+            };
+            if de.reclen as usize - used >= needed {
+                new.reclen = de.reclen - used as u16;
+                if used != 0 {
+                    de.reclen = used as u16;
+                    de.write_entry(dp, off, tx);
+                }
+                new.write_entry(dp, off + used as u32, tx);
+                return Ok(());
+            }
+            off += de.reclen as u32;
+        }
+
+        // A directory that outgrows its first block gets an index.
+        if off as usize == BSIZE && dp.dx_convert(tx).is_ok() {
+            return dp.dx_link(name, inum, tx);
         }
-        if off.wrapping_add(n) > inner.size {
-            n = inner.size.wrapping_sub(off)
+
+        // Add a block holding just the new record.
+        new.reclen = BSIZE as u16;
+        new.write_entry(dp, off, tx);
+        dp.deref_inner_mut().size = off + BSIZE as u32;
+        Ok(())
+    }
+
+    fn replace(&self, dp: &mut InodeGuard<'_>, off: u32, inum: u32, tx: &FsTransaction<'_>) {
+        let mut de: Dirent = Default::default();
+        de.read_entry(dp, off, "dirreplace read");
+        de.inum = inum;
+        de.write_entry(dp, off, tx);
+    }
+
+    fn readdir(
+        &self,
+        dp: &mut InodeGuard<'_>,
+        off: &mut u32,
+        f: &mut dyn FnMut(u32, &FileName) -> Result<bool, ()>,
+    ) -> Result<(), ()> {
+        let mut de: Dirent = Default::default();
+        if *off % 4 != 0 || *off > dp.deref_inner().size {
+            return Err(());
         }
+        // Records may have moved since *off was handed out (see dirindex.rs), so resume at the
+        // first record of its block that starts at or after it.
+        if *off < dp.deref_inner().size {
+            let mut start = *off / BSIZE as u32 * BSIZE as u32;
+            while start < *off {
+                de.read_record(dp, start)?;
+                start += de.reclen as u32;
+            }
+            *off = start;
+        }
+        while *off < dp.deref_inner().size {
+            de.read_record(dp, *off)?;
+            if de.inum != 0 && !f(de.inum, de.get_name())? {
+                break;
+            }
+            *off += de.reclen as u32;
+        }
+        Ok(())
+    }
+
+    fn read(
+        &self,
+        ip: &mut InodeGuard<'_>,
+        mut off: u32,
+        n: u32,
+        f: &mut dyn FnMut(&[u8]) -> Result<(), ()>,
+    ) -> Result<usize, ()> {
+        let size = ip.deref_inner().size;
+        if off > size {
+            return Ok(0);
+        }
+        let n = cmp::min(n, size - off);
         let mut tot: u32 = 0;
         while tot < n {
-            let mut bp = kernel()
-                .file_system
-                .disk
-                .read(self.dev, self.bmap((off as usize).wrapping_div(BSIZE)));
+            let addr = ip.bmap((off as usize).wrapping_div(BSIZE));
             let m = core::cmp::min(
                 n.wrapping_sub(tot),
                 (BSIZE as u32).wrapping_sub(off.wrapping_rem(BSIZE as u32)),
             );
             let begin = off.wrapping_rem(BSIZE as u32) as usize;
             let end = begin + m as usize;
-            unsafe {
-                VAddr::copyout(dst, &bp.deref_mut_inner().data[begin..end])?;
+            if addr == 0 {
+                f(&ZEROES[begin..end])?;
+            } else {
+                let mut bp = kernel().file_system.disk.read(ip.dev, addr);
+                f(&bp.deref_mut_inner().data[begin..end])?;
             }
             tot = tot.wrapping_add(m);
             off = off.wrapping_add(m);
-            dst = dst + (m as usize);
         }
         Ok(tot as usize)
     }
 
-    /// Write data to inode.
-    /// Returns the number of bytes successfully written.
-    /// If the return value is less than the requested n,
-    /// there was an error of some kind.
-    pub fn write<A: VAddr>(
-        &mut self,
-        mut src: A,
+    fn write(
+        &self,
+        ip: &mut InodeGuard<'_>,
         mut off: u32,
         n: u32,
         tx: &FsTransaction<'_>,
+        f: &mut dyn FnMut(&mut [u8]) -> Result<(), ()>,
     ) -> Result<usize, ()> {
-        if off > self.deref_inner().size || off.wrapping_add(n) < off {
-            return Err(());
-        }
+        // Writing past the end of the file leaves a hole, which has no blocks.
         if off.wrapping_add(n) as usize > MAXFILE.wrapping_mul(BSIZE) {
             return Err(());
         }
+        let file = ip.deref_inner().typ == InodeType::File;
         let mut tot: u32 = 0;
         while tot < n {
-            let mut bp = kernel().file_system.disk.read(
-                self.dev,
-                self.bmap_or_alloc((off as usize).wrapping_div(BSIZE), tx),
-            );
+            let bn = (off as usize).wrapping_div(BSIZE);
+            let new = ip.bmap(bn) == 0;
+            let addr = ok_or!(ip.bmap_or_alloc(bn, tx), break);
+            // A new block may not have been zeroed on disk; see balloc_data().
+            let mut bp = if new {
+                unsafe { tx.zero_buf(ip.dev, addr) }
+            } else {
+                kernel().file_system.disk.read(ip.dev, addr)
+            };
             let m = core::cmp::min(
                 n.wrapping_sub(tot),
                 (BSIZE as u32).wrapping_sub(off.wrapping_rem(BSIZE as u32)),
             );
             let begin = off.wrapping_rem(BSIZE as u32) as usize;
             let end = begin + m as usize;
+            let copied = f(&mut bp.deref_mut_inner().data[begin..end]);
+            // Write the block even if f failed, since it may have partly modified the cached
+            // copy, and a new block must be written in full.
             unsafe {
-                if VAddr::copyin(&mut bp.deref_mut_inner().data[begin..end], src).is_err() {
-                    break;
+                if file {
+                    tx.write_data(bp);
+                } else {
+                    tx.write(bp);
                 }
             }
-            unsafe {
-                tx.write(bp);
+            if copied.is_err() {
+                break;
             }
             tot = tot.wrapping_add(m);
             off = off.wrapping_add(m);
-            src = src.add(m as usize);
         }
+        Ok(tot as usize)
+    }
 
-        // TODO(@kimjungwow) : To pass copyin() usertest, I reflect the commit on Nov 5, 2020 (below link).
-        // https://github.com/mit-pdos/xv6-riscv/commit/5e392531c07966fd8a6bee50e3e357c553fb2a2f
-        // This comment will be removed as we fetch upstream(mit-pdos)
-        if off > self.deref_inner().size {
-            self.deref_inner_mut().size = off;
+    /// Frees the blocks past len and zeroes the rest of the new last block.
+    fn truncate(
+        &self,
+        ip: &mut InodeGuard<'_>,
+        len: u32,
+        tx: &FsTransaction<'_>,
+    ) -> Result<(), ()> {
+        if len as usize > MAXFILE * BSIZE {
+            return Err(());
+        }
+        if len < ip.deref_inner().size {
+            unsafe { ip.free_from((len as usize + BSIZE - 1) / BSIZE, tx) };
+            let tail = len as usize % BSIZE;
+            if tail != 0 {
+                let addr = ip.bmap(len as usize / BSIZE);
+                if addr != 0 {
+                    let mut bp = kernel().file_system.disk.read(ip.dev, addr);
+                    for b in &mut bp.deref_mut_inner().data[tail..] {
+                        *b = 0;
+                    }
+                    unsafe { tx.write(bp) };
+                }
+            }
         }
+        Ok(())
+    }
+}
 
-        // Write the i-node back to disk even if the size didn't change
-        // because the loop above might have called bmap() and added a new
-        // block to self->addrs[].
-        unsafe {
-            self.update(tx);
+impl InodeGuard<'_> {
+    /// Free the data blocks of the file from the `first`th on, and the indirect or extent
+    /// blocks no longer needed. The caller must write the inode to disk with update().
+    unsafe fn free_from(&mut self, first: usize, tx: &FsTransaction<'_>) {
+        let dev = self.dev;
+        if kernel().file_system.superblock(self.dev).has_extents() {
+            let inner = self.deref_inner_mut();
+            let mut bp = if inner.addr_extent != 0 {
+                Some(kernel().file_system.disk.read(dev, inner.addr_extent))
+            } else {
+                None
+            };
+            let mut freed = 0;
+            let mut block_changed = false;
+            // The extents past first are at the end of the list, so freeing them leaves a list.
+            for n in 0..NEXTENT + NEXTENT_BLOCK {
+                let extent = some_or!(extent_mut(inner, &mut bp, n), break);
+                if extent.len == 0 {
+                    break;
+                }
+                if (extent.lbn + extent.len) as usize > first {
+                    let keep = first.saturating_sub(extent.lbn as usize) as u32;
+                    for b in extent.start + keep..extent.start + extent.len {
+                        tx.bfree(dev, b);
+                    }
+                    freed += extent.len - keep;
+                    if keep == 0 {
+                        *extent = Default::default();
+                    } else {
+                        extent.len = keep;
+                    }
+                    block_changed |= n >= NEXTENT;
+                }
+            }
+            inner.blocks -= freed;
+
+            // Free the extent block once none of its extents is in use.
+            if let Some(mut bp) = bp {
+                let (prefix, extents, _) = bp.deref_mut_inner().data.align_to_mut::<Extent>();
+                debug_assert_eq!(prefix.len(), 0, "itrunc: Buf data unaligned");
+                if extents[0].len == 0 {
+                    drop(bp);
+                    tx.bfree(dev, inner.addr_extent);
+                    inner.addr_extent = 0;
+                    inner.blocks -= 1;
+                } else if block_changed {
+                    tx.write(bp);
+                }
+            }
+            return;
         }
-        Ok(tot as usize)
+
+        let inner = self.deref_inner_mut();
+        for addr in &mut inner.addr_direct[cmp::min(first, NDIRECT)..] {
+            if *addr != 0 {
+                tx.bfree(dev, *addr);
+                *addr = 0;
+                inner.blocks -= 1;
+            }
+        }
+
+        // Blocks start..start + nblocks are reached through the level-indirect root.
+        let mut start = NDIRECT;
+        let mut nblocks = NINDIRECT;
+        let mut freed = 0;
+        for level in 1..=3 {
+            let addr = self.indirect_root(level);
+            if *addr != 0
+                && first < start + nblocks
+                && free_indirect_from(
+                    dev,
+                    *addr,
+                    level,
+                    first.saturating_sub(start),
+                    &mut freed,
+                    tx,
+                )
+            {
+                *addr = 0;
+            }
+            start += nblocks;
+            nblocks *= NINDIRECT;
+        }
+        self.deref_inner_mut().blocks -= freed;
+    }
+
+    /// Set the given timestamps to the current time.
+    /// The caller must write the inode to disk with update().
+    pub fn touch(&mut self, times: InodeTimes) {
+        let now = time::now();
+        let inner = self.deref_inner_mut();
+        if times.contains(InodeTimes::ACCESS) {
+            inner.atime = now;
+        }
+        if times.contains(InodeTimes::MODIFY) {
+            inner.mtime = now;
+        }
+        if times.contains(InodeTimes::CHANGE) {
+            inner.ctime = now;
+        }
+    }
+
+    /// Returns true if reading the inode now should update its access time.
+    pub fn atime_stale(&self) -> bool {
+        let inner = self.deref_inner();
+        inner.atime <= inner.mtime
+            || inner.atime <= inner.ctime
+            || time::now().wrapping_sub(inner.atime) >= RELATIME_SECS
     }
 
     /// Inode content
     ///
     /// The content (data) associated with each inode is stored
     /// in blocks on the disk. The first NDIRECT block numbers
-    /// are listed in self->addrs[].  The next NINDIRECT blocks are
-    /// listed in block self->addr_indirect.
+    /// are listed in self->addr_direct[].  The next NINDIRECT blocks are
+    /// listed in block self->addr_indirect. The next NDINDIRECT blocks are
+    /// listed in the blocks listed in block self->addr_dindirect, and the
+    /// last NTINDIRECT blocks are reached from self->addr_tindirect through
+    /// three levels of indirect blocks.
+    ///
+    /// In a file system with extents, the blocks are instead listed by the
+    /// extents in self->extents[] and then in block self->addr_extent.
     /// Return the disk block address of the nth block in inode self.
     /// If there is no such block, bmap allocates one.
-    fn bmap_or_alloc(&mut self, bn: usize, tx: &FsTransaction<'_>) -> u32 {
+    pub(super) fn bmap_or_alloc(&mut self, bn: usize, tx: &FsTransaction<'_>) -> Result<u32, ()> {
         self.bmap_inner(bn, Some(tx))
     }
 
-    fn bmap(&mut self, bn: usize) -> u32 {
-        self.bmap_inner(bn, None)
+    /// Returns 0 if the nth block lies in a hole.
+    pub(super) fn bmap(&mut self, bn: usize) -> u32 {
+        self.bmap_inner(bn, None).expect("bmap: out of range")
     }
 
-    fn bmap_inner(&mut self, bn: usize, tx_opt: Option<&FsTransaction<'_>>) -> u32 {
+    fn bmap_inner(&mut self, bn: usize, tx_opt: Option<&FsTransaction<'_>>) -> Result<u32, ()> {
+        if kernel().file_system.superblock(self.dev).has_extents() {
+            return self.emap_inner(bn, tx_opt);
+        }
+
         let inner = self.deref_inner();
+        let file = inner.typ == InodeType::File;
 
         if bn < NDIRECT {
             let mut addr = inner.addr_direct[bn];
             if addr == 0 {
-                addr = unsafe { tx_opt.expect("bmap: out of range").balloc(self.dev) };
+                let tx = some_or!(tx_opt, return Ok(0));
+                addr = unsafe {
+                    if file {
+                        tx.balloc_data(self.dev, 0)
+                    } else {
+                        tx.balloc(self.dev)
+                    }
+                };
                 self.deref_inner_mut().addr_direct[bn] = addr;
+                self.deref_inner_mut().blocks += 1;
             }
-            addr
+            Ok(addr)
         } else {
-            let bn = bn - NDIRECT;
-            assert!(bn < NINDIRECT, "bmap: out of range");
-
-            let mut indirect = inner.addr_indirect;
-            if indirect == 0 {
-                indirect = unsafe { tx_opt.expect("bmap: out of range").balloc(self.dev) };
-                self.deref_inner_mut().addr_indirect = indirect;
+            // Find the level of indirection and the index of the block within it.
+            let mut bn = bn - NDIRECT;
+            let mut level = 1;
+            let mut nblocks = NINDIRECT;
+            while bn >= nblocks {
+                bn -= nblocks;
+                level += 1;
+                nblocks *= NINDIRECT;
+                assert!(level <= 3, "bmap: out of range");
             }
 
-            let mut bp = kernel().file_system.disk.read(self.dev, indirect);
-            let (prefix, data, _) = unsafe { bp.deref_mut_inner().data.align_to_mut::<u32>() };
-            debug_assert_eq!(prefix.len(), 0, "bmap: Buf data unaligned");
-            let mut addr = data[bn];
+            let dev = self.dev;
+            let root = self.indirect_root(level);
+            let mut addr = *root;
             if addr == 0 {
-                let tx = tx_opt.expect("bmap: out of range");
-                addr = unsafe { tx.balloc(self.dev) };
-                data[bn] = addr;
-                unsafe { tx.write(bp) };
+                let tx = some_or!(tx_opt, return Ok(0));
+                addr = unsafe { tx.balloc(dev) };
+                *root = addr;
+                self.deref_inner_mut().blocks += 1;
+            }
+
+            // Walk down the indirect blocks.
+            while nblocks > 1 {
+                nblocks /= NINDIRECT;
+                let index = bn / nblocks;
+                bn %= nblocks;
+
+                let mut bp = kernel().file_system.disk.read(dev, addr);
+                let (prefix, data, _) = unsafe { bp.deref_mut_inner().data.align_to_mut::<u32>() };
+                debug_assert_eq!(prefix.len(), 0, "bmap: Buf data unaligned");
+                addr = data[index];
+                if addr == 0 {
+                    let tx = some_or!(tx_opt, return Ok(0));
+                    addr = unsafe {
+                        if file && nblocks == 1 {
+                            tx.balloc_data(dev, 0)
+                        } else {
+                            tx.balloc(dev)
+                        }
+                    };
+                    data[index] = addr;
+                    unsafe { tx.write(bp) };
+                    self.deref_inner_mut().blocks += 1;
+                }
             }
-            addr
+            Ok(addr)
         }
     }
 
-    /// Is the directory dp empty except for "." and ".." ?
-    pub unsafe fn isdirempty(&mut self) -> bool {
-        let mut de: Dirent = Default::default();
-        for off in (2 * DIRENT_SIZE as u32..self.deref_inner().size).step_by(DIRENT_SIZE) {
-            let bytes_read = self.read(
-                KVAddr::new(&mut de as *mut Dirent as usize),
-                off as u32,
-                DIRENT_SIZE as u32,
-            );
-            assert_eq!(bytes_read, Ok(DIRENT_SIZE), "isdirempty: readi");
-            if de.inum != 0 {
-                return false;
+    /// bmap_inner() for a file system with extents. A new block extends the extent before it if
+    /// it directly follows that extent on disk, or the extent after it if it directly precedes
+    /// that one, and otherwise gets an extent of its own, which is inserted in order. Returns
+    /// `Err(())` if a new block needs a new extent but there is no room for one.
+    fn emap_inner(&mut self, bn: usize, tx_opt: Option<&FsTransaction<'_>>) -> Result<u32, ()> {
+        const MAX: usize = NEXTENT + NEXTENT_BLOCK;
+        let dev = self.dev;
+        let bn = bn as u32;
+        let inner = self.deref_inner_mut();
+        let mut bp = if inner.addr_extent != 0 {
+            Some(kernel().file_system.disk.read(dev, inner.addr_extent))
+        } else {
+            None
+        };
+
+        // Find the first extent that ends after block bn, which holds bn unless bn is in a hole.
+        let mut n = 0;
+        while n < MAX {
+            let extent = extent_at(inner, &mut bp, n);
+            if extent.len == 0 || bn < extent.lbn + extent.len {
+                break;
+            }
+            n += 1;
+        }
+        let next = extent_at(inner, &mut bp, n);
+        if next.len != 0 && next.lbn <= bn {
+            return Ok(next.start + (bn - next.lbn));
+        }
+        let tx = some_or!(tx_opt, return Ok(0));
+
+        // Allocate the block where it would continue the extent before it, or else lead into
+        // the extent after it.
+        let prev = n.checked_sub(1).map(|p| extent_at(inner, &mut bp, p));
+        let goal = match prev {
+            Some(prev) => prev.start + (bn - prev.lbn),
+            None if next.len != 0 => next.start.saturating_sub(next.lbn - bn),
+            None => 0,
+        };
+        let addr = unsafe {
+            if inner.typ == InodeType::File {
+                tx.balloc_data(dev, goal)
+            } else {
+                tx.balloc_near(dev, goal)
+            }
+        };
+        inner.blocks += 1;
+        let mut count = n;
+        while count < MAX && extent_at(inner, &mut bp, count).len != 0 {
+            count += 1;
+        }
+
+        let joins_prev = prev.map_or(false, |p| p.lbn + p.len == bn && p.start + p.len == addr);
+        let joins_next = next.len != 0 && next.lbn == bn + 1 && next.start == addr + 1;
+        // The last extent that changes.
+        let last = match (joins_prev, joins_next) {
+            (true, true) => {
+                // The block fills the gap between two extents, which become one.
+                extent_mut(inner, &mut bp, n - 1).unwrap().len += 1 + next.len;
+                for i in n..count - 1 {
+                    let extent = extent_at(inner, &mut bp, i + 1);
+                    *extent_mut(inner, &mut bp, i).unwrap() = extent;
+                }
+                *extent_mut(inner, &mut bp, count - 1).unwrap() = Default::default();
+                count - 1
+            }
+            (true, false) => {
+                extent_mut(inner, &mut bp, n - 1).unwrap().len += 1;
+                n - 1
+            }
+            (false, true) => {
+                let extent = extent_mut(inner, &mut bp, n).unwrap();
+                extent.lbn -= 1;
+                extent.start -= 1;
+                extent.len += 1;
+                n
+            }
+            (false, false) => {
+                if count == MAX {
+                    unsafe { tx.bfree(dev, addr) };
+                    inner.blocks -= 1;
+                    return Err(());
+                }
+                if count >= NEXTENT && bp.is_none() {
+                    inner.addr_extent = unsafe { tx.balloc(dev) };
+                    inner.blocks += 1;
+                    bp = Some(kernel().file_system.disk.read(dev, inner.addr_extent));
+                }
+                for i in (n..count).rev() {
+                    let extent = extent_at(inner, &mut bp, i);
+                    *extent_mut(inner, &mut bp, i + 1).unwrap() = extent;
+                }
+                *extent_mut(inner, &mut bp, n).unwrap() = Extent {
+                    lbn: bn,
+                    start: addr,
+                    len: 1,
+                };
+                count
+            }
+        };
+
+        // Extents in the inode are written by update().
+        if last >= NEXTENT {
+            unsafe { tx.write(bp.unwrap()) };
+        }
+        Ok(addr)
+    }
+
+    /// Returns the address of the root block of `level`-indirect blocks.
+    fn indirect_root(&mut self, level: usize) -> &mut u32 {
+        let inner = self.deref_inner_mut();
+        match level {
+            1 => &mut inner.addr_indirect,
+            2 => &mut inner.addr_dindirect,
+            3 => &mut inner.addr_tindirect,
+            _ => panic!("indirect_root"),
+        }
+    }
+}
+
+/// Returns the `n`th extent of an inode, or `None` if it is in the extent block and `bp`, the
+/// inode's extent block, is `None`.
+fn extent_mut<'a>(
+    inner: &'a mut InodeInner,
+    bp: &'a mut Option<Buf<'static>>,
+    n: usize,
+) -> Option<&'a mut Extent> {
+    if n < NEXTENT {
+        return Some(&mut inner.extents[n]);
+    }
+    let bp = bp.as_mut()?;
+    let (prefix, extents, _) = unsafe { bp.deref_mut_inner().data.align_to_mut::<Extent>() };
+    debug_assert_eq!(prefix.len(), 0, "bmap: Buf data unaligned");
+    Some(&mut extents[n - NEXTENT])
+}
+
+/// Returns a copy of the `n`th extent of an inode, which has length 0 if there is no such extent.
+fn extent_at(inner: &mut InodeInner, bp: &mut Option<Buf<'static>>, n: usize) -> Extent {
+    extent_mut(inner, bp, n).map_or_else(Default::default, |extent| *extent)
+}
+
+/// Free the `level`-indirect block at `addr` and all blocks reachable from it, adding their
+/// number to `freed`.
+unsafe fn free_indirect(
+    dev: u32,
+    addr: u32,
+    level: usize,
+    freed: &mut u32,
+    tx: &FsTransaction<'_>,
+) {
+    let mut bp = kernel().file_system.disk.read(dev, addr);
+    let (prefix, data, _) = bp.deref_mut_inner().data.align_to_mut::<u32>();
+    debug_assert_eq!(prefix.len(), 0, "itrunc: Buf data unaligned");
+    for &a in data.iter() {
+        if a != 0 {
+            if level > 1 {
+                free_indirect(dev, a, level - 1, freed, tx);
+            } else {
+                tx.bfree(dev, a);
+                *freed += 1;
             }
         }
-        true
     }
+    drop(bp);
+    tx.bfree(dev, addr);
+    *freed += 1;
+}
+
+/// Free the data blocks from the `first`th on that are reachable from the `level`-indirect
+/// block at `addr`, and the indirect blocks left unneeded, adding their number to `freed`.
+/// Returns true if the block at `addr` itself was freed.
+unsafe fn free_indirect_from(
+    dev: u32,
+    addr: u32,
+    level: usize,
+    first: usize,
+    freed: &mut u32,
+    tx: &FsTransaction<'_>,
+) -> bool {
+    if first == 0 {
+        free_indirect(dev, addr, level, freed, tx);
+        return true;
+    }
+
+    // Number of data blocks reached through each entry.
+    let span = NINDIRECT.pow(level as u32 - 1);
+    let mut bp = kernel().file_system.disk.read(dev, addr);
+    let (prefix, data, _) = bp.deref_mut_inner().data.align_to_mut::<u32>();
+    debug_assert_eq!(prefix.len(), 0, "itrunc: Buf data unaligned");
+    let mut changed = false;
+    for (i, a) in data.iter_mut().enumerate() {
+        if *a == 0 || (i + 1) * span <= first {
+            continue;
+        }
+        let entry_freed = if level > 1 {
+            free_indirect_from(
+                dev,
+                *a,
+                level - 1,
+                first.saturating_sub(i * span),
+                freed,
+                tx,
+            )
+        } else {
+            tx.bfree(dev, *a);
+            *freed += 1;
+            true
+        };
+        if entry_freed {
+            *a = 0;
+            changed = true;
+        }
+    }
+    if changed {
+        tx.write(bp);
+    }
+    false
 }
 
 impl ArenaObject for Inode {
@@ -555,18 +1384,21 @@ impl ArenaObject for Inode {
             // can be found in finalize in file.rs, sys_chdir in sysfile.rs,
             // close_files in proc.rs, and exec in exec.rs.
             // https://github.com/kaist-cp/rv6/issues/290
+            // Freeing the inode writes only the inode and bitmap blocks, which fit in the
+            // MAXOPBLOCKS that such transactions reserve at least.
             let tx = mem::ManuallyDrop::new(FsTransaction {
                 fs: &kernel().file_system,
+                reserved: MAXOPBLOCKS,
+                devs: 1 << self.dev,
+                logged: Default::default(),
             });
 
             // self->ref == 1 means no other process can have self locked,
             // so this acquiresleep() won't block (or deadlock).
             let mut ip = self.lock();
 
-            A::reacquire_after(guard, move || unsafe {
-                ip.itrunc(&tx);
-                ip.deref_inner_mut().typ = InodeType::None;
-                ip.update(&tx);
+            A::reacquire_after(guard, move || {
+                ip.fs().free_inode(&mut ip, &tx);
                 ip.deref_inner_mut().valid = false;
                 drop(ip);
             });
@@ -576,34 +1408,11 @@ impl ArenaObject for Inode {
 
 impl Inode {
     /// Lock the given inode.
-    /// Reads the inode from disk if necessary.
+    /// Reads the inode from its file system if necessary.
     pub fn lock(&self) -> InodeGuard<'_> {
         let mut guard = self.inner.lock();
         if !guard.valid {
-            let mut bp = kernel().file_system.disk.read(
-                self.dev,
-                kernel().file_system.superblock().iblock(self.inum),
-            );
-            let dip: &mut Dinode = unsafe {
-                &mut *((bp.deref_mut_inner().data.as_mut_ptr() as *mut Dinode)
-                    .add((self.inum as usize).wrapping_rem(IPB)))
-            };
-            match (*dip).typ {
-                DInodeType::None => guard.typ = InodeType::None,
-                DInodeType::Dir => guard.typ = InodeType::Dir,
-                DInodeType::File => guard.typ = InodeType::File,
-                DInodeType::Device => {
-                    guard.typ = InodeType::Device {
-                        major: (*dip).major,
-                        minor: (*dip).minor,
-                    }
-                }
-            }
-            guard.nlink = (*dip).nlink;
-            guard.size = (*dip).size;
-            guard.addr_direct.copy_from_slice(&(*dip).addr_direct);
-            guard.addr_indirect = (*dip).addr_indirect;
-            drop(bp);
+            self.fs().read_inode(self, &mut guard);
             guard.valid = true;
             assert_ne!(guard.typ, InodeType::None, "Inode::lock: no type");
         };
@@ -622,8 +1431,26 @@ impl Inode {
                     typ: InodeType::None,
                     nlink: 0,
                     size: 0,
+                    mode: 0,
+                    uid: 0,
+                    gid: 0,
+                    flags: InodeFlags::empty(),
+                    atime: 0,
+                    mtime: 0,
+                    ctime: 0,
+                    crtime: 0,
+                    blocks: 0,
+                    seq: 0,
                     addr_direct: [0; NDIRECT],
                     addr_indirect: 0,
+                    addr_dindirect: 0,
+                    addr_tindirect: 0,
+                    extents: [Extent {
+                        lbn: 0,
+                        start: 0,
+                        len: 0,
+                    }; NEXTENT],
+                    addr_extent: 0,
                 },
             ),
         }
@@ -638,8 +1465,68 @@ impl Inode {
             typ: inner.typ,
             nlink: inner.nlink,
             size: inner.size as usize,
+            mode: inner.mode,
+            uid: inner.uid,
+            gid: inner.gid,
+            atime: inner.atime,
+            mtime: inner.mtime,
+            ctime: inner.ctime,
+            crtime: inner.crtime,
+            blocks: inner.blocks,
         }
     }
+
+    /// Set the size of the regular file self to len, as InodeGuard::truncate() does. Shrinking
+    /// a large file frees its blocks from the end in several transactions, each of which frees
+    /// at most as many data blocks as truncate_step() allows. Must not be called inside a
+    /// transaction.
+    pub unsafe fn truncate(&self, len: u32) -> Result<(), ()> {
+        let (step, reserve) = truncate_step(self.dev);
+        loop {
+            let tx = kernel().file_system.begin_transaction_reserving(reserve);
+            let mut ip = self.lock();
+            if ip.deref_inner().typ != InodeType::File {
+                return Err(());
+            }
+            let nblocks = (ip.deref_inner().size as usize + BSIZE - 1) / BSIZE;
+            let target = if nblocks > step && (nblocks - step) * BSIZE > len as usize {
+                (nblocks - step) * BSIZE
+            } else {
+                len as usize
+            };
+            ip.truncate(target as u32, &tx)?;
+            if target == len as usize {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Returns how many data blocks a transaction of Inode::truncate() on device dev may free, and
+/// how many blocks it must reserve. Every block freed clears a bit in one of the disk's bitmap
+/// blocks, so freeing `step` data blocks writes at most TRUNCATE_FIXED_BLOCKS + min(nbitmap,
+/// step + TRUNCATE_FREED_INDIRECT) blocks. Large disks have many bitmap blocks, so the step
+/// shrinks until that fits in the log.
+fn truncate_step(dev: u32) -> (usize, usize) {
+    if dev == TMPFSDEV || dev == PROCFSDEV {
+        return (TRUNCATE_STEP, MAXOPBLOCKS);
+    }
+    let fs = &kernel().file_system;
+    let nbitmap = (fs.superblock(dev).size / BPB + 1) as usize;
+    let max = fs.max_transaction_blocks();
+    let step = if TRUNCATE_FIXED_BLOCKS + nbitmap <= max {
+        TRUNCATE_STEP
+    } else {
+        cmp::min(
+            TRUNCATE_STEP,
+            cmp::max(
+                1,
+                max.saturating_sub(TRUNCATE_FIXED_BLOCKS + TRUNCATE_FREED_INDIRECT),
+            ),
+        )
+    };
+    let nwrites = TRUNCATE_FIXED_BLOCKS + cmp::min(nbitmap, step + TRUNCATE_FREED_INDIRECT);
+    (step, cmp::max(MAXOPBLOCKS, nwrites))
 }
 
 impl Itable {
@@ -667,42 +1554,28 @@ impl Itable {
         unsafe { Rc::from_unchecked(self, inner) }
     }
 
+    /// Is any inode on device dev referenced?
+    pub fn in_use(&self, dev: u32) -> bool {
+        // The handle unforget() returns holds no reference, so it must not be dropped.
+        self.unforget(|inode| inode.dev == dev)
+            .map(mem::forget)
+            .is_some()
+    }
+
     /// Allocate an inode on device dev.
     /// Mark it as allocated by giving it type.
-    /// Returns an unlocked but allocated and referenced inode.
-    pub unsafe fn alloc_inode(
+    /// Returns an unlocked but allocated and referenced inode,
+    /// or fails if the file system has no free inode.
+    pub fn alloc_inode(
         &self,
         dev: u32,
         typ: InodeType,
         tx: &FsTransaction<'_>,
-    ) -> RcInode<'_> {
-        for inum in 1..kernel().file_system.superblock().ninodes {
-            let mut bp = kernel()
-                .file_system
-                .disk
-                .read(dev, kernel().file_system.superblock().iblock(inum));
-            let dip = (bp.deref_mut_inner().data.as_mut_ptr() as *mut Dinode)
-                .add((inum as usize).wrapping_rem(IPB));
-
-            // a free inode
-            if (*dip).typ == DInodeType::None {
-                ptr::write_bytes(dip, 0, 1);
-                match typ {
-                    InodeType::None => (*dip).typ = DInodeType::None,
-                    InodeType::Dir => (*dip).typ = DInodeType::Dir,
-                    InodeType::File => (*dip).typ = DInodeType::File,
-                    InodeType::Device { major, minor } => {
-                        (*dip).typ = DInodeType::Device;
-                        (*dip).major = major;
-                        (*dip).minor = minor
-                    }
-                }
-
-                // mark it allocated on the disk
-                tx.write(bp);
-                return self.get_inode(dev, inum);
-            }
-        }
-        panic!("[Itable::alloc_inode] no inodes");
+    ) -> Result<RcInode<'_>, ()> {
+        let inum = kernel()
+            .file_system
+            .fs_type(dev)
+            .alloc_inode(dev, typ, tx)?;
+        Ok(self.get_inode(dev, inum))
     }
 }
@@ -1,16 +1,27 @@
 //! Simple logging that allows concurrent FS system calls.
 //!
-//! A LOG transaction contains the updates of multiple FS system
-//! calls. The logging system only commits when there are
+//! A LOG transaction group contains the updates of multiple FS
+//! system calls. The logging system only commits when there are
 //! no FS system calls active. Thus there is never
 //! any reasoning required about whether a commit might
 //! write an uncommitted system call's updates to disk.
 //!
 //! A system call should call begin_op()/end_op() to mark
-//! its start and end. Usually begin_op() just increments
-//! the count of in-progress FS system calls and returns.
-//! But if it thinks the LOG is close to running out, it
-//! sleeps until the last outstanding end_op() commits.
+//! its start and end, and reserves in begin_op() the number
+//! of blocks it may write. Usually begin_op() just adds the
+//! reservation to those of the in-progress FS system calls
+//! and returns. But if the reservations would exceed the
+//! LOG's capacity, it asks for a commit and sleeps until the
+//! commit is done. An operation that writes more blocks
+//! than the LOG holds must be split into several transactions.
+//!
+//! Commits are done by a kernel thread running commit_thread(),
+//! not by end_op(), so system calls return without waiting for
+//! the disk. A commit is asked for when the LOG runs out of
+//! space, when sync() waits for a transaction group to reach the
+//! disk, and periodically by the timer. Once a commit is asked
+//! for, no new system calls join the group, and the thread
+//! commits it as soon as the outstanding ones have ended.
 //!
 //! The LOG is a physical re-do LOG containing disk blocks.
 //! The on-disk LOG format:
@@ -21,7 +32,7 @@
 //!   ...
 //! Log appends are synchronous.
 use arrayvec::ArrayVec;
-use core::{mem, ptr};
+use core::{cmp, mem, ptr};
 
 use crate::{
     bio::{Buf, BufUnlocked},
@@ -30,19 +41,43 @@ use crate::{
     sleepablelock::Sleepablelock,
 };
 
+/// Most blocks freed in a transaction group that the log keeps track of.
+const MAXFREED: usize = 1024;
+
 pub struct Log {
     dev: u32,
     start: i32,
     size: i32,
 
+    /// Most blocks a transaction may log. Limited by the on-disk log, the header block, and the
+    /// buffer cache, which must keep every logged block until the commit.
+    capacity: usize,
+
     /// How many FS sys calls are executing?
     outstanding: i32,
 
+    /// Blocks reserved by the executing FS sys calls.
+    reserved: usize,
+
     /// In commit(), please wait.
     committing: bool,
 
+    /// Has a commit been asked for? No FS sys calls may begin until it is done.
+    commit_wanted: bool,
+
+    /// Sequence number of the open transaction group. Groups commit in order, so all groups
+    /// with smaller numbers are on disk.
+    seq: u64,
+
     /// Contents of the header block, used to keep track in memory of logged block# before commit.
     lh: ArrayVec<[BufUnlocked<'static>; LOGSIZE]>,
+
+    /// Blocks freed in the open transaction group of an ordered file system. Until the group
+    /// commits, they still belong to their old files on disk and must not be written in place.
+    freed: ArrayVec<[u32; MAXFREED]>,
+
+    /// Were more blocks freed in the open transaction group than `freed` holds?
+    freed_overflow: bool,
 }
 
 /// Contents of the header block, used for the on-disk header block.
@@ -55,14 +90,26 @@ struct LogHeader {
 const_assert!(mem::size_of::<LogHeader>() < BSIZE);
 
 impl Log {
-    pub fn new(dev: u32, start: i32, size: i32) -> Self {
+    /// Create the log of `size` blocks at `start`, for a buffer cache of `nbuf` buffers.
+    pub fn new(dev: u32, start: i32, size: i32, nbuf: usize) -> Self {
+        let capacity = cmp::min(
+            cmp::min(size as usize, LOGSIZE) - 1,
+            nbuf.saturating_sub(MAXOPBLOCKS),
+        );
+        assert!(capacity >= MAXOPBLOCKS, "Log::new: log too small");
         let mut log = Self {
             dev,
             start,
             size,
+            capacity,
             outstanding: 0,
+            reserved: 0,
             committing: false,
+            commit_wanted: false,
+            seq: 1,
             lh: ArrayVec::new(),
+            freed: ArrayVec::new(),
+            freed_overflow: false,
         };
         unsafe {
             log.recover_from_log();
@@ -72,7 +119,7 @@ impl Log {
     }
 
     /// Copy committed blocks from log to their home location.
-    unsafe fn install_trans(&mut self, recovering: bool) {
+    unsafe fn install_trans(&mut self) {
         for (tail, dbuf) in self.lh.drain(..).enumerate() {
             // Read log block.
             let lbuf = kernel()
@@ -90,12 +137,10 @@ impl Log {
                 BSIZE,
             );
 
+            dbuf.deref_mut_inner().valid = true;
+
             // Write dst to disk.
             kernel().file_system.disk.write(&mut dbuf);
-
-            if recovering {
-                mem::forget(dbuf);
-            }
         }
     }
 
@@ -107,12 +152,9 @@ impl Log {
             .read(self.dev as u32, self.start as u32);
         let lh = buf.deref_mut_inner().data.as_mut_ptr() as *mut LogHeader;
         for b in &(*lh).block[0..(*lh).n as usize] {
-            self.lh.push(
-                kernel()
-                    .bcache
-                    .buf_unforget(self.dev as u32, *b as u32)
-                    .unwrap(),
-            );
+            // The blocks are not cached at boot, so hold new buffers until installed.
+            self.lh
+                .push(kernel().bcache.get_buf(self.dev as u32, *b as u32));
         }
     }
 
@@ -136,55 +178,96 @@ impl Log {
         self.read_head();
 
         // If committed, copy from log to disk.
-        self.install_trans(true);
+        self.install_trans();
 
         // Clear the log.
         self.write_head();
     }
 
-    /// Called at the start of each FS system call.
-    pub unsafe fn begin_op(this: &Sleepablelock<Self>) {
+    /// Most blocks a single FS system call may reserve.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Sequence number of the open transaction group.
+    pub fn seq(&self) -> u64 {
+        self.seq
+    }
+
+    /// Called at the start of each FS system call, which may write at most `nblocks` blocks.
+    pub unsafe fn begin_op(this: &Sleepablelock<Self>, nblocks: usize) {
         let mut guard = this.lock();
+        assert!(nblocks <= guard.capacity, "begin_op: reservation too big");
         loop {
-            if guard.committing ||
-            // This op might exhaust log space; wait for commit.
-            guard.lh.len() as i32 + (guard.outstanding + 1) * MAXOPBLOCKS as i32 > LOGSIZE as i32
-            {
+            if guard.committing || guard.commit_wanted {
+                guard.sleep();
+            } else if guard.lh.len() + guard.reserved + nblocks > guard.capacity {
+                // This op might exhaust log space; wait for commit.
+                guard.commit_wanted = true;
+                guard.wakeup();
                 guard.sleep();
             } else {
                 guard.outstanding += 1;
+                guard.reserved += nblocks;
                 break;
             }
         }
     }
 
-    /// Called at the end of each FS system call.
-    /// Commits if this was the last outstanding operation.
-    pub unsafe fn end_op(this: &Sleepablelock<Self>) {
+    /// Called at the end of each FS system call, with the number of blocks it reserved.
+    pub unsafe fn end_op(this: &Sleepablelock<Self>, nblocks: usize) {
         let mut guard = this.lock();
         guard.outstanding -= 1;
+        guard.reserved -= nblocks;
         assert!(!guard.committing, "guard.committing");
 
-        let do_commit = if guard.outstanding == 0 {
-            guard.committing = true;
-            true
-        } else {
-            // begin_op() may be waiting for LOG space,
-            // and decrementing log.outstanding has decreased
-            // the amount of reserved space.
+        // begin_op() may be waiting for LOG space, and decrementing
+        // log.reserved has decreased the amount of reserved space.
+        // If this was the last outstanding operation, the commit
+        // thread may be waiting to commit.
+        guard.wakeup();
+    }
+
+    /// Ask for the open transaction group to be committed, if it has logged any blocks.
+    pub fn request_commit(this: &Sleepablelock<Self>) {
+        let mut guard = this.lock();
+        if !guard.lh.is_empty() {
+            guard.commit_wanted = true;
             guard.wakeup();
-            false
-        };
-        drop(guard);
+        }
+    }
+
+    /// Wait until transaction group `seq` and all before it are on disk.
+    pub fn sync(this: &Sleepablelock<Self>, seq: u64) {
+        let mut guard = this.lock();
+        // Later groups contain no updates made before this call.
+        let seq = cmp::min(seq, guard.seq);
+        while guard.seq == seq && (guard.committing || !guard.lh.is_empty()) {
+            guard.commit_wanted = true;
+            guard.wakeup();
+            guard.sleep();
+        }
+    }
+
+    /// Body of the kernel thread that commits transaction groups. Never returns.
+    pub unsafe fn commit_thread(this: &Sleepablelock<Self>) -> ! {
+        loop {
+            let mut guard = this.lock();
+            while !guard.commit_wanted || guard.outstanding > 0 {
+                guard.sleep();
+            }
+            guard.committing = true;
+            drop(guard);
 
-        if do_commit {
             // Call commit w/o holding locks, since not allowed
             // to sleep with locks.
             this.get_mut_unchecked().commit();
             let mut guard = this.lock();
             guard.committing = false;
+            guard.commit_wanted = false;
+            guard.seq += 1;
             guard.wakeup();
-        };
+        }
     }
 
     /// Copy modified blocks from cache to self.
@@ -222,11 +305,29 @@ impl Log {
             self.write_head();
 
             // Now install writes to home locations.
-            self.install_trans(false);
+            self.install_trans();
 
             // Erase the transaction from the self.
             self.write_head();
         };
+        self.freed.clear();
+        self.freed_overflow = false;
+    }
+
+    /// Record that block b was freed in the open transaction group.
+    pub fn note_freed(&mut self, b: u32) {
+        if self.freed.try_push(b).is_err() {
+            self.freed_overflow = true;
+        }
+    }
+
+    /// May block b be written in place, bypassing the log? Not if it is logged in the open
+    /// transaction group, as installing the logged copy would overwrite it, nor if it may have
+    /// been freed in the group, as a crash would leave it in its old file.
+    pub fn may_write_in_place(&self, b: u32) -> bool {
+        !self.freed_overflow
+            && !self.freed.contains(&b)
+            && self.lh.iter().all(|buf| buf.blockno != b)
     }
 
     /// Caller has modified b->data and is done with the buffer.
@@ -237,21 +338,21 @@ impl Log {
     ///   bp = Disk::read(...)
     ///   modify bp->data[]
     ///   write(bp)
-    pub unsafe fn write(&mut self, b: Buf<'static>) {
-        assert!(
-            !(self.lh.len() >= LOGSIZE || self.lh.len() as i32 >= self.size - 1),
-            "too big a transaction"
-        );
+    ///
+    /// Returns false if the block was in the log already.
+    pub unsafe fn write(&mut self, b: Buf<'static>) -> bool {
+        assert!(self.lh.len() < self.capacity, "too big a transaction");
         assert!(self.outstanding >= 1, "write outside of trans");
 
         for buf in &self.lh {
             // Log absorbtion.
             if buf.blockno == (*b).blockno {
-                return;
+                return false;
             }
         }
 
         // Add new block to log?
         self.lh.push(b.unlock());
+        true
     }
 }
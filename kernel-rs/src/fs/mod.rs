@@ -11,114 +11,261 @@
 //!
 //! On-disk file system format used for both kernel and user programs are also included here.
 
-use core::{cmp, mem, ptr};
+use core::{cell::Cell, cmp, mem, ptr};
 use spin::Once;
 
 use crate::{
-    bio::Buf, kernel::kernel, param::BSIZE, sleepablelock::Sleepablelock, virtio_disk::Disk,
+    bio::Buf,
+    kernel::kernel,
+    param::{BSIZE, MAXOPBLOCKS, NDISK, PROCFSDEV, ROOTDEV, TMPFSDEV},
+    sleepablelock::Sleepablelock,
+    sleeplock::Sleeplock,
+    virtio_disk::Disks,
 };
 
+mod dirindex;
+mod fsck;
 mod inode;
 mod log;
+mod mount;
 mod path;
+mod procfs;
 mod superblock;
+mod tmpfs;
+mod vfs;
 
+pub use dirindex::DIRLINK_BLOCKS;
 pub use inode::{
-    Dinode, Dirent, Inode, InodeGuard, InodeInner, InodeType, Itable, RcInode, DIRENT_SIZE, DIRSIZ,
+    Access, Dinode, Dirent, Extent, Inode, InodeFlags, InodeGuard, InodeInner, InodeTimes,
+    InodeType, Itable, RcInode, ALLOC_BLOCKS, DIRENT_HDRSIZE, DIRSIZ,
 };
 pub use log::Log;
+use mount::MountTable;
 pub use path::{FileName, Path};
+use procfs::Procfs;
 pub use superblock::{Superblock, BPB, IPB};
+use tmpfs::Tmpfs;
+pub use vfs::{FsType, InodeOps, SuperOps};
 
 /// root i-number
 const ROOTINO: u32 = 1;
 
 const NDIRECT: usize = 12;
 const NINDIRECT: usize = BSIZE.wrapping_div(mem::size_of::<u32>());
-const MAXFILE: usize = NDIRECT.wrapping_add(NINDIRECT);
+const NDINDIRECT: usize = NINDIRECT * NINDIRECT;
+const NTINDIRECT: usize = NDINDIRECT * NINDIRECT;
+const MAXFILE: usize = NDIRECT + NINDIRECT + NDINDIRECT + NTINDIRECT;
+
+/// Number of extents stored in an inode of a file system with extents.
+const NEXTENT: usize = 4;
+/// Number of extents in an inode's extent block.
+const NEXTENT_BLOCK: usize = BSIZE / mem::size_of::<Extent>();
 
 pub struct FileSystem {
-    /// TODO(rv6): initializing superblock should be run only once
-    /// because forkret() calls fsinit()
-    /// There should be one superblock per disk device, but we run with
-    /// only one device.
-    superblock: Once<Superblock>,
+    /// The superblock of each disk, read when its file system is first mounted. A disk does not
+    /// change while the machine runs, so it is kept after the file system is unmounted.
+    superblocks: [Once<Superblock>; NDISK],
 
-    /// TODO(rv6): document it / initializing log should be run
-    /// only once because forkret() calls fsinit()
-    log: Once<Sleepablelock<Log>>,
+    /// The log of each disk, recovered when its superblock is read. Its commit thread runs from
+    /// then on.
+    logs: [Once<Sleepablelock<Log>>; NDISK],
 
     /// It may sleep until some Descriptors are freed.
-    pub disk: Sleepablelock<Disk>,
+    pub disk: Disks,
+
+    /// The in-memory file system on device TMPFSDEV.
+    pub tmpfs: Tmpfs,
+
+    /// File systems mounted besides the root, and the transactions in progress.
+    mounts: Sleepablelock<MountTable>,
+
+    /// Held by rename, the only operation that moves directories, so that the directory tree
+    /// does not change while it works out the order in which to lock directories.
+    pub rename_lock: Sleeplock<()>,
 }
 
+/// The file system type of the virtio disks.
+pub struct DiskFs;
+
 pub struct FsTransaction<'s> {
     fs: &'s FileSystem,
+
+    /// Blocks reserved in the log of each mounted file system.
+    reserved: usize,
+
+    /// The mounted file systems: bit dev is set for each device dev.
+    devs: u32,
+
+    /// Blocks the transaction has added to the log of each disk.
+    logged: [Cell<usize>; NDISK],
 }
 
 impl FileSystem {
     pub const fn zero() -> Self {
         Self {
-            superblock: Once::new(),
-            log: Once::new(),
-            disk: Sleepablelock::new("virtio_disk", Disk::zero()),
+            superblocks: array![_ => Once::<Superblock>::new(); NDISK],
+            logs: array![_ => Once::<Sleepablelock<Log>>::new(); NDISK],
+            disk: Disks::zero(),
+            tmpfs: Tmpfs::zero(),
+            mounts: Sleepablelock::new("mounts", MountTable::new()),
+            rename_lock: Sleeplock::new("rename", ()),
         }
     }
 
-    pub fn init(&self, dev: u32) {
-        self.superblock
-            .call_once(|| unsafe { Superblock::new(&self.disk.read(dev, 1)) });
-        self.log.call_once(|| {
+    /// Read the superblock of disk dev and recover its log, unless done already. The root disk
+    /// must be initialized first, since the buffer cache is sized by its superblock. Fails if
+    /// the disk holds no file system, or one whose log is smaller than the root's.
+    pub fn init(&self, dev: u32) -> Result<(), ()> {
+        let i = dev as usize - 1;
+        if self.superblocks[i].get().is_none() {
+            let sb = unsafe { Superblock::new(&self.disk.read(dev, 1)) }?;
+            // A transaction must fit in every log.
+            if dev != ROOTDEV && sb.nlog as usize <= self.max_transaction_blocks() {
+                return Err(());
+            }
+            self.superblocks[i].call_once(|| sb);
+        }
+
+        let mut log_created = false;
+        self.logs[i].call_once(|| {
+            log_created = true;
+            let nbuf = self.superblock(ROOTDEV).nbuf();
+            if dev == ROOTDEV {
+                kernel().bcache.lock().set_capacity(nbuf);
+            }
+            // Each log may pin its share of the buffer cache, leaving a share for the blocks
+            // that transactions read and write. See NBUF for what this costs the root log.
             Sleepablelock::new(
                 "LOG",
                 Log::new(
                     dev,
-                    self.superblock().logstart as i32,
-                    self.superblock().nlog as i32,
+                    self.superblock(dev).logstart as i32,
+                    self.superblock(dev).nlog as i32,
+                    nbuf / (NDISK + 1),
                 ),
             )
         });
+        if log_created {
+            // SAFETY: the log has been initialized.
+            unsafe {
+                kernel()
+                    .procs
+                    .spawn_kthread(b"logcommit", log_commit_thread, dev as usize)
+            }
+            .expect("FileSystem::init: cannot start the commit thread");
+        }
+        Ok(())
     }
 
-    /// TODO(rv6): calling superblock() after initialize is safe
-    fn superblock(&self) -> &Superblock {
-        if let Some(sb) = self.superblock.get() {
+    /// The superblock of disk dev, which must have been initialized.
+    fn superblock(&self, dev: u32) -> &Superblock {
+        if let Some(sb) = self.superblocks[dev as usize - 1].get() {
             sb
         } else {
             unreachable!()
         }
     }
 
-    /// TODO(rv6): calling log() after initialize is safe
-    fn log(&self) -> &Sleepablelock<Log> {
-        if let Some(log) = self.log.get() {
+    /// The log of disk dev, which must have been initialized.
+    fn log(&self, dev: u32) -> &Sleepablelock<Log> {
+        if let Some(log) = self.logs[dev as usize - 1].get() {
             log
         } else {
             unreachable!()
         }
     }
 
+    /// The type of the file system on device dev.
+    pub fn fs_type(&self, dev: u32) -> &'static dyn FsType {
+        match dev {
+            TMPFSDEV => &kernel().file_system.tmpfs,
+            PROCFSDEV => &Procfs,
+            _ => &DiskFs,
+        }
+    }
+
     /// Called for each FS system call.
     pub fn begin_transaction(&self) -> FsTransaction<'_> {
-        // TODO(rv6): safety?
-        unsafe {
-            Log::begin_op(self.log());
+        self.begin_transaction_reserving(MAXOPBLOCKS)
+    }
+
+    /// Called for an FS system call that may write up to `nblocks` blocks, which must not
+    /// exceed max_transaction_blocks(). The blocks are reserved in the log of every mounted file
+    /// system, in the order of their devices, so that transactions never wait for each other's
+    /// logs in a cycle.
+    pub fn begin_transaction_reserving(&self, nblocks: usize) -> FsTransaction<'_> {
+        let tx = FsTransaction {
+            fs: self,
+            reserved: nblocks,
+            devs: self.begin_mounted(),
+            logged: Default::default(),
+        };
+        for dev in tx.devs() {
+            // TODO(rv6): safety?
+            unsafe {
+                Log::begin_op(self.log(dev), nblocks);
+            }
+        }
+        tx
+    }
+
+    /// Most blocks a transaction may reserve. Every log mounted holds at least as many as the
+    /// root's.
+    pub fn max_transaction_blocks(&self) -> usize {
+        self.log(ROOTDEV).lock().capacity()
+    }
+
+    /// Wait until the transaction group of disk dev with sequence number `seq`, and all before
+    /// it, are on disk. Must not be called inside a transaction.
+    pub fn sync(&self, dev: u32, seq: u64) {
+        Log::sync(self.log(dev), seq);
+    }
+
+    /// Wait until every update made so far is on disk. Must not be called inside a transaction.
+    pub fn sync_all(&self) {
+        for log in self.logs.iter().filter_map(Once::get) {
+            Log::sync(log, u64::MAX);
+        }
+    }
+
+    /// Called periodically by the timer to bound how long updates stay only in memory.
+    pub fn request_commit(&self) {
+        for log in self.logs.iter().filter_map(Once::get) {
+            Log::request_commit(log);
         }
-        FsTransaction { fs: self }
     }
 }
 
+/// Body of the kernel thread that commits the log of disk dev.
+unsafe fn log_commit_thread(dev: usize) -> ! {
+    Log::commit_thread(kernel().file_system.log(dev as u32))
+}
+
 impl Drop for FsTransaction<'_> {
     fn drop(&mut self) {
         // Called at the end of each FS system call.
         // Commits if this was the last outstanding operation.
-        unsafe {
-            Log::end_op(self.fs.log());
+        for dev in self.devs() {
+            unsafe {
+                Log::end_op(self.fs.log(dev), self.reserved);
+            }
         }
+        self.fs.end_mounted();
     }
 }
 
 impl FsTransaction<'_> {
+    /// Devices of the file systems the transaction has reserved log space in.
+    fn devs(&self) -> impl Iterator<Item = u32> {
+        let devs = self.devs;
+        (1..=NDISK as u32).filter(move |dev| devs & 1 << dev != 0)
+    }
+
+    /// Sequence number of the transaction group of disk dev this transaction belongs to.
+    pub fn seq(&self, dev: u32) -> u64 {
+        self.fs.log(dev).lock().seq()
+    }
+
     /// Caller has modified b->data and is done with the buffer.
     /// Record the block number and pin in the cache by increasing refcnt.
     /// commit()/write_log() will do the disk write.
@@ -128,31 +275,95 @@ impl FsTransaction<'_> {
     ///   modify bp->data[]
     ///   write(bp)
     unsafe fn write(&self, b: Buf<'static>) {
-        self.fs.log().lock().write(b);
+        debug_assert!(
+            self.devs & 1 << b.dev != 0,
+            "write to a file system not mounted"
+        );
+        let logged = &self.logged[b.dev as usize - 1];
+        if self.fs.log(b.dev).lock().write(b) {
+            logged.set(logged.get() + 1);
+            debug_assert!(
+                logged.get() <= self.reserved,
+                "transaction writes more than it reserved"
+            );
+        }
     }
 
-    /// Zero a block.
-    unsafe fn bzero(&self, dev: u32, bno: u32) {
+    /// Write b, a data block of a regular file. On an ordered file system, the block is written
+    /// in place if it is safe to, and otherwise goes through the log like a metadata block.
+    unsafe fn write_data(&self, mut b: Buf<'static>) {
+        if self.in_place(b.dev, b.blockno) {
+            self.fs.disk.write(&mut b);
+        } else {
+            self.write(b);
+        }
+    }
+
+    /// Is the file system on dev ordered, and may its data block b be written in place?
+    fn in_place(&self, dev: u32, b: u32) -> bool {
+        self.fs.superblock(dev).is_ordered() && self.fs.log(dev).lock().may_write_in_place(b)
+    }
+
+    /// Zero the cached copy of a block.
+    unsafe fn zero_buf(&self, dev: u32, bno: u32) -> Buf<'static> {
         let mut buf = kernel().bcache.get_buf(dev, bno).lock();
         ptr::write_bytes(buf.deref_mut_inner().data.as_mut_ptr(), 0, BSIZE);
         buf.deref_mut_inner().valid = true;
-        self.write(buf);
+        buf
+    }
+
+    /// Zero a block.
+    unsafe fn bzero(&self, dev: u32, bno: u32) {
+        self.write(self.zero_buf(dev, bno));
     }
 
     /// Blocks.
     /// Allocate a zeroed disk block.
     unsafe fn balloc(&self, dev: u32) -> u32 {
-        for b in num_iter::range_step(0, self.fs.superblock().size, BPB) {
-            let mut bp = self.fs.disk.read(dev, self.fs.superblock().bblock(b));
-            for bi in 0..cmp::min(BPB, self.fs.superblock().size - b) {
-                let m = 1 << (bi % 8);
-                if bp.deref_mut_inner().data[(bi / 8) as usize] & m == 0 {
-                    // Is block free?
-                    bp.deref_mut_inner().data[(bi / 8) as usize] |= m; // Mark block in use.
-                    self.write(bp);
-                    self.bzero(dev, b + bi);
-                    return b + bi;
+        self.balloc_near(dev, 0)
+    }
+
+    /// Allocate a zeroed disk block, preferring block `goal` or else the first free block after
+    /// it, so that blocks allocated one after another tend to be contiguous on disk.
+    unsafe fn balloc_near(&self, dev: u32, goal: u32) -> u32 {
+        let blockno = self.alloc_bit(dev, goal);
+        self.bzero(dev, blockno);
+        blockno
+    }
+
+    /// Allocate a data block of a regular file, as balloc_near() does. If the block may be
+    /// written in place, it is not zeroed, and the caller must write all of it with write_data().
+    unsafe fn balloc_data(&self, dev: u32, goal: u32) -> u32 {
+        let blockno = self.alloc_bit(dev, goal);
+        if !self.in_place(dev, blockno) {
+            self.bzero(dev, blockno);
+        }
+        blockno
+    }
+
+    /// Mark a free block in use in the bitmap and return it, preferring block `goal` as
+    /// balloc_near() does.
+    unsafe fn alloc_bit(&self, dev: u32, goal: u32) -> u32 {
+        let size = self.fs.superblock(dev).size;
+        let goal = if goal < size { goal } else { 0 };
+
+        // Scan from goal to the end of the disk, then wrap around.
+        for &(start, end) in &[(goal, size), (0, goal)] {
+            let mut b = start;
+            while b < end {
+                let mut bp = self.fs.disk.read(dev, self.fs.superblock(dev).bblock(b));
+                let block_end = cmp::min(end, (b / BPB + 1) * BPB);
+                for blockno in b..block_end {
+                    let bi = blockno % BPB;
+                    let m = 1 << (bi % 8);
+                    if bp.deref_mut_inner().data[(bi / 8) as usize] & m == 0 {
+                        // Is block free?
+                        bp.deref_mut_inner().data[(bi / 8) as usize] |= m; // Mark block in use.
+                        self.write(bp);
+                        return blockno;
+                    }
                 }
+                b = block_end;
             }
         }
 
@@ -161,7 +372,7 @@ impl FsTransaction<'_> {
 
     /// Free a disk block.
     unsafe fn bfree(&self, dev: u32, b: u32) {
-        let mut bp = self.fs.disk.read(dev, self.fs.superblock().bblock(b));
+        let mut bp = self.fs.disk.read(dev, self.fs.superblock(dev).bblock(b));
         let bi = b.wrapping_rem(BPB) as i32;
         let m = 1u8 << (bi % 8);
         assert_ne!(
@@ -171,5 +382,8 @@ impl FsTransaction<'_> {
         );
         bp.deref_mut_inner().data[(bi / 8) as usize] &= !m;
         self.write(bp);
+        if self.fs.superblock(dev).is_ordered() {
+            self.fs.log(dev).lock().note_freed(b);
+        }
     }
 }
@@ -0,0 +1,181 @@
+//! File systems mounted on directories.
+//!
+//! The root file system is always mounted. The file system of another disk is mounted on a
+//! directory, its mount point, and path name lookup crosses from the mount point to the root of
+//! the mounted file system, and from that root back to the mount point for "..".
+//!
+//! A transaction reserves space in the log of every mounted file system when it begins, since
+//! it cannot tell which ones it will write. So that the file systems do not change under a
+//! transaction, mount and umount wait until no transaction is in progress, and no transaction
+//! begins until they are done.
+
+use crate::{
+    kernel::kernel,
+    param::{NMOUNT, ROOTDEV},
+};
+
+use super::{FileSystem, Inode, RcInode, ROOTINO};
+
+struct Mount {
+    /// Device of the mounted file system.
+    dev: u32,
+
+    /// The directory it is mounted on.
+    point: RcInode<'static>,
+}
+
+pub struct MountTable {
+    mounts: [Option<Mount>; NMOUNT],
+
+    /// Number of transactions in progress.
+    ntx: usize,
+
+    /// Is a mount or umount in progress? No transaction may begin until it is done.
+    changing: bool,
+}
+
+impl MountTable {
+    pub const fn new() -> Self {
+        Self {
+            mounts: array![_ => Option::<Mount>::None; NMOUNT],
+            ntx: 0,
+            changing: false,
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &Mount> {
+        self.mounts.iter().flatten()
+    }
+}
+
+impl Mount {
+    fn is_on(&self, ip: &Inode) -> bool {
+        self.point.dev == ip.dev && self.point.inum == ip.inum
+    }
+}
+
+impl FileSystem {
+    /// Count a transaction in, and return the mounted file systems: bit dev is set for each
+    /// device dev.
+    pub(super) fn begin_mounted(&self) -> u32 {
+        let mut guard = self.mounts.lock();
+        while guard.changing {
+            guard.sleep();
+        }
+        guard.ntx += 1;
+        guard.iter().fold(1 << ROOTDEV, |devs, m| devs | 1 << m.dev)
+    }
+
+    /// Count a transaction out.
+    pub(super) fn end_mounted(&self) {
+        let mut guard = self.mounts.lock();
+        guard.ntx -= 1;
+        if guard.ntx == 0 {
+            guard.wakeup();
+        }
+    }
+
+    /// Wait until no transaction is in progress, and keep any from beginning until
+    /// end_change().
+    fn begin_change(&self) {
+        let mut guard = self.mounts.lock();
+        while guard.changing {
+            guard.sleep();
+        }
+        guard.changing = true;
+        while guard.ntx > 0 {
+            guard.sleep();
+        }
+    }
+
+    fn end_change(&self) {
+        let mut guard = self.mounts.lock();
+        guard.changing = false;
+        guard.wakeup();
+    }
+
+    /// Drop an inode outside of a transaction.
+    fn put(&self, ip: RcInode<'static>) {
+        let tx = self.begin_transaction();
+        drop(ip);
+        drop(tx);
+    }
+
+    /// Mount the file system of disk dev on directory point, which must not be the root of a
+    /// file system. Fails if the disk holds no file system or is already mounted, or if another
+    /// file system is mounted on point. Must not be called inside a transaction.
+    pub fn mount(&self, dev: u32, point: RcInode<'static>) -> Result<(), ()> {
+        self.begin_change();
+        let slot = {
+            let guard = self.mounts.lock();
+            if dev == ROOTDEV
+                || point.inum == ROOTINO
+                || guard.iter().any(|m| m.dev == dev || m.is_on(&point))
+            {
+                None
+            } else {
+                guard.mounts.iter().position(Option::is_none)
+            }
+        };
+        match slot {
+            Some(i) if self.fs_type(dev).mount(dev).is_ok() => {
+                self.mounts.lock().mounts[i] = Some(Mount { dev, point });
+                self.end_change();
+                Ok(())
+            }
+            _ => {
+                self.end_change();
+                self.put(point);
+                Err(())
+            }
+        }
+    }
+
+    /// Unmount the file system of disk dev, committing its updates to disk. Fails if it is not
+    /// mounted, or if any of its inodes is in use, such as by an open file, a process's current
+    /// directory, or a file system mounted on one of its directories. Must not be called inside
+    /// a transaction.
+    pub fn umount(&self, dev: u32) -> Result<(), ()> {
+        self.begin_change();
+        let mount = if kernel().itable.in_use(dev) {
+            None
+        } else {
+            let mut guard = self.mounts.lock();
+            let slot = guard
+                .mounts
+                .iter_mut()
+                .find(|m| m.as_ref().map_or(false, |m| m.dev == dev));
+            slot.and_then(Option::take)
+        };
+        if mount.is_some() {
+            self.fs_type(dev).unmount(dev);
+        }
+        self.end_change();
+        self.put(mount.ok_or(())?.point);
+        Ok(())
+    }
+
+    /// If a file system is mounted on directory ip, return its root.
+    pub fn mounted_root(&self, ip: &Inode) -> Option<RcInode<'static>> {
+        let guard = self.mounts.lock();
+        let dev = guard.iter().find(|m| m.is_on(ip))?.dev;
+        drop(guard);
+        Some(kernel().itable.get_inode(dev, ROOTINO))
+    }
+
+    /// If ip is the root of a mounted file system other than the root file system, return the
+    /// directory it is mounted on.
+    pub fn mount_point(&self, ip: &Inode) -> Option<RcInode<'static>> {
+        if ip.inum != ROOTINO {
+            return None;
+        }
+        let guard = self.mounts.lock();
+        let m = guard.iter().find(|m| m.dev == ip.dev)?;
+        Some(m.point.clone())
+    }
+
+    /// Is a file system mounted on directory ip?
+    pub fn is_mount_point(&self, ip: &Inode) -> bool {
+        self.mounts.lock().iter().any(|m| m.is_on(ip))
+    }
+}
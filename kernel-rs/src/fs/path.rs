@@ -1,29 +1,31 @@
-use core::cmp;
 use core::mem;
 use cstr_core::CStr;
 
-use crate::{kernel::kernel, param::ROOTDEV, proc::myproc};
+use crate::{
+    kernel::kernel,
+    param::{MAXPATH, MAXSYMLINKS, ROOTDEV},
+    proc::myproc,
+    sandbox, some_or,
+    vm::{KVAddr, VAddr},
+};
 
-use super::{InodeType, RcInode, DIRSIZ, ROOTINO};
+use super::{Access, InodeType, RcInode, DIRSIZ, ROOTINO};
 
 #[derive(PartialEq)]
 #[repr(transparent)]
 pub struct FileName {
-    // Invariant:
-    // - The slice contains no NUL characters.
-    // - The slice is not longer than DIRSIZ.
+    // Invariant: the slice contains no NUL characters.
+    // It may be longer than DIRSIZ, in which case no directory can contain it.
     inner: [u8],
 }
 
 impl FileName {
-    /// Truncate bytes followed by the first DIRSIZ bytes.
-    ///
     /// # Safety
     ///
     /// `bytes` must not contain any NUL characters.
     pub unsafe fn from_bytes(bytes: &[u8]) -> &Self {
         debug_assert!(!bytes.contains(&0));
-        &*(&bytes[..cmp::min(DIRSIZ, bytes.len())] as *const [u8] as *const Self)
+        &*(bytes as *const [u8] as *const Self)
     }
 
     pub fn as_bytes(&self) -> &[u8] {
@@ -62,11 +64,16 @@ impl Path {
     }
 
     pub unsafe fn namei(&self) -> Result<RcInode<'static>, ()> {
-        Ok(self.namex(false)?.0)
+        Ok(self.namex(false, true)?.0)
+    }
+
+    /// Like namei(), but if the final path element is a symbolic link, return the link itself.
+    pub unsafe fn namei_nofollow(&self) -> Result<RcInode<'static>, ()> {
+        Ok(self.namex(false, false)?.0)
     }
 
     pub unsafe fn nameiparent(&self) -> Result<(RcInode<'static>, &FileName), ()> {
-        let (ip, name_in_path) = self.namex(true)?;
+        let (ip, name_in_path) = self.namex(true, false)?;
         let name_in_path = name_in_path.ok_or(())?;
         Ok((ip, name_in_path))
     }
@@ -101,7 +108,7 @@ impl Path {
     /// ```
     // TODO: Make an iterator.
     // TODO: Fix doctests work.
-    fn skipelem(&self) -> Option<(&Self, &FileName)> {
+    pub fn skipelem(&self) -> Option<(&Self, &FileName)> {
         let mut bytes = &self.inner;
 
         let name_start = bytes.iter().position(|ch| *ch != b'/')?;
@@ -127,38 +134,122 @@ impl Path {
     }
 
     /// Returns `true` if `Path` begins with `'/'`.
-    fn is_absolute(&self) -> bool {
+    pub fn is_absolute(&self) -> bool {
         !self.inner.is_empty() && self.inner[0] == b'/'
     }
 
     /// Look up and return the inode for a path name.
-    /// If parent != 0, return the inode for the parent and copy the final
-    /// path element into name, which must have room for DIRSIZ bytes.
+    /// If parent != 0, return the inode for the parent and the final
+    /// path element. Symbolic links are followed, except in the final
+    /// path element if parent or !follow. Fails if any path element is
+    /// longer than DIRSIZ, or if more than MAXSYMLINKS links are followed.
+    /// Crosses mount points in both directions.
     /// Must be called inside a transaction since it calls Inode::put().
-    unsafe fn namex(&self, parent: bool) -> Result<(RcInode<'static>, Option<&FileName>), ()> {
+    unsafe fn namex(
+        &self,
+        parent: bool,
+        follow: bool,
+    ) -> Result<(RcInode<'static>, Option<&FileName>), ()> {
+        sandbox::check_path(self)?;
         let mut ptr = if self.is_absolute() {
             Self::root()
         } else {
             (*(*myproc()).data.get()).cwd.clone().unwrap()
         };
 
-        let mut path = self;
+        // The path left to look up is buf[head..head_end] followed by tail. When a symbolic
+        // link is followed, the link's element is replaced by its target in buf. Since links in
+        // the final element are not followed if parent, the final name then comes from tail,
+        // which is part of self.
+        let mut buf = [0u8; MAXPATH];
+        let mut head = 0;
+        let mut head_end = 0;
+        let mut tail = self;
+        let mut tail_name;
+        let mut nlinks = 0;
 
-        while let Some((new_path, name)) = path.skipelem() {
-            path = new_path;
+        loop {
+            let name = match Self::from_bytes(&buf[head..head_end]).skipelem() {
+                Some((rest, name)) => {
+                    head = head_end - rest.inner.len();
+                    tail_name = None;
+                    name
+                }
+                None => {
+                    let (rest, name) = some_or!(tail.skipelem(), break);
+                    tail = rest;
+                    tail_name = Some(name);
+                    name
+                }
+            };
+            if name.as_bytes().len() > DIRSIZ {
+                return Err(());
+            }
+            let last = head == head_end && tail.inner.is_empty();
+
+            // ".." of the root of a mounted file system is ".." of the directory it is
+            // mounted on.
+            if name.as_bytes() == b".." {
+                if let Some(point) = kernel().file_system.mount_point(&ptr) {
+                    ptr = point;
+                }
+            }
 
             let mut ip = ptr.lock();
             if ip.deref_inner().typ != InodeType::Dir {
                 return Err(());
             }
-            if parent && path.inner.is_empty() {
+            // Searching a directory requires execute permission.
+            ip.permission(Access::EXEC)?;
+            if parent && last {
                 // Stop one level early.
                 mem::drop(ip);
-                return Ok((ptr, Some(name)));
+                return Ok((ptr, tail_name));
             }
             let next = ip.dirlookup(name);
             mem::drop(ip);
-            ptr = next?.0
+            let next = next?.0;
+            // Cross to the root of the file system mounted on next, if any.
+            let next = kernel().file_system.mounted_root(&next).unwrap_or(next);
+
+            if !last || follow {
+                let mut ip = next.lock();
+                if ip.deref_inner().typ == InodeType::Symlink {
+                    nlinks += 1;
+                    if nlinks > MAXSYMLINKS {
+                        return Err(());
+                    }
+
+                    // Replace the link's element with its target, which is looked up in the
+                    // directory holding the link, unless it is absolute.
+                    let len = ip.deref_inner().size as usize;
+                    let rest = head_end - head;
+                    let sep = if rest > 0 { 1 } else { 0 };
+                    if len == 0 || len + sep + rest > MAXPATH {
+                        return Err(());
+                    }
+                    buf.copy_within(head..head_end, len + sep);
+                    if sep > 0 {
+                        buf[len] = b'/';
+                    }
+                    if ip.read(KVAddr::new(buf.as_mut_ptr() as usize), 0, len as u32)? != len
+                        || buf[..len].contains(&0)
+                    {
+                        return Err(());
+                    }
+                    mem::drop(ip);
+                    head = 0;
+                    head_end = len + sep + rest;
+
+                    let target = Self::from_bytes(&buf[..len]);
+                    sandbox::check_path(target)?;
+                    if target.is_absolute() {
+                        ptr = Self::root();
+                    }
+                    continue;
+                }
+            }
+            ptr = next;
         }
         if parent {
             return Err(());
@@ -0,0 +1,395 @@
+//! Process file system.
+//!
+//! A synthetic file system, which init mounts on /proc. Nothing is stored: every directory
+//! listing and every file is generated on demand from the process pool and other kernel state.
+//!
+//!   /proc/meminfo       -- total and free physical memory, and memory used by tmpfs
+//!   /proc/uptime        -- clock ticks since boot
+//!   /proc/<pid>/status  -- summary of the process
+//!   /proc/<pid>/name    -- process name
+//!   /proc/<pid>/parent  -- parent's pid
+//!   /proc/<pid>/size    -- size of process memory (bytes)
+//!   /proc/<pid>/fd      -- open files, one per line
+//!   /proc/<pid>/cwd     -- device and inode number of the current directory
+//!
+//! The inode number of a file tells which file it is, so procfs keeps no state of its own. The
+//! offset of a directory entry is its index in the listing. Files are read-only, even for the
+//! superuser, and have size 0, since their contents are only known once they are read.
+
+use arrayvec::ArrayVec;
+use core::{
+    cmp,
+    fmt::{self, Write},
+    str,
+};
+
+use crate::{kernel::kernel, riscv::PGSIZE, some_or, time};
+
+use super::{
+    FileName, FsTransaction, Inode, InodeFlags, InodeGuard, InodeInner, InodeOps, InodeType,
+    RcInode, SuperOps, ROOTINO,
+};
+
+/// Size of the buffer used to generate the content of a procfs file.
+const PROCFS_BUFSIZE: usize = 512;
+
+/// Files in each /proc/<pid> directory.
+#[derive(Copy, Clone, PartialEq)]
+enum PidEntry {
+    Status,
+    Name,
+    Parent,
+    Size,
+    Fd,
+    Cwd,
+}
+
+const PID_ENTRIES: [PidEntry; 6] = [
+    PidEntry::Status,
+    PidEntry::Name,
+    PidEntry::Parent,
+    PidEntry::Size,
+    PidEntry::Fd,
+    PidEntry::Cwd,
+];
+
+#[derive(Copy, Clone, PartialEq)]
+enum ProcfsNode {
+    /// /proc
+    Root,
+    /// /proc/meminfo
+    Meminfo,
+    /// /proc/uptime
+    Uptime,
+    /// /proc/<pid>
+    PidDir(i32),
+    /// /proc/<pid>/<entry>
+    Pid(i32, PidEntry),
+}
+
+/// Accumulates formatted text, silently dropping what does not fit.
+struct ProcfsBuf {
+    inner: ArrayVec<[u8; PROCFS_BUFSIZE]>,
+}
+
+impl Write for ProcfsBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for &c in s.as_bytes() {
+            if self.inner.try_push(c).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl PidEntry {
+    fn name(self) -> &'static [u8] {
+        match self {
+            PidEntry::Status => b"status",
+            PidEntry::Name => b"name",
+            PidEntry::Parent => b"parent",
+            PidEntry::Size => b"size",
+            PidEntry::Fd => b"fd",
+            PidEntry::Cwd => b"cwd",
+        }
+    }
+}
+
+impl ProcfsNode {
+    fn is_dir(self) -> bool {
+        matches!(self, ProcfsNode::Root | ProcfsNode::PidDir(_))
+    }
+
+    /// A made-up inode number, unique among procfs nodes.
+    fn inum(self) -> u32 {
+        match self {
+            ProcfsNode::Root => ROOTINO,
+            ProcfsNode::Meminfo => 2,
+            ProcfsNode::Uptime => 3,
+            ProcfsNode::PidDir(pid) => (pid as u32).wrapping_mul(8),
+            ProcfsNode::Pid(pid, entry) => (pid as u32)
+                .wrapping_mul(8)
+                .wrapping_add(1)
+                .wrapping_add(entry as u32),
+        }
+    }
+
+    /// The node whose inode number is inum, or None if there is no such node.
+    fn from_inum(inum: u32) -> Option<Self> {
+        match (inum, inum / 8, inum % 8) {
+            (ROOTINO, _, _) => Some(ProcfsNode::Root),
+            (2, _, _) => Some(ProcfsNode::Meminfo),
+            (3, _, _) => Some(ProcfsNode::Uptime),
+            (_, 0, _) => None,
+            (_, pid, 0) => Some(ProcfsNode::PidDir(pid as i32)),
+            (_, pid, i) => Some(ProcfsNode::Pid(
+                pid as i32,
+                *PID_ENTRIES.get(i as usize - 1)?,
+            )),
+        }
+    }
+
+    /// Writes the name of the `index`th entry of directory self into `buf`, and returns its inode
+    /// number. Returns `None` if there is no such entry.
+    fn dir_entry(self, index: usize, buf: &mut ProcfsBuf) -> Option<u32> {
+        match (self, index) {
+            (_, 0) => {
+                buf.inner.push(b'.');
+                Some(self.inum())
+            }
+            (_, 1) => {
+                let parent = match self {
+                    ProcfsNode::PidDir(_) => ProcfsNode::Root,
+                    _ => self,
+                };
+                let _ = buf.write_str("..");
+                Some(parent.inum())
+            }
+            (ProcfsNode::Root, 2) => {
+                let _ = buf.write_str("meminfo");
+                Some(ProcfsNode::Meminfo.inum())
+            }
+            (ProcfsNode::Root, 3) => {
+                let _ = buf.write_str("uptime");
+                Some(ProcfsNode::Uptime.inum())
+            }
+            (ProcfsNode::Root, index) => {
+                let pid = kernel().procs.nth_pid(index - 4)?;
+                let _ = write!(buf, "{}", pid);
+                Some(ProcfsNode::PidDir(pid).inum())
+            }
+            (ProcfsNode::PidDir(pid), index) => {
+                let entry = *PID_ENTRIES.get(index - 2)?;
+                buf.inner
+                    .try_extend_from_slice(entry.name())
+                    .expect("dir_entry");
+                Some(ProcfsNode::Pid(pid, entry).inum())
+            }
+            _ => None,
+        }
+    }
+
+    /// Generate the content of file self.
+    fn content(self, buf: &mut ProcfsBuf) -> Result<(), ()> {
+        match self {
+            ProcfsNode::Meminfo => {
+                let (total, free) = kernel().meminfo();
+                let _ = write!(
+                    buf,
+                    "total:\t{} kB\nfree:\t{} kB\ntmpfs:\t{} kB\n",
+                    total * PGSIZE / 1024,
+                    free * PGSIZE / 1024,
+                    kernel().file_system.tmpfs.npages() * PGSIZE / 1024
+                );
+            }
+            ProcfsNode::Uptime => {
+                let _ = writeln!(buf, "{}", *kernel().ticks.lock());
+            }
+            ProcfsNode::Pid(pid, entry) => {
+                let st = unsafe { kernel().procs.stat(pid) }.ok_or(())?;
+                let length = st
+                    .name
+                    .iter()
+                    .position(|&c| c == 0)
+                    .unwrap_or(st.name.len());
+                let name = str::from_utf8(&st.name[..length]).unwrap_or("???");
+                let _ = match entry {
+                    PidEntry::Status => write!(
+                        buf,
+                        "pid:\t{}\nstate:\t{}\nname:\t{}\nppid:\t{}\nsize:\t{}\n",
+                        st.pid,
+                        st.state.to_str().trim_end(),
+                        name,
+                        st.ppid,
+                        st.sz
+                    ),
+                    PidEntry::Name => writeln!(buf, "{}", name),
+                    PidEntry::Parent => writeln!(buf, "{}", st.ppid),
+                    PidEntry::Size => writeln!(buf, "{}", st.sz),
+                    PidEntry::Fd => {
+                        for (fd, f) in st.open_files.iter().enumerate() {
+                            if let Some(f) = f {
+                                let _ = writeln!(buf, "{}\t{}\t{}", fd, f.kind, f.inum);
+                            }
+                        }
+                        Ok(())
+                    }
+                    PidEntry::Cwd => match st.cwd {
+                        Some((dev, inum)) => writeln!(buf, "{} {}", dev, inum),
+                        None => Ok(()),
+                    },
+                };
+            }
+            ProcfsNode::Root | ProcfsNode::PidDir(_) => return Err(()),
+        }
+        Ok(())
+    }
+}
+
+/// Parse a decimal pid. Leading zeros are rejected so that each process has a single name.
+fn parse_pid(name: &[u8]) -> Result<i32, ()> {
+    if name.is_empty() || name.len() > 9 || name[0] == b'0' {
+        return Err(());
+    }
+    let mut pid = 0;
+    for &c in name {
+        if !(b'0'..=b'9').contains(&c) {
+            return Err(());
+        }
+        pid = pid * 10 + (c - b'0') as i32;
+    }
+    Ok(pid)
+}
+
+/// The file system type of procfs, whose device number is PROCFSDEV.
+pub struct Procfs;
+
+impl Procfs {
+    /// The node of inode ip, which procfs gave out.
+    fn node(ip: &Inode) -> ProcfsNode {
+        ProcfsNode::from_inum(ip.inum).expect("procfs: bad inode number")
+    }
+}
+
+impl SuperOps for Procfs {
+    fn read_inode(&self, ip: &Inode, inner: &mut InodeInner) {
+        let now = time::now();
+        let node = Self::node(ip);
+        if node.is_dir() {
+            inner.typ = InodeType::Dir;
+            inner.nlink = 2;
+            inner.mode = 0o555;
+        } else {
+            inner.typ = InodeType::File;
+            inner.nlink = 1;
+            inner.mode = 0o444;
+        }
+        inner.size = 0;
+        inner.uid = 0;
+        inner.gid = 0;
+        inner.flags = InodeFlags::empty();
+        inner.atime = now;
+        inner.mtime = now;
+        inner.ctime = now;
+        inner.crtime = now;
+        inner.blocks = 0;
+        inner.seq = 0;
+    }
+
+    /// Keeps nothing, since nothing can be changed.
+    fn write_inode(&self, _ip: &InodeGuard<'_>, _tx: &FsTransaction<'_>) {}
+
+    fn alloc_inode(&self, _dev: u32, _typ: InodeType, _tx: &FsTransaction<'_>) -> Result<u32, ()> {
+        Err(())
+    }
+
+    /// Never called, since no file loses its links.
+    fn free_inode(&self, _ip: &mut InodeGuard<'_>, _tx: &FsTransaction<'_>) {}
+
+    fn mount(&self, _dev: u32) -> Result<(), ()> {
+        Ok(())
+    }
+
+    fn unmount(&self, _dev: u32) {}
+
+    fn sync(&self, _dev: u32, _seq: u64) {}
+
+    fn read_only(&self) -> bool {
+        true
+    }
+}
+
+impl InodeOps for Procfs {
+    fn lookup(
+        &self,
+        dp: &mut InodeGuard<'_>,
+        name: &FileName,
+    ) -> Result<(RcInode<'static>, u32), ()> {
+        let node = Self::node(dp);
+        let mut buf = ProcfsBuf {
+            inner: ArrayVec::new(),
+        };
+        for index in 0.. {
+            buf.inner.clear();
+            let inum = node.dir_entry(index, &mut buf).ok_or(())?;
+            if buf.inner.as_slice() == name.as_bytes() {
+                return Ok((kernel().itable.get_inode(dp.dev, inum), index as u32));
+            }
+        }
+        Err(())
+    }
+
+    fn link(
+        &self,
+        _dp: &mut InodeGuard<'_>,
+        _name: &FileName,
+        _inum: u32,
+        _tx: &FsTransaction<'_>,
+    ) -> Result<(), ()> {
+        Err(())
+    }
+
+    /// Never called, since no directory may be written.
+    fn replace(&self, _dp: &mut InodeGuard<'_>, _off: u32, _inum: u32, _tx: &FsTransaction<'_>) {}
+
+    fn readdir(
+        &self,
+        dp: &mut InodeGuard<'_>,
+        off: &mut u32,
+        f: &mut dyn FnMut(u32, &FileName) -> Result<bool, ()>,
+    ) -> Result<(), ()> {
+        let node = Self::node(dp);
+        let mut buf = ProcfsBuf {
+            inner: ArrayVec::new(),
+        };
+        loop {
+            buf.inner.clear();
+            let inum = some_or!(node.dir_entry(*off as usize, &mut buf), break);
+            // SAFETY: names of procfs entries don't contain '\0'.
+            if !f(inum, unsafe { FileName::from_bytes(&buf.inner) })? {
+                break;
+            }
+            *off += 1;
+        }
+        Ok(())
+    }
+
+    /// Generates the file and passes the part of it at off.
+    fn read(
+        &self,
+        ip: &mut InodeGuard<'_>,
+        off: u32,
+        n: u32,
+        f: &mut dyn FnMut(&[u8]) -> Result<(), ()>,
+    ) -> Result<usize, ()> {
+        let mut buf = ProcfsBuf {
+            inner: ArrayVec::new(),
+        };
+        Self::node(ip).content(&mut buf)?;
+        let begin = cmp::min(off as usize, buf.inner.len());
+        let end = cmp::min(begin + n as usize, buf.inner.len());
+        f(&buf.inner[begin..end])?;
+        Ok(end - begin)
+    }
+
+    fn write(
+        &self,
+        _ip: &mut InodeGuard<'_>,
+        _off: u32,
+        _n: u32,
+        _tx: &FsTransaction<'_>,
+        _f: &mut dyn FnMut(&mut [u8]) -> Result<(), ()>,
+    ) -> Result<usize, ()> {
+        Err(())
+    }
+
+    fn truncate(
+        &self,
+        _ip: &mut InodeGuard<'_>,
+        _len: u32,
+        _tx: &FsTransaction<'_>,
+    ) -> Result<(), ()> {
+        Err(())
+    }
+}
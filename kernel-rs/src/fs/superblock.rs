@@ -1,10 +1,15 @@
-use core::{mem, ptr};
+use core::{cmp, mem, ptr};
 
-use crate::{bio::Buf, param::BSIZE};
+use crate::{
+    bio::Buf,
+    param::{BSIZE, MAXNBUF, NBUF},
+};
 
 use super::Dinode;
 
-const FSMAGIC: u32 = 0x10203040;
+/// Identifies the current on-disk format. Changed whenever the layout of inodes or directory
+/// records changes, so that a disk in an older format is not misread.
+const FSMAGIC: u32 = 0x10203041;
 
 /// Disk layout:
 /// [ boot block | super block | log | inode blocks |
@@ -37,8 +42,22 @@ pub struct Superblock {
 
     /// Block number of first free map block
     pub bmapstart: u32,
+
+    /// Optional features of the file system, or zero for none. Images made before this format,
+    /// with or without features, have a different FSMAGIC and cannot be mounted.
+    features: u32,
+
+    /// Number of buffers in the disk block cache, or zero for NBUF
+    nbuf: u32,
 }
 
+/// Files are mapped by extents instead of block addresses.
+pub const FEATURE_EXTENTS: u32 = 1;
+
+/// Data blocks of regular files are written in place before the transaction that allocates them
+/// commits, and only metadata goes through the log.
+pub const FEATURE_ORDERED: u32 = 2;
+
 /// Inodes per block.
 pub const IPB: usize = BSIZE.wrapping_div(mem::size_of::<Dinode>());
 
@@ -46,11 +65,29 @@ pub const IPB: usize = BSIZE.wrapping_div(mem::size_of::<Dinode>());
 pub const BPB: u32 = BSIZE.wrapping_mul(8) as u32;
 
 impl Superblock {
-    /// Read the super block.
-    pub unsafe fn new(buf: &Buf<'static>) -> Self {
+    /// Read the super block. Fails if the block does not hold one.
+    pub unsafe fn new(buf: &Buf<'static>) -> Result<Self, ()> {
         let result = ptr::read(buf.deref_inner().data.as_ptr() as *const Superblock);
-        assert_eq!(result.magic, FSMAGIC, "invalid file system");
-        result
+        if result.magic != FSMAGIC {
+            return Err(());
+        }
+        Ok(result)
+    }
+
+    pub const fn has_extents(self) -> bool {
+        self.features & FEATURE_EXTENTS != 0
+    }
+
+    pub const fn is_ordered(self) -> bool {
+        self.features & FEATURE_ORDERED != 0
+    }
+
+    /// Number of buffers the disk block cache should use.
+    pub fn nbuf(self) -> usize {
+        match self.nbuf {
+            0 => NBUF,
+            n => cmp::min(n as usize, MAXNBUF),
+        }
     }
 
     /// Block containing inode i
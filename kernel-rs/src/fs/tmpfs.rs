@@ -0,0 +1,520 @@
+//! In-memory file system.
+//!
+//! A tmpfs keeps its files in kernel memory, so that temporary files do not go to the disk,
+//! and loses them when it is unmounted. Its inodes are the NTMPINODE nodes of a table, inode
+//! inum being node inum - 1 and the root directory being inode ROOTINO.
+//!
+//! The data of a file is in pages listed by its index page, a page of page addresses, which is
+//! allocated along with the first page of the file. A page that is not listed is a hole. A
+//! directory is a file of TmpDirent records, and a record whose inum is 0 is free.
+//!
+//! At most NTMPPAGE pages, index pages included, are in use at a time. The pages of a file are
+//! freed as it shrinks, and when it is removed.
+
+use core::{cmp, mem, slice};
+
+use crate::{
+    kernel::kernel,
+    ok_or,
+    page::Page,
+    param::{BSIZE, NTMPINODE, NTMPPAGE},
+    riscv::PGSIZE,
+    some_or,
+    spinlock::Spinlock,
+    stat::S_IALLUGO,
+    time,
+};
+
+use super::{
+    FileName, FsTransaction, Inode, InodeFlags, InodeGuard, InodeInner, InodeOps, InodeType,
+    RcInode, SuperOps, DIRSIZ, ROOTINO,
+};
+
+/// Number of pages an index page lists.
+const NINDEX: usize = PGSIZE / mem::size_of::<usize>();
+
+/// Largest size of a file.
+const MAXSIZE: usize = NINDEX * PGSIZE;
+
+/// Number of blocks of stat() a page counts as.
+const PAGE_BLOCKS: u32 = (PGSIZE / BSIZE) as u32;
+
+/// Size of a directory record.
+const DIRENT_SIZE: usize = mem::size_of::<TmpDirent>();
+
+/// Number of directory records in a page. Records do not cross pages.
+const DIRENTS_PER_PAGE: usize = PGSIZE / DIRENT_SIZE;
+
+/// What holes read as.
+static ZEROES: [u8; PGSIZE] = [0; PGSIZE];
+
+#[repr(C)]
+struct TmpDirent {
+    /// Inode number, or 0 if the record is free.
+    inum: u32,
+
+    /// Length of name.
+    namelen: u8,
+
+    name: [u8; DIRSIZ],
+}
+
+impl TmpDirent {
+    fn set(&mut self, inum: u32, name: &FileName) {
+        let name = name.as_bytes();
+        self.inum = inum;
+        self.namelen = name.len() as u8;
+        self.name[..name.len()].copy_from_slice(name);
+    }
+
+    fn get_name(&self) -> &FileName {
+        // Safety: a name in a directory doesn't contain '\0'.
+        unsafe { FileName::from_bytes(&self.name[..self.namelen as usize]) }
+    }
+}
+
+/// An inode of a tmpfs, holding the fields of InodeInner that a tmpfs keeps.
+struct TmpNode {
+    typ: InodeType,
+    nlink: i16,
+    size: u32,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    atime: u32,
+    mtime: u32,
+    ctime: u32,
+    crtime: u32,
+    blocks: u32,
+
+    /// Address of the index page, or 0 if the file has no pages.
+    index: usize,
+}
+
+impl TmpNode {
+    const fn zero() -> Self {
+        Self {
+            typ: InodeType::None,
+            nlink: 0,
+            size: 0,
+            mode: 0,
+            uid: 0,
+            gid: 0,
+            atime: 0,
+            mtime: 0,
+            ctime: 0,
+            crtime: 0,
+            blocks: 0,
+            index: 0,
+        }
+    }
+}
+
+struct TmpfsInner {
+    nodes: [TmpNode; NTMPINODE],
+
+    /// Number of pages in use.
+    npages: usize,
+}
+
+/// The file system type of the tmpfs, whose device number is TMPFSDEV.
+///
+/// The lock only protects the node table and the page count. Like the blocks of a disk file,
+/// the pages of a file are only accessed while its inode is locked.
+pub struct Tmpfs {
+    inner: Spinlock<TmpfsInner>,
+}
+
+impl Tmpfs {
+    pub const fn zero() -> Self {
+        Self {
+            inner: Spinlock::new(
+                "TMPFS",
+                TmpfsInner {
+                    nodes: array![_ => TmpNode::zero(); NTMPINODE],
+                    npages: 0,
+                },
+            ),
+        }
+    }
+
+    /// Number of pages in use.
+    pub fn npages(&self) -> usize {
+        self.inner.lock().npages
+    }
+
+    /// Allocate a zeroed page. Fails if NTMPPAGE pages are in use, or if memory runs out.
+    fn alloc_page(&self) -> Result<usize, ()> {
+        let mut inner = self.inner.lock();
+        if inner.npages == NTMPPAGE {
+            return Err(());
+        }
+        let mut page = unsafe { kernel().alloc() }.ok_or(())?;
+        inner.npages += 1;
+        drop(inner);
+        page.write_bytes(0);
+        Ok(page.into_usize())
+    }
+
+    fn free_page(&self, pa: usize) {
+        unsafe { kernel().free(Page::from_usize(pa)) };
+        self.inner.lock().npages -= 1;
+    }
+
+    /// The pages the index page of inode inum lists, or None if it has no index page.
+    fn index(&self, inum: u32) -> Option<&mut [usize]> {
+        let index = self.inner.lock().nodes[inum as usize - 1].index;
+        if index == 0 {
+            return None;
+        }
+        // SAFETY: an index page holds NINDEX page addresses, and is only accessed while the
+        // inode is locked.
+        Some(unsafe { slice::from_raw_parts_mut(index as *mut usize, NINDEX) })
+    }
+
+    /// Returns the address of the nth page of the file of inode inum, or 0 if it is a hole.
+    fn page(&self, inum: u32, bn: usize) -> usize {
+        self.index(inum).map_or(0, |pages| pages[bn])
+    }
+
+    /// Returns the address of the nth page of the file of inode inum, allocating it and adding
+    /// to *blocks if it is a hole. Fails if the page would lie past MAXSIZE, or if no page is
+    /// left.
+    fn page_or_alloc(&self, inum: u32, bn: usize, blocks: &mut u32) -> Result<usize, ()> {
+        if bn >= NINDEX {
+            return Err(());
+        }
+        let pages = match self.index(inum) {
+            Some(pages) => pages,
+            None => {
+                let index = self.alloc_page()?;
+                self.inner.lock().nodes[inum as usize - 1].index = index;
+                *blocks += PAGE_BLOCKS;
+                self.index(inum).unwrap()
+            }
+        };
+        if pages[bn] == 0 {
+            pages[bn] = self.alloc_page()?;
+            *blocks += PAGE_BLOCKS;
+        }
+        Ok(pages[bn])
+    }
+
+    /// Free the pages of the file of inode inum from the `first`th on, and its index page if
+    /// none is left, subtracting them from *blocks.
+    fn free_from(&self, inum: u32, first: usize, blocks: &mut u32) {
+        let pages = some_or!(self.index(inum), return);
+        for pa in pages.iter_mut().skip(first) {
+            if *pa != 0 {
+                self.free_page(*pa);
+                *pa = 0;
+                *blocks -= PAGE_BLOCKS;
+            }
+        }
+        if first == 0 {
+            let index = mem::replace(&mut self.inner.lock().nodes[inum as usize - 1].index, 0);
+            self.free_page(index);
+            *blocks -= PAGE_BLOCKS;
+        }
+    }
+
+    /// The record at offset off of the directory inode inum, which must lie within the
+    /// directory.
+    ///
+    /// # Safety
+    ///
+    /// The directory must be locked, or not in use, and the record must not be borrowed
+    /// already.
+    #[allow(clippy::mut_from_ref)]
+    unsafe fn dirent(&self, inum: u32, off: u32) -> &mut TmpDirent {
+        let i = off as usize / DIRENT_SIZE;
+        let page = self.page(inum, i / DIRENTS_PER_PAGE);
+        assert_ne!(page, 0, "tmpfs: directory hole");
+        &mut *(page as *mut TmpDirent).add(i % DIRENTS_PER_PAGE)
+    }
+
+    /// Put the record (name, inum) in the first free record of the directory inode dir, or
+    /// else in a new record at its end, adding to *size and *blocks.
+    fn put_dirent(
+        &self,
+        dir: u32,
+        name: &FileName,
+        inum: u32,
+        size: &mut u32,
+        blocks: &mut u32,
+    ) -> Result<(), ()> {
+        let mut off = 0;
+        while off < *size {
+            let de = unsafe { self.dirent(dir, off) };
+            if de.inum == 0 {
+                de.set(inum, name);
+                return Ok(());
+            }
+            off += DIRENT_SIZE as u32;
+        }
+        let _ = self.page_or_alloc(dir, off as usize / DIRENT_SIZE / DIRENTS_PER_PAGE, blocks)?;
+        *size += DIRENT_SIZE as u32;
+        unsafe { self.dirent(dir, off) }.set(inum, name);
+        Ok(())
+    }
+}
+
+impl SuperOps for Tmpfs {
+    fn read_inode(&self, ip: &Inode, inner: &mut InodeInner) {
+        let guard = self.inner.lock();
+        let node = &guard.nodes[ip.inum as usize - 1];
+        inner.typ = node.typ;
+        inner.nlink = node.nlink;
+        inner.size = node.size;
+        inner.mode = node.mode;
+        inner.uid = node.uid;
+        inner.gid = node.gid;
+        inner.flags = InodeFlags::empty();
+        inner.atime = node.atime;
+        inner.mtime = node.mtime;
+        inner.ctime = node.ctime;
+        inner.crtime = node.crtime;
+        inner.blocks = node.blocks;
+        inner.seq = 0;
+    }
+
+    fn write_inode(&self, ip: &InodeGuard<'_>, _tx: &FsTransaction<'_>) {
+        let inner = ip.deref_inner();
+        let mut guard = self.inner.lock();
+        let node = &mut guard.nodes[ip.inum as usize - 1];
+        node.typ = inner.typ;
+        node.nlink = inner.nlink;
+        node.size = inner.size;
+        node.mode = inner.mode;
+        node.uid = inner.uid;
+        node.gid = inner.gid;
+        node.atime = inner.atime;
+        node.mtime = inner.mtime;
+        node.ctime = inner.ctime;
+        node.crtime = inner.crtime;
+        node.blocks = inner.blocks;
+    }
+
+    fn alloc_inode(&self, _dev: u32, typ: InodeType, _tx: &FsTransaction<'_>) -> Result<u32, ()> {
+        let mut guard = self.inner.lock();
+        let i = guard
+            .nodes
+            .iter()
+            .position(|node| node.typ == InodeType::None)
+            .ok_or(())?;
+        let now = time::now();
+        guard.nodes[i] = TmpNode {
+            typ,
+            atime: now,
+            mtime: now,
+            ctime: now,
+            crtime: now,
+            ..TmpNode::zero()
+        };
+        Ok(i as u32 + 1)
+    }
+
+    fn free_inode(&self, ip: &mut InodeGuard<'_>, tx: &FsTransaction<'_>) {
+        let inum = ip.inum;
+        let inner = ip.deref_inner_mut();
+        self.free_from(inum, 0, &mut inner.blocks);
+        inner.typ = InodeType::None;
+        inner.size = 0;
+        self.write_inode(ip, tx);
+    }
+
+    /// Creates an empty root directory that anyone may write to.
+    fn mount(&self, _dev: u32) -> Result<(), ()> {
+        let (mut size, mut blocks) = (0, 0);
+        // Safety: "." and ".." don't contain '\0'.
+        let res = self
+            .put_dirent(
+                ROOTINO,
+                unsafe { FileName::from_bytes(b".") },
+                ROOTINO,
+                &mut size,
+                &mut blocks,
+            )
+            .and_then(|_| {
+                self.put_dirent(
+                    ROOTINO,
+                    unsafe { FileName::from_bytes(b"..") },
+                    ROOTINO,
+                    &mut size,
+                    &mut blocks,
+                )
+            });
+        if res.is_err() {
+            self.free_from(ROOTINO, 0, &mut blocks);
+            return Err(());
+        }
+        let now = time::now();
+        let mut guard = self.inner.lock();
+        let root = &mut guard.nodes[ROOTINO as usize - 1];
+        root.typ = InodeType::Dir;
+        root.nlink = 1;
+        root.size = size;
+        root.mode = S_IALLUGO;
+        root.atime = now;
+        root.mtime = now;
+        root.ctime = now;
+        root.crtime = now;
+        root.blocks = blocks;
+        Ok(())
+    }
+
+    /// Frees every file.
+    fn unmount(&self, _dev: u32) {
+        for inum in 1..=NTMPINODE as u32 {
+            let mut blocks = self.inner.lock().nodes[inum as usize - 1].blocks;
+            self.free_from(inum, 0, &mut blocks);
+            debug_assert_eq!(blocks, 0, "tmpfs: blocks left");
+            self.inner.lock().nodes[inum as usize - 1] = TmpNode::zero();
+        }
+        debug_assert_eq!(self.inner.lock().npages, 0, "tmpfs: pages left");
+    }
+
+    fn sync(&self, _dev: u32, _seq: u64) {}
+}
+
+impl InodeOps for Tmpfs {
+    fn lookup(
+        &self,
+        dp: &mut InodeGuard<'_>,
+        name: &FileName,
+    ) -> Result<(RcInode<'static>, u32), ()> {
+        let mut off = 0;
+        while off < dp.deref_inner().size {
+            let de = unsafe { self.dirent(dp.inum, off) };
+            if de.inum != 0 && name == de.get_name() {
+                return Ok((kernel().itable.get_inode(dp.dev, de.inum), off));
+            }
+            off += DIRENT_SIZE as u32;
+        }
+        Err(())
+    }
+
+    fn link(
+        &self,
+        dp: &mut InodeGuard<'_>,
+        name: &FileName,
+        inum: u32,
+        _tx: &FsTransaction<'_>,
+    ) -> Result<(), ()> {
+        let dir = dp.inum;
+        let inner = dp.deref_inner_mut();
+        self.put_dirent(dir, name, inum, &mut inner.size, &mut inner.blocks)
+    }
+
+    fn replace(&self, dp: &mut InodeGuard<'_>, off: u32, inum: u32, _tx: &FsTransaction<'_>) {
+        unsafe { self.dirent(dp.inum, off) }.inum = inum;
+    }
+
+    fn readdir(
+        &self,
+        dp: &mut InodeGuard<'_>,
+        off: &mut u32,
+        f: &mut dyn FnMut(u32, &FileName) -> Result<bool, ()>,
+    ) -> Result<(), ()> {
+        while *off < dp.deref_inner().size {
+            let de = unsafe { self.dirent(dp.inum, *off) };
+            if de.inum != 0 && !f(de.inum, de.get_name())? {
+                break;
+            }
+            *off += DIRENT_SIZE as u32;
+        }
+        Ok(())
+    }
+
+    fn read(
+        &self,
+        ip: &mut InodeGuard<'_>,
+        mut off: u32,
+        n: u32,
+        f: &mut dyn FnMut(&[u8]) -> Result<(), ()>,
+    ) -> Result<usize, ()> {
+        let size = ip.deref_inner().size;
+        if off > size {
+            return Ok(0);
+        }
+        let n = cmp::min(n, size - off);
+        let mut tot: u32 = 0;
+        while tot < n {
+            let page = self.page(ip.inum, off as usize / PGSIZE);
+            let begin = off as usize % PGSIZE;
+            let m = cmp::min(n - tot, (PGSIZE - begin) as u32);
+            let end = begin + m as usize;
+            if page == 0 {
+                f(&ZEROES[begin..end])?;
+            } else {
+                // SAFETY: the page is only accessed while the inode is locked.
+                f(unsafe { &slice::from_raw_parts(page as *const u8, PGSIZE)[begin..end] })?;
+            }
+            tot += m;
+            off += m;
+        }
+        Ok(tot as usize)
+    }
+
+    fn write(
+        &self,
+        ip: &mut InodeGuard<'_>,
+        mut off: u32,
+        n: u32,
+        _tx: &FsTransaction<'_>,
+        f: &mut dyn FnMut(&mut [u8]) -> Result<(), ()>,
+    ) -> Result<usize, ()> {
+        if off as usize + n as usize > MAXSIZE {
+            return Err(());
+        }
+        let inum = ip.inum;
+        let mut tot: u32 = 0;
+        while tot < n {
+            let bn = off as usize / PGSIZE;
+            let page = ok_or!(
+                self.page_or_alloc(inum, bn, &mut ip.deref_inner_mut().blocks),
+                break
+            );
+            let begin = off as usize % PGSIZE;
+            let m = cmp::min(n - tot, (PGSIZE - begin) as u32);
+            let end = begin + m as usize;
+            // SAFETY: the page is only accessed while the inode is locked.
+            let data = unsafe { slice::from_raw_parts_mut(page as *mut u8, PGSIZE) };
+            if f(&mut data[begin..end]).is_err() {
+                break;
+            }
+            tot += m;
+            off += m;
+        }
+        Ok(tot as usize)
+    }
+
+    /// Frees the pages past len and zeroes the rest of the new last page.
+    fn truncate(
+        &self,
+        ip: &mut InodeGuard<'_>,
+        len: u32,
+        _tx: &FsTransaction<'_>,
+    ) -> Result<(), ()> {
+        if len as usize > MAXSIZE {
+            return Err(());
+        }
+        if len < ip.deref_inner().size {
+            let inum = ip.inum;
+            let first = (len as usize + PGSIZE - 1) / PGSIZE;
+            self.free_from(inum, first, &mut ip.deref_inner_mut().blocks);
+            let tail = len as usize % PGSIZE;
+            if tail != 0 {
+                let page = self.page(inum, len as usize / PGSIZE);
+                if page != 0 {
+                    // SAFETY: the page is only accessed while the inode is locked.
+                    let data = unsafe { slice::from_raw_parts_mut(page as *mut u8, PGSIZE) };
+                    for b in &mut data[tail..] {
+                        *b = 0;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,274 @@
+//! Virtual file system layer.
+//!
+//! The inode table, path name lookup and the file system calls work with the inodes of any type
+//! of file system. A file system type implements SuperOps, which keeps inodes, and InodeOps,
+//! which keeps the contents of files and directories, and FileSystem::fs_type() tells the type
+//! of the file system on each device.
+//!
+//! The methods of InodeGuard below are what the rest of the kernel uses. They check their
+//! arguments and maintain the size and times of inodes, and leave the rest to the file system
+//! type of the inode.
+
+use crate::{
+    kernel::kernel,
+    stat::DirEntry,
+    vm::{UVAddr, VAddr},
+};
+
+use super::{
+    FileName, FsTransaction, Inode, InodeGuard, InodeInner, InodeTimes, InodeType, RcInode, DIRSIZ,
+};
+
+/// Operations on the inodes of a file system.
+pub trait SuperOps {
+    /// Fill in the fields of inode ip from the file system. Called when ip is locked and not
+    /// valid.
+    fn read_inode(&self, ip: &Inode, inner: &mut InodeInner);
+
+    /// Write the fields of inode ip back to the file system.
+    fn write_inode(&self, ip: &InodeGuard<'_>, tx: &FsTransaction<'_>);
+
+    /// Allocate an inode of type typ on device dev and return its number.
+    fn alloc_inode(&self, dev: u32, typ: InodeType, tx: &FsTransaction<'_>) -> Result<u32, ()>;
+
+    /// Free inode ip and its contents. Called when ip has no links and no other references.
+    fn free_inode(&self, ip: &mut InodeGuard<'_>, tx: &FsTransaction<'_>);
+
+    /// Make the file system on device dev ready to be mounted. Must not be called inside a
+    /// transaction.
+    fn mount(&self, dev: u32) -> Result<(), ()>;
+
+    /// Called once the file system on device dev is unmounted and no transaction is in
+    /// progress.
+    fn unmount(&self, dev: u32);
+
+    /// Wait until the updates to device dev made in the transaction group with sequence number
+    /// `seq`, and all before it, are durable. Must not be called inside a transaction.
+    fn sync(&self, dev: u32, seq: u64);
+
+    /// Is the file system read-only? Then no one, not even the superuser, may write to it.
+    fn read_only(&self) -> bool {
+        false
+    }
+}
+
+/// Operations on the contents of files and directories. Offsets of directory entries are
+/// chosen by the file system, and only need to stay valid while the directory is locked.
+pub trait InodeOps {
+    /// Look for name in directory dp. If found, return its inode and the offset of its entry.
+    fn lookup(
+        &self,
+        dp: &mut InodeGuard<'_>,
+        name: &FileName,
+    ) -> Result<(RcInode<'static>, u32), ()>;
+
+    /// Add the entry (name, inum) to directory dp, which has no entry for name. name is at most
+    /// DIRSIZ bytes long. The caller must write dp with update().
+    fn link(
+        &self,
+        dp: &mut InodeGuard<'_>,
+        name: &FileName,
+        inum: u32,
+        tx: &FsTransaction<'_>,
+    ) -> Result<(), ()>;
+
+    /// Make the entry of directory dp at offset off refer to inode inum, or remove it if inum
+    /// is 0. The caller must write dp with update().
+    fn replace(&self, dp: &mut InodeGuard<'_>, off: u32, inum: u32, tx: &FsTransaction<'_>);
+
+    /// Call f with the inode number and name of each entry of directory dp, starting with the
+    /// entry at offset `*off`, and advance `*off` past each entry f returns true for. Stops when
+    /// f returns false or fails.
+    fn readdir(
+        &self,
+        dp: &mut InodeGuard<'_>,
+        off: &mut u32,
+        f: &mut dyn FnMut(u32, &FileName) -> Result<bool, ()>,
+    ) -> Result<(), ()>;
+
+    /// Pass the bytes of file ip from offset off on to f in order, n of them or as many as lie
+    /// before the end of the file. Returns the number of bytes passed. Fails if f fails.
+    fn read(
+        &self,
+        ip: &mut InodeGuard<'_>,
+        off: u32,
+        n: u32,
+        f: &mut dyn FnMut(&[u8]) -> Result<(), ()>,
+    ) -> Result<usize, ()>;
+
+    /// Write n bytes to file ip at offset off, having f fill them in order. Returns the number
+    /// of bytes written, which is less than n if f fails or the file system is full. Does not
+    /// change the size of ip, and the caller must write ip with update(). Fails if the file
+    /// cannot reach off + n bytes.
+    fn write(
+        &self,
+        ip: &mut InodeGuard<'_>,
+        off: u32,
+        n: u32,
+        tx: &FsTransaction<'_>,
+        f: &mut dyn FnMut(&mut [u8]) -> Result<(), ()>,
+    ) -> Result<usize, ()>;
+
+    /// Prepare file ip to have size len: free what lies past len, and zero the rest of the
+    /// file's last block, so that growing the file again reads zeroes. Does not change the size
+    /// of ip, and the caller must write ip with update(). Fails if the file cannot reach len
+    /// bytes.
+    fn truncate(&self, ip: &mut InodeGuard<'_>, len: u32, tx: &FsTransaction<'_>)
+        -> Result<(), ()>;
+}
+
+/// A type of file system.
+pub trait FsType: SuperOps + InodeOps + Sync {}
+
+impl<T: SuperOps + InodeOps + Sync> FsType for T {}
+
+impl Inode {
+    /// The type of the file system the inode belongs to.
+    pub fn fs(&self) -> &'static dyn FsType {
+        kernel().file_system.fs_type(self.dev)
+    }
+}
+
+impl InodeGuard<'_> {
+    /// Copy a modified in-memory inode to the file system.
+    /// Must be called after every change to an ip->xxx field
+    /// that the file system keeps.
+    pub unsafe fn update(&self, tx: &FsTransaction<'_>) {
+        self.fs().write_inode(self, tx);
+    }
+
+    /// Look for a directory entry in a directory.
+    /// If found, return the entry and byte offset of entry.
+    pub fn dirlookup(&mut self, name: &FileName) -> Result<(RcInode<'static>, u32), ()> {
+        assert_eq!(self.deref_inner().typ, InodeType::Dir, "dirlookup not DIR");
+        self.fs().lookup(self, name)
+    }
+
+    /// Write a new directory entry (name, inum) into the directory dp.
+    pub fn dirlink(
+        &mut self,
+        name: &FileName,
+        inum: u32,
+        tx: &FsTransaction<'_>,
+    ) -> Result<(), ()> {
+        if name.as_bytes().len() > DIRSIZ {
+            return Err(());
+        }
+
+        // Check that name is not present.
+        if let Ok((_ip, _)) = self.dirlookup(name) {
+            return Err(());
+        };
+
+        self.fs().link(self, name, inum, tx)?;
+        self.touch(InodeTimes::MODIFY | InodeTimes::CHANGE);
+        unsafe { self.update(tx) };
+        Ok(())
+    }
+
+    /// Free the directory entry at offset `off`.
+    pub fn dirunlink(&mut self, off: u32, tx: &FsTransaction<'_>) {
+        self.dirreplace(off, 0, tx);
+    }
+
+    /// Make the directory entry at offset `off` refer to inode `inum`.
+    pub fn dirreplace(&mut self, off: u32, inum: u32, tx: &FsTransaction<'_>) {
+        self.fs().replace(self, off, inum, tx);
+        self.touch(InodeTimes::MODIFY | InodeTimes::CHANGE);
+        unsafe { self.update(tx) };
+    }
+
+    /// Copy the entries in use of the directory, starting with the entry at offset `*off`, to
+    /// user address dst as DirEntry records, as many as fit in n bytes, and advance `*off` past
+    /// them. Returns the number of bytes copied, which is 0 at the end of the directory.
+    /// Fails if the first entry does not fit.
+    pub fn getdents(&mut self, mut dst: UVAddr, off: &mut u32, n: usize) -> Result<usize, ()> {
+        let mut tot = 0;
+        self.fs().readdir(self, off, &mut |inum, name| {
+            let name = name.as_bytes();
+            let size = DirEntry::size(name.len());
+            if tot + size > n {
+                return if tot == 0 { Err(()) } else { Ok(false) };
+            }
+            unsafe { DirEntry::copyout(dst, inum, name)? };
+            dst = dst + size;
+            tot += size;
+            Ok(true)
+        })?;
+        Ok(tot)
+    }
+
+    /// Is the directory dp empty except for "." and ".." ?
+    pub unsafe fn isdirempty(&mut self) -> bool {
+        let mut empty = true;
+        let _ = self.fs().readdir(self, &mut 0, &mut |_, name| {
+            let name = name.as_bytes();
+            empty = name == b"." || name == b"..";
+            Ok(empty)
+        });
+        empty
+    }
+
+    /// Read data from inode.
+    pub fn read<A: VAddr>(&mut self, mut dst: A, off: u32, n: u32) -> Result<usize, ()> {
+        if off.wrapping_add(n) < off {
+            return Ok(0);
+        }
+        self.fs().read(self, off, n, &mut |data| {
+            unsafe { VAddr::copyout(dst, data)? };
+            dst = dst + data.len();
+            Ok(())
+        })
+    }
+
+    /// Write data to inode.
+    /// Returns the number of bytes successfully written.
+    /// If the return value is less than the requested n,
+    /// there was an error of some kind.
+    pub fn write<A: VAddr>(
+        &mut self,
+        mut src: A,
+        off: u32,
+        n: u32,
+        tx: &FsTransaction<'_>,
+    ) -> Result<usize, ()> {
+        // Writing past the end of the file leaves a hole.
+        if off.wrapping_add(n) < off {
+            return Err(());
+        }
+        let tot = self.fs().write(self, off, n, tx, &mut |data| {
+            unsafe { VAddr::copyin(data, src)? };
+            src = src.add(data.len());
+            Ok(())
+        })? as u32;
+
+        // TODO(@kimjungwow) : To pass copyin() usertest, I reflect the commit on Nov 5, 2020 (below link).
+        // https://github.com/mit-pdos/xv6-riscv/commit/5e392531c07966fd8a6bee50e3e357c553fb2a2f
+        // This comment will be removed as we fetch upstream(mit-pdos)
+        if tot > 0 && off + tot > self.deref_inner().size {
+            self.deref_inner_mut().size = off + tot;
+        }
+
+        if tot > 0 {
+            self.touch(InodeTimes::MODIFY | InodeTimes::CHANGE);
+        }
+
+        // Write the i-node back even if the size didn't change
+        // because the file system may have added blocks to it.
+        unsafe {
+            self.update(tx);
+        }
+        Ok(tot as usize)
+    }
+
+    /// Set the size of the file to len. If the file shrinks, its contents past its new end are
+    /// discarded. If it grows, the new part reads as zeroes. Fails if the file cannot be that
+    /// large.
+    pub unsafe fn truncate(&mut self, len: u32, tx: &FsTransaction<'_>) -> Result<(), ()> {
+        self.fs().truncate(self, len, tx)?;
+        self.deref_inner_mut().size = len;
+        self.touch(InodeTimes::MODIFY | InodeTimes::CHANGE);
+        self.update(tx);
+        Ok(())
+    }
+}
@@ -22,12 +22,20 @@ struct Run {
 
 pub struct Kmem {
     head: *mut Run,
+
+    /// Number of pages handed to the allocator by kinit().
+    total: usize,
+
+    /// Number of pages in the free list.
+    nfree: usize,
 }
 
 impl Kmem {
     pub const fn new() -> Self {
         Self {
             head: ptr::null_mut(),
+            total: 0,
+            nfree: 0,
         }
     }
 
@@ -35,12 +43,14 @@ impl Kmem {
         let mut r = pa.into_usize() as *mut Run;
         (*r).next = self.head;
         self.head = r;
+        self.nfree += 1;
     }
 
     pub unsafe fn freerange(&mut self, pa_start: *mut u8, pa_end: *mut u8) {
         let mut p = pgroundup(pa_start as _) as *mut u8;
         while p.add(PGSIZE) <= pa_end {
             self.free(Page::from_usize(p as _));
+            self.total += 1;
             p = p.add(PGSIZE);
         }
     }
@@ -50,8 +60,14 @@ impl Kmem {
             return None;
         }
         let next = (*self.head).next;
+        self.nfree -= 1;
         Some(Page::from_usize(mem::replace(&mut self.head, next) as _))
     }
+
+    /// Returns the total number of pages and the number of free pages.
+    pub fn meminfo(&self) -> (usize, usize) {
+        (self.total, self.nfree)
+    }
 }
 
 pub unsafe fn kinit(kmem: &mut Kmem) {
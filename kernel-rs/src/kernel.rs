@@ -7,15 +7,16 @@ use crate::{
     file::{Devsw, FileTable},
     fs::{FileSystem, Itable},
     kalloc::{end, kinit, Kmem},
-    memlayout::PHYSTOP,
+    memlayout::{virtio, PHYSTOP},
     page::{Page, RawPage},
-    param::{NCPU, NDEV},
+    param::{NCPU, NDEV, NDISK, ROOTDEV},
     plic::{plicinit, plicinithart},
     println,
     proc::{cpuid, procinit, scheduler, Cpu, ProcessSystem},
     riscv::PGSIZE,
     sleepablelock::Sleepablelock,
     spinlock::Spinlock,
+    time::Clock,
     trap::{trapinit, trapinithart},
     uart::Uart,
     virtio_disk::virtio_disk_init,
@@ -51,6 +52,9 @@ pub struct Kernel {
 
     pub ticks: Sleepablelock<u32>,
 
+    /// Wall-clock and monotonic time.
+    pub clock: Clock,
+
     /// Current process system.
     pub procs: ProcessSystem,
 
@@ -58,12 +62,12 @@ pub struct Kernel {
 
     pub bcache: Bcache,
 
-    /// Memory for virtio descriptors `&c` for queue 0.
+    /// Memory for virtio descriptors `&c` for queue 0 of each disk.
     ///
     /// This is a global instead of allocated because it must be multiple contiguous pages, which
     /// `kernel().alloc()` doesn't support, and page aligned.
     // TODO(efenniht): I moved out pages from Disk. Did I changed semantics (pointer indirection?)
-    virtqueue: [RawPage; 2],
+    virtqueue: [[RawPage; 2]; NDISK],
 
     pub devsw: [Devsw; NDEV],
 
@@ -84,10 +88,11 @@ impl Kernel {
             kmem: Spinlock::new("KMEM", Kmem::new()),
             page_table: PageTable::zero(),
             ticks: Sleepablelock::new("time", 0),
+            clock: Clock::zero(),
             procs: ProcessSystem::zero(),
             cpus: [Cpu::new(); NCPU],
             bcache: Bcache::zero(),
-            virtqueue: [RawPage::DEFAULT, RawPage::DEFAULT],
+            virtqueue: array![_ => [RawPage::DEFAULT, RawPage::DEFAULT]; NDISK],
             devsw: [Devsw {
                 read: None,
                 write: None,
@@ -134,6 +139,11 @@ impl Kernel {
         Some(page)
     }
 
+    /// Returns the total number of physical pages and the number of free pages.
+    pub fn meminfo(&self) -> (usize, usize) {
+        self.kmem.lock().meminfo()
+    }
+
     /// Prints the given formatted string with the Printer.
     pub fn printer_write_fmt(&self, args: fmt::Arguments<'_>) -> fmt::Result {
         if self.is_panicked() {
@@ -204,6 +214,9 @@ pub unsafe fn kernel_main() -> ! {
         // Turn on paging.
         kernel().page_table.kvminithart();
 
+        // Real-time clock.
+        KERNEL.clock.init();
+
         // Process system.
         procinit(&mut KERNEL.procs);
 
@@ -222,8 +235,14 @@ pub unsafe fn kernel_main() -> ! {
         // Buffer cache.
         KERNEL.bcache.get_mut().init();
 
-        // Emulated hard disk.
-        virtio_disk_init(&mut KERNEL.virtqueue, KERNEL.file_system.disk.get_mut());
+        // Emulated hard disks. Only the root disk must be there.
+        for (n, virtqueue) in KERNEL.virtqueue.iter_mut().enumerate() {
+            let found = virtio_disk_init(virtio(n), virtqueue, KERNEL.file_system.disk.get_mut(n));
+            assert!(
+                found.is_ok() || n as u32 + 1 != ROOTDEV,
+                "could not find virtio disk"
+            );
+        }
 
         // First user process.
         KERNEL.procs.user_proc_init();
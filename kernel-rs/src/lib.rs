@@ -37,6 +37,8 @@ mod plic;
 mod poweroff;
 mod proc;
 mod riscv;
+mod rtc;
+mod sandbox;
 mod sleepablelock;
 mod sleeplock;
 mod spinlock;
@@ -46,6 +48,7 @@ mod string;
 mod syscall;
 mod sysfile;
 mod sysproc;
+mod time;
 mod trap;
 mod uart;
 mod utils;
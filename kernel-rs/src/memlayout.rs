@@ -4,10 +4,13 @@
 //! based on qemu's hw/riscv/virt.c:
 //!
 //! 00001000 -- boot ROM, provided by qemu
+//! 00100000 -- SiFive test finisher
+//! 00101000 -- goldfish rtc
 //! 02000000 -- CLINT
 //! 0C000000 -- PLIC
 //! 10000000 -- uart0
-//! 10001000 -- virtio disk
+//! 10001000 -- virtio disk 0
+//! 10002000 -- virtio disk 1
 //! 80000000 -- boot ROM jumps here in machine mode
 //!             -kernel loads the kernel here
 //! unused RAM after 80000000.
@@ -20,6 +23,9 @@ use crate::riscv::{MAXVA, PGSIZE};
 /// SiFive Test Finisher. (virt device only)
 pub const FINISHER: usize = 0x100000;
 
+/// Goldfish real-time clock. (virt device only)
+pub const RTC: usize = 0x101000;
+
 /// qemu puts UART registers here in physical memory.
 pub const UART0: usize = 0x10000000;
 pub const UART0_IRQ: usize = 10;
@@ -28,8 +34,18 @@ pub const UART0_IRQ: usize = 10;
 pub const VIRTIO0: usize = 0x10001000;
 pub const VIRTIO0_IRQ: usize = 1;
 
+/// qemu has more virtio mmio interfaces, each a page after the previous one and interrupting
+/// with the next IRQ.
+pub const fn virtio(n: usize) -> usize {
+    VIRTIO0.wrapping_add(n.wrapping_mul(PGSIZE))
+}
+pub const fn virtio_irq(n: usize) -> usize {
+    VIRTIO0_IRQ.wrapping_add(n)
+}
+
 /// core local interruptor (CLINT), which contains the timer.
 pub const CLINT: usize = 0x2000000;
+pub const CLINT_SIZE: usize = 0x10000;
 pub const fn clint_mtimecmp(hartid: usize) -> usize {
     CLINT
         .wrapping_add(0x4000)
@@ -19,27 +19,68 @@ pub const NDEV: usize = 10;
 /// Device number of file system root disk.
 pub const ROOTDEV: u32 = 1;
 
+/// Number of virtio disks. Disk n is on virtio mmio interface n and has device number n + 1.
+pub const NDISK: usize = 2;
+
+/// Maximum number of file systems mounted besides the root.
+pub const NMOUNT: usize = 4;
+
+/// Device number of the tmpfs, which follows those of the disks.
+pub const TMPFSDEV: u32 = NDISK as u32 + 1;
+
+/// Device number of procfs.
+pub const PROCFSDEV: u32 = NDISK as u32 + 2;
+
+/// Maximum number of files and directories in the tmpfs.
+pub const NTMPINODE: usize = 200;
+
+/// Maximum number of pages of memory the tmpfs uses.
+pub const NTMPPAGE: usize = 1024;
+
 /// Max exec arguments.
 pub const MAXARG: usize = 32;
 
+/// Max nesting of #! interpreter scripts.
+pub const MAXINTERP: usize = 4;
+
 /// Block Size.
 pub const BSIZE: usize = 1024;
 
-/// Max # of blocks any FS op writes.
-/// Will be handled in #31.
+/// Blocks of log space reserved by an FS operation that does not reserve a specific number.
 pub const MAXOPBLOCKS: usize = 10;
 
-/// Max data blocks in on-disk log.
-pub const LOGSIZE: usize = MAXOPBLOCKS * 3;
+/// O_APPEND writes of at most this many bytes are atomic: no other write to the file lands in
+/// the middle of one.
+pub const MAXATOMICAPPEND: usize = 2 * BSIZE;
+
+/// Blocks in on-disk log, including the header. A log holds at most this many blocks.
+pub const LOGSIZE: usize = 128;
+
+/// Size of disk block cache if the superblock does not give one.
+///
+/// The log of each of the NDISK disks may pin nbuf / (NDISK + 1) buffers until it commits,
+/// whether or not a second disk is ever mounted, which leaves a share for the blocks that
+/// transactions read. So with 256 buffers a transaction may log at most 75 blocks, although the
+/// on-disk log holds 127. Raising NBUF, up to MAXNBUF, lets the logs use more of their size.
+pub const NBUF: usize = 256;
 
-/// Size of disk block cache.
-pub const NBUF: usize = MAXOPBLOCKS * 3;
+/// Maximum size of disk block cache.
+pub const MAXNBUF: usize = 512;
+
+/// Ticks between periodic commits of the file system log.
+pub const COMMIT_TICKS: u32 = 10;
 
 /// Size of file system in blocks.
-pub const FSSIZE: usize = 1000;
+pub const FSSIZE: usize = 10000;
 
 /// Maximum file path name.
-pub const MAXPATH: usize = 128;
+pub const MAXPATH: usize = 256;
+
+/// Maximum number of symbolic links followed in a path name lookup.
+pub const MAXSYMLINKS: usize = 10;
+
+/// Maximum number of path prefixes a sandboxed process may be confined to.
+pub const NSANDBOXPATH: usize = 4;
 
 /// Maximum length of process name.
 pub const MAXPROCNAME: usize = 16;
@@ -1,20 +1,24 @@
 //! the riscv Platform Level Interrupt Controller (PLIC).
 use crate::{
-    memlayout::{plic_sclaim, plic_senable, plic_spriority, PLIC, UART0_IRQ, VIRTIO0_IRQ},
+    memlayout::{plic_sclaim, plic_senable, plic_spriority, virtio_irq, PLIC, UART0_IRQ},
+    param::NDISK,
     proc::cpuid,
 };
 
 pub unsafe fn plicinit() {
     // set desired IRQ priorities non-zero (otherwise disabled).
     *((PLIC.wrapping_add(UART0_IRQ.wrapping_mul(4))) as *mut u32) = 1;
-    *((PLIC + VIRTIO0_IRQ * 4) as *mut u32) = 1;
+    for n in 0..NDISK {
+        *((PLIC + virtio_irq(n) * 4) as *mut u32) = 1;
+    }
 }
 
 pub unsafe fn plicinithart() {
     let hart: usize = cpuid();
 
-    // set uart's enable bit for this hart's S-mode.
-    *(plic_senable(hart) as *mut u32) = (1 << UART0_IRQ | 1 << VIRTIO0_IRQ) as u32;
+    // set uart's and disks' enable bits for this hart's S-mode.
+    let disks = (0..NDISK).fold(0, |bits, n| bits | 1 << virtio_irq(n));
+    *(plic_senable(hart) as *mut u32) = (1 << UART0_IRQ | disks) as u32;
 
     // set this hart's S-mode priority threshold to 0.
     *(plic_spriority(hart) as *mut u32) = 0;
@@ -19,6 +19,7 @@ use crate::{
     param::{MAXPROCNAME, NOFILE, NPROC, ROOTDEV},
     println,
     riscv::{intr_get, intr_on, r_tp, PGSIZE, PTE_R, PTE_W, PTE_X},
+    sandbox::Sandbox,
     sleepablelock::SleepablelockGuard,
     some_or,
     spinlock::{
@@ -314,6 +315,52 @@ pub struct ProcData {
 
     /// Current directory.
     pub cwd: Option<RcInode<'static>>,
+
+    /// Real user ID. 0 is the superuser.
+    pub uid: u32,
+
+    /// Real group ID.
+    pub gid: u32,
+
+    /// Effective user ID, used for permission checks.
+    /// Differs from uid while running a setuid executable.
+    pub euid: u32,
+
+    /// Effective group ID, used for permission checks.
+    pub egid: u32,
+
+    /// System calls and paths this process may use. Inherited by children.
+    pub sandbox: Sandbox,
+
+    /// Function run by a kernel thread and its argument, or None for a user process.
+    kthread: Option<(unsafe fn(usize) -> !, usize)>,
+}
+
+/// An open file of a process, as seen by procfs.
+#[derive(Copy, Clone)]
+pub struct FdStat {
+    /// Kind of the file: "pipe", "inode" or "device".
+    pub kind: &'static str,
+
+    /// Inode number, or 0 if the file is not backed by an inode.
+    pub inum: u32,
+}
+
+/// A snapshot of a process, as seen by procfs.
+pub struct ProcStat {
+    pub pid: i32,
+
+    /// Parent's pid, or 0 if the process has no parent.
+    pub ppid: i32,
+    pub state: Procstate,
+    pub name: [u8; MAXPROCNAME],
+
+    /// Size of process memory (bytes).
+    pub sz: usize,
+
+    /// Device and inode number of the current directory.
+    pub cwd: Option<(u32, u32)>,
+    pub open_files: [Option<FdStat>; NOFILE],
 }
 
 /// Per-process state.
@@ -436,7 +483,7 @@ impl Context {
 }
 
 impl Procstate {
-    fn to_str(&self) -> &'static str {
+    pub fn to_str(&self) -> &'static str {
         match self {
             Procstate::USED => "used",
             Procstate::UNUSED => "unused",
@@ -458,22 +505,14 @@ impl ProcData {
             context: Context::new(),
             open_files: [None; NOFILE],
             cwd: None,
+            uid: 0,
+            gid: 0,
+            euid: 0,
+            egid: 0,
+            sandbox: Sandbox::new(),
+            kthread: None,
         }
     }
-
-    /// Close all open files.
-    unsafe fn close_files(&mut self) {
-        for file in &mut self.open_files {
-            *file = None;
-        }
-        // TODO(rv6)
-        // If self.cwd is not None, the inode inside self.cwd will be dropped
-        // by assigning None to self.cwd. Deallocation of an inode may cause
-        // disk write operations, so we must begin a transaction here.
-        // https://github.com/kaist-cp/rv6/issues/290
-        let _tx = kernel().file_system.begin_transaction();
-        self.cwd = None;
-    }
 }
 
 /// TODO(@efenniht): pid, state, wakeup should be methods of ProcGuard.
@@ -519,6 +558,38 @@ impl Proc {
         self.killed.load(Ordering::Acquire)
     }
 
+    /// Make file descriptor fd refer to file, and return the file it referred to.
+    /// Only the process itself may change its open files and current directory, and it holds
+    /// p->lock while it does, so that ProcessSystem::stat() can read them.
+    pub unsafe fn set_open_file(
+        &self,
+        fd: usize,
+        file: Option<RcFile<'static>>,
+    ) -> Option<RcFile<'static>> {
+        let _guard = self.lock();
+        mem::replace(&mut (*self.data.get()).open_files[fd], file)
+    }
+
+    /// Make cwd the current directory, and return the old one.
+    pub unsafe fn set_cwd(&self, cwd: Option<RcInode<'static>>) -> Option<RcInode<'static>> {
+        let _guard = self.lock();
+        mem::replace(&mut (*self.data.get()).cwd, cwd)
+    }
+
+    /// Close all open files.
+    unsafe fn close_files(&self) {
+        for fd in 0..NOFILE {
+            let _ = self.set_open_file(fd, None);
+        }
+        // TODO(rv6)
+        // If cwd is not None, the inode inside cwd will be dropped
+        // by assigning None to cwd. Deallocation of an inode may cause
+        // disk write operations, so we must begin a transaction here.
+        // https://github.com/kaist-cp/rv6/issues/290
+        let _tx = kernel().file_system.begin_transaction();
+        let _ = self.set_cwd(None);
+    }
+
     /// Wake process from sleep().
     fn wakeup(&mut self) {
         if self.info.get_mut().state == Procstate::SLEEPING {
@@ -667,6 +738,25 @@ impl ProcessSystem {
         guard.deref_mut_info().state = Procstate::RUNNABLE;
     }
 
+    /// Start a kernel thread: a process that runs `entry(arg)` in the kernel and never returns to
+    /// user space.
+    pub unsafe fn spawn_kthread(
+        &self,
+        name: &[u8],
+        entry: unsafe fn(usize) -> !,
+        arg: usize,
+    ) -> Result<(), ()> {
+        let mut guard = self.alloc()?;
+        let data = &mut *guard.data.get();
+        data.kthread = Some((entry, arg));
+        data.context.ra = kthreadret as usize;
+        let len = cmp::min(name.len(), MAXPROCNAME - 1);
+        (*guard).name[..len].copy_from_slice(&name[..len]);
+        (*guard).name[len] = 0;
+        guard.deref_mut_info().state = Procstate::RUNNABLE;
+        Ok(())
+    }
+
     /// Create a new process, copying the parent.
     /// Sets up child kernel stack to return as if from fork() system call.
     pub unsafe fn fork(&self) -> i32 {
@@ -701,6 +791,11 @@ impl ProcessSystem {
             }
         }
         npdata.cwd = Some(pdata.cwd.clone().unwrap());
+        npdata.uid = pdata.uid;
+        npdata.gid = pdata.gid;
+        npdata.euid = pdata.euid;
+        npdata.egid = pdata.egid;
+        npdata.sandbox = pdata.sandbox;
 
         safestrcpy(
             (*np).name.as_mut_ptr(),
@@ -780,10 +875,9 @@ impl ProcessSystem {
     /// until its parent calls wait().
     pub unsafe fn exit_current(&self, status: i32) -> ! {
         let p = myproc();
-        let data = &mut *(*p).data.get();
         assert_ne!(p, self.initial_proc, "init exiting");
 
-        data.close_files();
+        (*p).close_files();
 
         // Give all children to init.
         let mut parent_guard = (*p).parent.assume_init_ref().lock();
@@ -810,6 +904,57 @@ impl ProcessSystem {
         unreachable!("zombie exit")
     }
 
+    /// Returns the pid of the `index`th live process in the process pool, or `None` if there are
+    /// fewer live processes.
+    pub fn nth_pid(&self, index: usize) -> Option<i32> {
+        self.process_pool
+            .iter()
+            .filter_map(|p| {
+                let guard = p.lock();
+                let info = guard.deref_info();
+                if info.state == Procstate::UNUSED {
+                    None
+                } else {
+                    Some(info.pid)
+                }
+            })
+            .nth(index)
+    }
+
+    /// Returns a snapshot of the process with the given pid, or `None` if there is no such
+    /// process.
+    /// The open files and current directory are read under p->lock, which the process holds
+    /// when it changes them. The size is read racily, so it may be stale.
+    pub unsafe fn stat(&self, pid: i32) -> Option<ProcStat> {
+        // Assumes that the process_pool has at least 1 element.
+        let mut parent_guard = self.process_pool[0].parent.assume_init_ref().lock();
+
+        for p in &self.process_pool {
+            let parent = *p.parent.assume_init_ref().get_mut(&mut parent_guard);
+            let guard = p.lock();
+            let info = guard.deref_info();
+            if info.state == Procstate::UNUSED || info.pid != pid {
+                continue;
+            }
+
+            let data = &*p.data.get();
+            let mut open_files = [None; NOFILE];
+            for (fd, file) in izip!(&mut open_files, &data.open_files) {
+                *fd = file.as_ref().map(|f| f.fdstat());
+            }
+            return Some(ProcStat {
+                pid,
+                ppid: if parent.is_null() { 0 } else { (*parent).pid() },
+                state: info.state,
+                name: p.name,
+                sz: data.sz,
+                cwd: data.cwd.as_ref().map(|ip| (ip.dev, ip.inum)),
+                open_files,
+            });
+        }
+        None
+    }
+
     /// Print a process listing to the console for debugging.
     /// Runs when user types ^P on console.
     /// Doesn't acquire locks in order to avoid wedging a stuck machine further.
@@ -1024,6 +1169,16 @@ pub unsafe fn proc_yield() {
     guard.sched();
 }
 
+/// A kernel thread's very first scheduling by scheduler()
+/// will swtch to kthreadret.
+unsafe fn kthreadret() {
+    // Still holding p->lock from scheduler.
+    (*myproc()).info.unlock();
+
+    let (entry, arg) = (*(*myproc()).data.get()).kthread.expect("kthreadret");
+    entry(arg);
+}
+
 /// A fork child's very first scheduling by scheduler()
 /// will swtch to forkret.
 unsafe fn forkret() {
@@ -1033,7 +1188,10 @@ unsafe fn forkret() {
     // File system initialization must be run in the context of a
     // regular process (e.g., because it calls sleep), and thus cannot
     // be run from main().
-    kernel().file_system.init(ROOTDEV);
+    kernel()
+        .file_system
+        .init(ROOTDEV)
+        .expect("invalid file system");
 
     usertrapret();
 }
@@ -0,0 +1,21 @@
+//! Driver for the Goldfish real-time clock of qemu's virt machine.
+//!
+//! https://android.googlesource.com/platform/external/qemu/+/master/docs/GOLDFISH-VIRTUAL-HARDWARE.TXT
+use crate::memlayout::RTC;
+use core::ptr;
+
+/// Low 32 bits of the time. Reading it latches the high bits into TIME_HIGH.
+const TIME_LOW: usize = 0x00;
+
+/// High 32 bits of the time.
+const TIME_HIGH: usize = 0x04;
+
+/// Returns the time in nanoseconds since the Unix epoch.
+pub fn read_ns() -> u64 {
+    // SAFETY: RTC is identically mapped from physical address by kvmmake().
+    unsafe {
+        let low = ptr::read_volatile((RTC + TIME_LOW) as *const u32);
+        let high = ptr::read_volatile((RTC + TIME_HIGH) as *const u32);
+        (high as u64) << 32 | low as u64
+    }
+}
@@ -0,0 +1,181 @@
+//! Process sandboxing.
+//!
+//! A process may irrevocably restrict the system calls that it and its descendants can invoke,
+//! and optionally the paths that namei may resolve for them. A sandbox can only ever be
+//! narrowed: a new restriction is intersected with the current one. A process which violates
+//! its sandbox is killed, and the offending system call is logged on the console.
+//!
+//! Path restrictions are checked on the path string, element by element. Since the kernel does
+//! not know the path of the current directory, a path-restricted process may only use absolute
+//! paths without ".." elements. For the same reason, namei checks the target of each symbolic
+//! link it follows, so such a process may only follow links whose targets are absolute and
+//! allowed; otherwise a link inside an allowed directory could lead anywhere.
+
+use core::str;
+
+use crate::{
+    fs::{FileName, Path},
+    param::{MAXPATH, NSANDBOXPATH},
+    println,
+    proc::{myproc, Proc},
+    some_or,
+};
+
+/// An absolute path prefix that a sandboxed process may look up.
+#[derive(Copy, Clone)]
+struct Prefix {
+    len: usize,
+    bytes: [u8; MAXPATH],
+}
+
+impl Prefix {
+    const fn new() -> Self {
+        Self {
+            len: 0,
+            bytes: [0; MAXPATH],
+        }
+    }
+
+    fn path(&self) -> &Path {
+        // SAFETY: `bytes[..len]` was copied from a `Path`, which contains no NUL characters.
+        unsafe { Path::from_bytes(&self.bytes[..self.len]) }
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct Sandbox {
+    /// Bit n is set if system call n may be invoked.
+    syscalls: u64,
+
+    /// If true, only paths under one of `paths[..npaths]` may be looked up.
+    restrict_paths: bool,
+
+    npaths: usize,
+    paths: [Prefix; NSANDBOXPATH],
+}
+
+impl Sandbox {
+    /// An unrestricted sandbox.
+    pub const fn new() -> Self {
+        Self {
+            syscalls: u64::MAX,
+            restrict_paths: false,
+            npaths: 0,
+            paths: [Prefix::new(); NSANDBOXPATH],
+        }
+    }
+
+    pub fn allows_syscall(&self, num: i32) -> bool {
+        if self.syscalls == u64::MAX {
+            return true;
+        }
+        (0..64).contains(&num) && self.syscalls & (1 << num) != 0
+    }
+
+    pub fn allows_path(&self, path: &Path) -> bool {
+        if !self.restrict_paths {
+            return true;
+        }
+        if !path.is_absolute() || has_dotdot(path) {
+            return false;
+        }
+        self.paths[..self.npaths]
+            .iter()
+            .any(|prefix| is_prefix(prefix.path(), path))
+    }
+
+    /// Allow only the system calls whose bits are set in both `syscalls` and the current mask.
+    pub fn restrict_syscalls(&mut self, syscalls: u64) {
+        self.syscalls &= syscalls;
+    }
+
+    /// Allow only paths under one of `prefixes`. Each prefix must be absolute and allowed by the
+    /// current sandbox, so that no previously forbidden path becomes reachable.
+    pub fn restrict_paths(&mut self, prefixes: &[&Path]) -> Result<(), ()> {
+        if prefixes.len() > NSANDBOXPATH {
+            return Err(());
+        }
+        for prefix in prefixes {
+            if !prefix.is_absolute() || has_dotdot(prefix) || !self.allows_path(prefix) {
+                return Err(());
+            }
+        }
+        for (dst, prefix) in self.paths.iter_mut().zip(prefixes) {
+            let bytes = prefix.as_bytes();
+            dst.len = bytes.len();
+            dst.bytes[..bytes.len()].copy_from_slice(bytes);
+        }
+        self.npaths = prefixes.len();
+        self.restrict_paths = true;
+        Ok(())
+    }
+}
+
+/// Check whether the current process may look up `path`. If not, the process is killed.
+pub unsafe fn check_path(path: &Path) -> Result<(), ()> {
+    let p = myproc();
+    if (*(*p).data.get()).sandbox.allows_path(path) {
+        return Ok(());
+    }
+    violation(p, path.as_bytes());
+    Err(())
+}
+
+/// Kill process `p` for invoking a forbidden system call, or looking up `path` if it is not empty.
+pub unsafe fn violation(p: *mut Proc, path: &[u8]) {
+    let num = (*(*(*p).data.get()).trapframe).a7;
+    let name = &(*p).name;
+    let length = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+    let name = str::from_utf8(&name[..length]).unwrap_or("???");
+    if path.is_empty() {
+        println!(
+            "{} {}: sandbox violation: syscall {}",
+            (*p).pid(),
+            name,
+            num
+        );
+    } else {
+        println!(
+            "{} {}: sandbox violation: syscall {} on path {}",
+            (*p).pid(),
+            name,
+            num,
+            str::from_utf8(path).unwrap_or("???")
+        );
+    }
+    (*p).kill();
+}
+
+/// Returns the next path element of `path` other than ".".
+fn next_elem(mut path: &Path) -> Option<(&Path, &FileName)> {
+    loop {
+        let (rest, name) = path.skipelem()?;
+        if name.as_bytes() != b"." {
+            return Some((rest, name));
+        }
+        path = rest;
+    }
+}
+
+fn has_dotdot(mut path: &Path) -> bool {
+    while let Some((rest, name)) = next_elem(path) {
+        if name.as_bytes() == b".." {
+            return true;
+        }
+        path = rest;
+    }
+    false
+}
+
+/// Returns true if the elements of `prefix` are the first elements of `path`.
+fn is_prefix(mut prefix: &Path, mut path: &Path) -> bool {
+    loop {
+        let (prefix_rest, name) = some_or!(next_elem(prefix), return true);
+        let (path_rest, path_name) = some_or!(next_elem(path), return false);
+        if name != path_name {
+            return false;
+        }
+        prefix = prefix_rest;
+        path = path_rest;
+    }
+}
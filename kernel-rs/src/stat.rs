@@ -1,4 +1,25 @@
-use crate::fs::InodeType;
+use core::{mem, slice};
+
+use crate::{
+    fs::InodeType,
+    vm::{UVAddr, VAddr},
+};
+
+/// Set-user-ID on execution.
+pub const S_ISUID: u32 = 0o4000;
+
+/// Set-group-ID on execution.
+pub const S_ISGID: u32 = 0o2000;
+
+/// Bits of an inode's mode which chmod() may change.
+pub const S_IALLUGO: u32 = S_ISUID | S_ISGID | 0o777;
+
+/// Default mode of newly created files, directories and devices.
+pub const DEFAULT_FILE_MODE: u32 = 0o644;
+pub const DEFAULT_DIR_MODE: u32 = 0o755;
+pub const DEFAULT_DEVICE_MODE: u32 = 0o666;
+pub const DEFAULT_SYMLINK_MODE: u32 = 0o777;
+
 #[derive(Copy, Clone)]
 #[repr(C)]
 pub struct Stat {
@@ -16,4 +37,73 @@ pub struct Stat {
 
     /// Size of file in bytes
     pub size: usize,
+
+    /// Permission bits
+    pub mode: u32,
+
+    /// Owner's user ID
+    pub uid: u32,
+
+    /// Owner's group ID
+    pub gid: u32,
+
+    /// Time of last access
+    pub atime: u32,
+
+    /// Time of last modification of the contents
+    pub mtime: u32,
+
+    /// Time of last change of the inode
+    pub ctime: u32,
+
+    /// Time of creation
+    pub crtime: u32,
+
+    /// Number of disk blocks allocated, which is less than the size suggests for a file with
+    /// holes
+    pub blocks: u32,
+}
+
+/// The fixed part of a directory entry returned by getdents(). Entries are packed one after
+/// another, and each is followed by its nul-terminated name and padding up to `reclen` bytes.
+/// Unlike Dirent, this format does not depend on how directories are stored.
+#[derive(Copy, Clone)]
+#[repr(C)]
+pub struct DirEntry {
+    /// Inode number
+    pub ino: u32,
+
+    /// Length of the entry, including the name and padding
+    pub reclen: u16,
+
+    /// Length of the name, not counting the nul
+    pub namelen: u16,
+}
+
+impl DirEntry {
+    /// Length of an entry whose name is `namelen` bytes long.
+    pub const fn size(namelen: usize) -> usize {
+        (mem::size_of::<Self>() + namelen + 1 + 3) & !3
+    }
+
+    /// Copy the entry for (ino, name) to user address dst.
+    pub unsafe fn copyout(dst: UVAddr, ino: u32, name: &[u8]) -> Result<(), ()> {
+        let size = Self::size(name.len());
+        let header = Self {
+            ino,
+            reclen: size as u16,
+            namelen: name.len() as u16,
+        };
+        let header_len = mem::size_of::<Self>();
+        VAddr::copyout(
+            dst,
+            slice::from_raw_parts(&header as *const Self as *const u8, header_len),
+        )?;
+        VAddr::copyout(dst + header_len, name)?;
+        // The nul and the padding.
+        VAddr::copyout(
+            dst + header_len + name.len(),
+            &[0; 4][..size - header_len - name.len()],
+        )
+    }
 }
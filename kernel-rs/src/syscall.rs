@@ -2,6 +2,7 @@ use crate::{
     kernel::Kernel,
     println,
     proc::{myproc, Proc},
+    sandbox,
     vm::{UVAddr, VAddr},
 };
 use core::{mem, slice, str};
@@ -78,6 +79,12 @@ impl Kernel {
         let mut data = &mut *(*p).data.get();
         let num: i32 = (*data.trapframe).a7 as i32;
 
+        if !data.sandbox.allows_syscall(num) {
+            sandbox::violation(p, &[]);
+            (*data.trapframe).a0 = usize::MAX;
+            return;
+        }
+
         let result = match num {
             1 => self.sys_fork(),
             2 => self.sys_exit(),
@@ -101,6 +108,32 @@ impl Kernel {
             20 => self.sys_mkdir(),
             21 => self.sys_close(),
             22 => self.sys_poweroff(),
+            23 => self.sys_getuid(),
+            24 => self.sys_setuid(),
+            25 => self.sys_chmod(),
+            26 => self.sys_chown(),
+            27 => self.sys_geteuid(),
+            28 => self.sys_getgid(),
+            29 => self.sys_setgid(),
+            30 => self.sys_sandbox(),
+            31 => self.sys_symlink(),
+            32 => self.sys_readlink(),
+            33 => self.sys_rename(),
+            34 => self.sys_getdents(),
+            35 => self.sys_rmdir(),
+            36 => self.sys_utimes(),
+            37 => self.sys_clock_gettime(),
+            38 => self.sys_gettimeofday(),
+            39 => self.sys_lseek(),
+            40 => self.sys_pread(),
+            41 => self.sys_pwrite(),
+            42 => self.sys_truncate(),
+            43 => self.sys_ftruncate(),
+            44 => self.sys_fsync(),
+            45 => self.sys_crash(),
+            46 => self.sys_fsck(),
+            47 => self.sys_mount(),
+            48 => self.sys_umount(),
             _ => {
                 println!(
                     "{} {}: unknown sys call {}",
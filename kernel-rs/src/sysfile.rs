@@ -6,32 +6,43 @@
 
 use crate::{
     fcntl::FcntlFlags,
-    file::{FileType, RcFile},
-    fs::{Dirent, FileName, FsTransaction, InodeGuard, InodeType, Path, RcInode, DIRENT_SIZE},
+    file::{FileType, RcFile, Whence},
+    fs::{
+        Access, FileName, FsTransaction, InodeGuard, InodeTimes, InodeType, Path, RcInode,
+        ALLOC_BLOCKS, DIRLINK_BLOCKS,
+    },
     kernel::{kernel, Kernel},
     ok_or,
     page::Page,
-    param::{MAXARG, MAXPATH, NDEV, NOFILE},
+    param::{MAXARG, MAXPATH, NDEV, NOFILE, ROOTDEV},
     pipe::AllocatedPipe,
     proc::{myproc, Proc},
     riscv::PGSIZE,
     some_or,
+    stat::{
+        DEFAULT_DEVICE_MODE, DEFAULT_DIR_MODE, DEFAULT_FILE_MODE, DEFAULT_SYMLINK_MODE, S_IALLUGO,
+        S_ISGID, S_ISUID,
+    },
     syscall::{argaddr, argint, argstr, fetchaddr, fetchstr},
     vm::{KVAddr, UVAddr, VAddr},
 };
 
-use core::{cell::UnsafeCell, mem, ptr, slice};
+use core::{
+    cell::UnsafeCell,
+    convert::{TryFrom, TryInto},
+    mem, ptr, slice,
+};
 
 impl RcFile<'static> {
     /// Allocate a file descriptor for the given file.
     /// Takes over file reference from caller on success.
     unsafe fn fdalloc(self) -> Result<i32, Self> {
         let p: *mut Proc = myproc();
-        let mut data = &mut *(*p).data.get();
+        let data = &*(*p).data.get();
         for fd in 0..NOFILE {
             // user pointer to struct stat
             if data.open_files[fd].is_none() {
-                data.open_files[fd] = Some(self);
+                let _ = (*p).set_open_file(fd, Some(self));
                 return Ok(fd as i32);
             }
         }
@@ -55,6 +66,13 @@ unsafe fn argfd(n: usize) -> Result<(i32, &'static RcFile<'static>), ()> {
     Ok((fd, f))
 }
 
+/// Most blocks that create() writes: the new inode, the first block of a new directory or
+/// symbolic link, the parent's inode and the blocks of dirlink().
+const CREATE_BLOCKS: usize = 1 + ALLOC_BLOCKS + 1 + DIRLINK_BLOCKS;
+
+/// Create the inode at path, or with type File, open an existing file or device there. f is
+/// called on the inode, before a new one is linked into its directory, and creation fails
+/// without leaving an entry behind if f does.
 unsafe fn create<F, T>(
     path: &Path,
     typ: InodeType,
@@ -62,7 +80,7 @@ unsafe fn create<F, T>(
     f: F,
 ) -> Result<(RcInode<'static>, T), ()>
 where
-    F: FnOnce(&mut InodeGuard<'_>) -> T,
+    F: FnOnce(&mut InodeGuard<'_>) -> Result<T, ()>,
 {
     let (ptr, name) = path.nameiparent()?;
     let mut dp = ptr.lock();
@@ -72,7 +90,7 @@ where
         if typ == InodeType::File {
             match ip.deref_inner().typ {
                 InodeType::File | InodeType::Device { .. } => {
-                    let ret = f(&mut ip);
+                    let ret = f(&mut ip)?;
                     mem::drop(ip);
                     return Ok((ptr2, ret));
                 }
@@ -81,28 +99,242 @@ where
         }
         return Err(());
     }
-    let ptr2 = kernel().itable.alloc_inode(dp.dev, typ, tx);
+    dp.permission(Access::WRITE | Access::EXEC)?;
+    let ptr2 = kernel().itable.alloc_inode(dp.dev, typ, tx)?;
     let mut ip = ptr2.lock();
-    ip.deref_inner_mut().nlink = 1;
+    let data = &*(*myproc()).data.get();
+    let inner = ip.deref_inner_mut();
+    inner.nlink = 1;
+    inner.uid = data.euid;
+    inner.gid = data.egid;
+    inner.mode = match typ {
+        InodeType::Dir => DEFAULT_DIR_MODE,
+        InodeType::Device { .. } => DEFAULT_DEVICE_MODE,
+        InodeType::Symlink => DEFAULT_SYMLINK_MODE,
+        _ => DEFAULT_FILE_MODE,
+    };
     ip.update(tx);
 
     // Create . and .. entries.
+    // No ip->nlink++ for ".": avoid cyclic ref count.
+    let ret = if typ == InodeType::Dir {
+        ip.dirlink(FileName::from_bytes(b"."), ip.inum, tx)
+            .and_then(|_| ip.dirlink(FileName::from_bytes(b".."), dp.inum, tx))
+            .and_then(|_| f(&mut ip))
+    } else {
+        f(&mut ip)
+    };
+    let ret = match ret.and_then(|ret| dp.dirlink(&name, ip.inum, tx).map(|_| ret)) {
+        Ok(ret) => ret,
+        Err(()) => {
+            // The file system is full, or f failed. Dropping the inode frees it.
+            ip.deref_inner_mut().nlink = 0;
+            ip.update(tx);
+            return Err(());
+        }
+    };
     if typ == InodeType::Dir {
         // for ".."
         dp.deref_inner_mut().nlink += 1;
         dp.update(tx);
-
-        // No ip->nlink++ for ".": avoid cyclic ref count.
-        ip.dirlink(FileName::from_bytes(b"."), ip.inum, tx)
-            .and_then(|_| ip.dirlink(FileName::from_bytes(b".."), dp.inum, tx))
-            .expect("create dots");
     }
-    dp.dirlink(&name, ip.inum, tx).expect("create: dirlink");
-    let ret = f(&mut ip);
     mem::drop(ip);
     Ok((ptr2, ret))
 }
 
+/// Returns true if the directory `dir` is `ancestor` or lies below it.
+/// Must be called inside a transaction since it calls Inode::put().
+unsafe fn is_ancestor(ancestor: &RcInode<'static>, dir: &RcInode<'static>) -> bool {
+    let mut ptr = dir.clone();
+    loop {
+        if ptr.dev == ancestor.dev && ptr.inum == ancestor.inum {
+            return true;
+        }
+        let mut ip = ptr.lock();
+        let parent = ok_or!(ip.dirlookup(FileName::from_bytes(b"..")), return false).0;
+        drop(ip);
+        // The root is its own parent.
+        if parent.inum == ptr.inum {
+            return false;
+        }
+        ptr = parent;
+    }
+}
+
+/// The locked parent directories of rename's old and new paths.
+struct RenameDirs<'a> {
+    old: InodeGuard<'a>,
+    /// `None` if it is the same directory as `old`.
+    new: Option<InodeGuard<'a>>,
+}
+
+impl<'a> RenameDirs<'a> {
+    fn new_dir(&mut self) -> &mut InodeGuard<'a> {
+        match &mut self.new {
+            Some(dp) => dp,
+            None => &mut self.old,
+        }
+    }
+}
+
+/// Most blocks that rename() writes: the blocks of dirlink(), which are more than those of
+/// replacing the target's entry, the target's inode and the new directory's inode, and the
+/// block of the old entry, the block of a moved directory's "..", the moved directory's inode
+/// and the old directory's inode.
+const RENAME_BLOCKS: usize = DIRLINK_BLOCKS + 4;
+
+unsafe fn rename(old: &Path, new: &Path, tx: &FsTransaction<'_>) -> Result<(), ()> {
+    let (optr, oname) = old.nameiparent()?;
+    let (nptr, nname) = new.nameiparent()?;
+    for name in &[oname, nname] {
+        if name.as_bytes() == b"." || name.as_bytes() == b".." {
+            return Err(());
+        }
+    }
+    if optr.dev != nptr.dev {
+        return Err(());
+    }
+
+    // Since no directory moves while we hold the rename lock, we can find out how the inodes
+    // involved are related before locking them.
+    let _rename_lock = kernel().file_system.rename_lock.lock();
+    let ptr = {
+        let mut dp = optr.lock();
+        dp.permission(Access::WRITE | Access::EXEC)?;
+        dp.dirlookup(oname)?.0
+    };
+    let target = {
+        let mut dp = nptr.lock();
+        dp.permission(Access::WRITE | Access::EXEC)?;
+        dp.dirlookup(nname).ok().map(|(ip, _)| ip)
+    };
+    if let Some(target) = &target {
+        // Both names refer to the same file.
+        if target.inum == ptr.inum {
+            return Ok(());
+        }
+        // A file system is mounted on the target.
+        if kernel().file_system.is_mount_point(target) {
+            return Err(());
+        }
+        // A directory above old is not empty, so it cannot be replaced.
+        if is_ancestor(target, &optr) {
+            return Err(());
+        }
+    }
+    let is_dir = ptr.lock().deref_inner().typ == InodeType::Dir;
+    if is_dir && is_ancestor(&ptr, &nptr) {
+        // Cannot move a directory below itself.
+        return Err(());
+    }
+
+    // Lock the parents, an ancestor before its descendant as everyone else does.
+    let same = optr.inum == nptr.inum;
+    let mut dirs = if same {
+        RenameDirs {
+            old: optr.lock(),
+            new: None,
+        }
+    } else if is_ancestor(&optr, &nptr) {
+        let old = optr.lock();
+        RenameDirs {
+            old,
+            new: Some(nptr.lock()),
+        }
+    } else {
+        let new = nptr.lock();
+        RenameDirs {
+            old: optr.lock(),
+            new: Some(new),
+        }
+    };
+
+    // The directories may have changed before they were locked.
+    let ptr2 = dirs.old.dirlookup(oname)?.0;
+    let target2 = dirs.new_dir().dirlookup(nname).ok();
+    if ptr2.inum != ptr.inum
+        || target2.as_ref().map(|(ip, _)| ip.inum) != target.as_ref().map(|ip| ip.inum)
+    {
+        return Err(());
+    }
+
+    let mut ip = ptr.lock();
+    if is_dir && !same {
+        // Moving a directory rewrites its "..".
+        ip.permission(Access::WRITE)?;
+    }
+    if let Some((tptr, toff)) = &target2 {
+        let mut tip = tptr.lock();
+        let target_is_dir = tip.deref_inner().typ == InodeType::Dir;
+        if target_is_dir != is_dir || (target_is_dir && !tip.isdirempty()) {
+            return Err(());
+        }
+        dirs.new_dir().dirreplace(*toff, ip.inum, tx);
+        tip.deref_inner_mut().nlink -= 1;
+        tip.update(tx);
+        if target_is_dir {
+            // For the target's "..".
+            dirs.new_dir().deref_inner_mut().nlink -= 1;
+        }
+    } else {
+        dirs.new_dir().dirlink(nname, ip.inum, tx)?;
+    }
+
+    // Adding the new name may have moved entries of the same directory.
+    let (_, off) = dirs.old.dirlookup(oname).expect("rename: old name");
+    dirs.old.dirunlink(off, tx);
+
+    if is_dir && !same {
+        let (_, off) = ip
+            .dirlookup(FileName::from_bytes(b".."))
+            .expect("rename: no ..");
+        ip.dirreplace(off, nptr.inum, tx);
+        dirs.old.deref_inner_mut().nlink -= 1;
+        dirs.new_dir().deref_inner_mut().nlink += 1;
+    }
+    dirs.old.update(tx);
+    if let Some(dp) = &dirs.new {
+        dp.update(tx);
+    }
+    Ok(())
+}
+
+/// Remove the entry of path from its directory. A directory can only be removed if it is empty.
+/// If dir_only is true, fails unless path is a directory.
+unsafe fn remove(path: &Path, dir_only: bool, tx: &FsTransaction<'_>) -> Result<(), ()> {
+    let (ptr, name) = path.nameiparent()?;
+    let mut dp = ptr.lock();
+    dp.permission(Access::WRITE | Access::EXEC)?;
+
+    // Cannot unlink "." or "..".
+    if name.as_bytes() == b"." || name.as_bytes() == b".." {
+        return Err(());
+    }
+    let (ptr2, off) = dp.dirlookup(&name)?;
+    // A file system is mounted on it.
+    if kernel().file_system.is_mount_point(&ptr2) {
+        return Err(());
+    }
+    let mut ip = ptr2.lock();
+    assert!(ip.deref_inner().nlink >= 1, "unlink: nlink < 1");
+
+    let is_dir = ip.deref_inner().typ == InodeType::Dir;
+    if (dir_only && !is_dir) || (is_dir && !ip.isdirempty()) {
+        return Err(());
+    }
+    dp.dirunlink(off, tx);
+    if is_dir {
+        dp.deref_inner_mut().nlink -= 1;
+        dp.update(tx);
+    }
+    drop(dp);
+    drop(ptr);
+    ip.deref_inner_mut().nlink -= 1;
+    ip.touch(InodeTimes::CHANGE);
+    ip.update(tx);
+    Ok(())
+}
+
 impl Kernel {
     pub unsafe fn sys_dup(&self) -> usize {
         let (_, f) = ok_or!(argfd(0), return usize::MAX);
@@ -119,6 +351,35 @@ impl Kernel {
         ok_or!(f.read(UVAddr::new(p), n), usize::MAX)
     }
 
+    /// Read at an offset given as the fourth argument, leaving the file offset alone.
+    pub unsafe fn sys_pread(&self) -> usize {
+        let (_, f) = ok_or!(argfd(0), return usize::MAX);
+        let n = ok_or!(argint(2), return usize::MAX);
+        let p = ok_or!(argaddr(1), return usize::MAX);
+        let off = ok_or!(argint(3), return usize::MAX);
+        let off = ok_or!(u32::try_from(off), return usize::MAX);
+        ok_or!(f.pread(UVAddr::new(p), n, off), usize::MAX)
+    }
+
+    /// Write at an offset given as the fourth argument, leaving the file offset alone.
+    pub unsafe fn sys_pwrite(&self) -> usize {
+        let (_, f) = ok_or!(argfd(0), return usize::MAX);
+        let n = ok_or!(argint(2), return usize::MAX);
+        let p = ok_or!(argaddr(1), return usize::MAX);
+        let off = ok_or!(argint(3), return usize::MAX);
+        let off = ok_or!(u32::try_from(off), return usize::MAX);
+        ok_or!(f.pwrite(UVAddr::new(p), n, off), usize::MAX)
+    }
+
+    /// Reposition the offset of a file, and return the new offset.
+    pub unsafe fn sys_lseek(&self) -> usize {
+        let (_, f) = ok_or!(argfd(0), return usize::MAX);
+        let off = ok_or!(argint(1), return usize::MAX);
+        let whence = ok_or!(argint(2), return usize::MAX);
+        let whence = some_or!(Whence::from_i32(whence), return usize::MAX);
+        ok_or!(f.lseek(off, whence), usize::MAX)
+    }
+
     pub unsafe fn sys_write(&self) -> usize {
         let (_, f) = ok_or!(argfd(0), return usize::MAX);
         let n = ok_or!(argint(2), return usize::MAX);
@@ -128,7 +389,7 @@ impl Kernel {
 
     pub unsafe fn sys_close(&self) -> usize {
         let (fd, _) = ok_or!(argfd(0), return usize::MAX);
-        (*(*myproc()).data.get()).open_files[fd as usize] = None;
+        let _ = (*myproc()).set_open_file(fd as usize, None);
         0
     }
 
@@ -141,24 +402,31 @@ impl Kernel {
     }
 
     /// Create the path new as a link to the same inode as old.
+    /// If old is a symbolic link, new is linked to the symbolic link itself.
     pub unsafe fn sys_link(&self) -> usize {
         let mut new: [u8; MAXPATH as usize] = [0; MAXPATH];
         let mut old: [u8; MAXPATH as usize] = [0; MAXPATH];
         let old = ok_or!(argstr(0, &mut old), return usize::MAX);
         let new = ok_or!(argstr(1, &mut new), return usize::MAX);
-        let tx = self.file_system.begin_transaction();
-        let ptr = ok_or!(Path::new(old).namei(), return usize::MAX);
+        let tx = self
+            .file_system
+            .begin_transaction_reserving(1 + DIRLINK_BLOCKS);
+        let ptr = ok_or!(Path::new(old).namei_nofollow(), return usize::MAX);
         let mut ip = ptr.lock();
         if ip.deref_inner().typ == InodeType::Dir {
             return usize::MAX;
         }
         ip.deref_inner_mut().nlink += 1;
+        ip.touch(InodeTimes::CHANGE);
         ip.update(&tx);
         drop(ip);
 
         if let Ok((ptr2, name)) = Path::new(new).nameiparent() {
             let mut dp = ptr2.lock();
-            if dp.dev != ptr.dev || dp.dirlink(name, ptr.inum, &tx).is_err() {
+            if dp.dev != ptr.dev
+                || dp.permission(Access::WRITE | Access::EXEC).is_err()
+                || dp.dirlink(name, ptr.inum, &tx).is_err()
+            {
             } else {
                 return 0;
             }
@@ -170,43 +438,46 @@ impl Kernel {
         usize::MAX
     }
 
+    /// Rename old to new in a single transaction, replacing new if it exists.
+    /// A directory may be moved to another directory, but not below itself, and may only
+    /// replace an empty directory.
+    pub unsafe fn sys_rename(&self) -> usize {
+        let mut old: [u8; MAXPATH] = [0; MAXPATH];
+        let mut new: [u8; MAXPATH] = [0; MAXPATH];
+        let old = ok_or!(argstr(0, &mut old), return usize::MAX);
+        let new = ok_or!(argstr(1, &mut new), return usize::MAX);
+        let tx = self.file_system.begin_transaction_reserving(RENAME_BLOCKS);
+        ok_or!(
+            rename(Path::new(old), Path::new(new), &tx),
+            return usize::MAX
+        );
+        0
+    }
+
     pub unsafe fn sys_unlink(&self) -> usize {
-        let mut de: Dirent = Default::default();
         let mut path: [u8; MAXPATH] = [0; MAXPATH];
         let path = ok_or!(argstr(0, &mut path), return usize::MAX);
         let tx = self.file_system.begin_transaction();
-        let (ptr, name) = ok_or!(Path::new(path).nameiparent(), return usize::MAX);
-        let mut dp = ptr.lock();
-
-        // Cannot unlink "." or "..".
-        if !(name.as_bytes() == b"." || name.as_bytes() == b"..") {
-            // TODO: use other Result related functions
-            if let Ok((ptr2, off)) = dp.dirlookup(&name) {
-                let mut ip = ptr2.lock();
-                assert!(ip.deref_inner().nlink >= 1, "unlink: nlink < 1");
-
-                if ip.deref_inner().typ != InodeType::Dir || ip.isdirempty() {
-                    let bytes_write = dp.write(
-                        KVAddr::new(&mut de as *mut Dirent as usize),
-                        off,
-                        DIRENT_SIZE as u32,
-                        &tx,
-                    );
-                    assert_eq!(bytes_write, Ok(DIRENT_SIZE), "unlink: writei");
-                    if ip.deref_inner().typ == InodeType::Dir {
-                        dp.deref_inner_mut().nlink -= 1;
-                        dp.update(&tx);
-                    }
-                    drop(dp);
-                    drop(ptr);
-                    ip.deref_inner_mut().nlink -= 1;
-                    ip.update(&tx);
-                    return 0;
-                }
-            }
-        }
+        ok_or!(remove(Path::new(path), false, &tx), return usize::MAX);
+        0
+    }
 
-        usize::MAX
+    /// Remove an empty directory. Unlike unlink, fails if path is not a directory.
+    pub unsafe fn sys_rmdir(&self) -> usize {
+        let mut path: [u8; MAXPATH] = [0; MAXPATH];
+        let path = ok_or!(argstr(0, &mut path), return usize::MAX);
+        let tx = self.file_system.begin_transaction();
+        ok_or!(remove(Path::new(path), true, &tx), return usize::MAX);
+        0
+    }
+
+    /// Read entries of the directory fd into buf as DirEntry records.
+    /// Returns the number of bytes read, or 0 at the end of the directory.
+    pub unsafe fn sys_getdents(&self) -> usize {
+        let (_, f) = ok_or!(argfd(0), return usize::MAX);
+        let n = ok_or!(argint(2), return usize::MAX);
+        let p = ok_or!(argaddr(1), return usize::MAX);
+        ok_or!(f.getdents(UVAddr::new(p), n), usize::MAX)
     }
 
     pub unsafe fn sys_open(&'static self) -> usize {
@@ -216,21 +487,53 @@ impl Kernel {
         let omode = ok_or!(argint(1), return usize::MAX);
         let omode = FcntlFlags::from_bits_truncate(omode);
 
-        let tx = self.file_system.begin_transaction();
+        let tx = if omode.contains(FcntlFlags::O_CREATE) {
+            self.file_system.begin_transaction_reserving(CREATE_BLOCKS)
+        } else {
+            self.file_system.begin_transaction()
+        };
 
-        let (ip, typ) = if omode.contains(FcntlFlags::O_CREATE) {
-            ok_or!(
-                create(path, InodeType::File, &tx, |ip| ip.deref_inner().typ),
+        let readable = !omode.intersects(FcntlFlags::O_WRONLY);
+        let writable = omode.intersects(FcntlFlags::O_WRONLY | FcntlFlags::O_RDWR);
+        let mut access = Access::empty();
+        if readable {
+            access |= Access::READ;
+        }
+        if writable || omode.contains(FcntlFlags::O_TRUNC) {
+            access |= Access::WRITE;
+        }
+
+        // With O_NOFOLLOW, opening a symbolic link fails. Otherwise, an existing file is opened
+        // through a symbolic link, but O_CREATE does not create the target of a dangling one.
+        let follow = !omode.contains(FcntlFlags::O_NOFOLLOW);
+        let (ip, typ) = if omode.contains(FcntlFlags::O_CREATE) && !(follow && path.namei().is_ok())
+        {
+            let (ip, (typ, permitted)) = ok_or!(
+                create(path, InodeType::File, &tx, |ip| Ok((
+                    ip.deref_inner().typ,
+                    ip.permission(access)
+                ))),
                 return usize::MAX
-            )
+            );
+            ok_or!(permitted, return usize::MAX);
+            (ip, typ)
         } else {
-            let ptr = ok_or!(path.namei(), return usize::MAX);
+            let ptr = if follow {
+                path.namei()
+            } else {
+                path.namei_nofollow()
+            };
+            let ptr = ok_or!(ptr, return usize::MAX);
             let ip = ptr.lock();
             let typ = ip.deref_inner().typ;
 
-            if typ == InodeType::Dir && omode != FcntlFlags::O_RDONLY {
+            if typ == InodeType::Symlink
+                || (typ == InodeType::Dir
+                    && omode & !FcntlFlags::O_NOFOLLOW != FcntlFlags::O_RDONLY)
+            {
                 return usize::MAX;
             }
+            ok_or!(ip.permission(access), return usize::MAX);
             mem::drop(ip);
             (ptr, typ)
         };
@@ -245,21 +548,23 @@ impl Kernel {
             _ => FileType::Inode {
                 ip,
                 off: UnsafeCell::new(0),
+                append: omode.contains(FcntlFlags::O_APPEND),
             },
         };
 
         let f = some_or!(
-            self.ftable.alloc_file(
-                filetype,
-                !omode.intersects(FcntlFlags::O_WRONLY),
-                omode.intersects(FcntlFlags::O_WRONLY | FcntlFlags::O_RDWR)
-            ),
+            self.ftable.alloc_file(filetype, readable, writable),
             return usize::MAX
         );
 
+        // If fdalloc() fails, dropping f begins a transaction of its own.
+        drop(tx);
+        // A large file is freed in several transactions.
         if omode.contains(FcntlFlags::O_TRUNC) && typ == InodeType::File {
             match &f.typ {
-                FileType::Device { ip, .. } | FileType::Inode { ip, .. } => ip.lock().itrunc(&tx),
+                FileType::Device { ip, .. } | FileType::Inode { ip, .. } => {
+                    ip.truncate(0).expect("sys_open: truncate")
+                }
                 _ => panic!("sys_open : Not reach"),
             };
         }
@@ -269,10 +574,10 @@ impl Kernel {
 
     pub unsafe fn sys_mkdir(&self) -> usize {
         let mut path: [u8; MAXPATH] = [0; MAXPATH];
-        let tx = self.file_system.begin_transaction();
+        let tx = self.file_system.begin_transaction_reserving(CREATE_BLOCKS);
         let path = ok_or!(argstr(0, &mut path), return usize::MAX);
         ok_or!(
-            create(Path::new(path), InodeType::Dir, &tx, |_| ()),
+            create(Path::new(path), InodeType::Dir, &tx, |_| Ok(())),
             return usize::MAX
         );
         0
@@ -283,23 +588,71 @@ impl Kernel {
         let path = ok_or!(argstr(0, &mut path), return usize::MAX);
         let major = ok_or!(argint(1), return usize::MAX) as u16;
         let minor = ok_or!(argint(2), return usize::MAX) as u16;
-        let tx = self.file_system.begin_transaction();
+        let tx = self.file_system.begin_transaction_reserving(CREATE_BLOCKS);
         let _ip = ok_or!(
             create(
                 Path::new(path),
                 InodeType::Device { major, minor },
                 &tx,
-                |_| ()
+                |_| Ok(())
             ),
             return usize::MAX
         );
         0
     }
 
+    /// Create the path as a symbolic link to target, which need not exist.
+    pub unsafe fn sys_symlink(&self) -> usize {
+        let mut target: [u8; MAXPATH] = [0; MAXPATH];
+        let mut path: [u8; MAXPATH] = [0; MAXPATH];
+        let target = ok_or!(argstr(0, &mut target), return usize::MAX).to_bytes();
+        let path = ok_or!(argstr(1, &mut path), return usize::MAX);
+        if target.is_empty() {
+            return usize::MAX;
+        }
+        let tx = self.file_system.begin_transaction_reserving(CREATE_BLOCKS);
+        // The target is written before the link is made, so that a failed write leaves no
+        // empty or truncated symbolic link behind.
+        let _ip = ok_or!(
+            create(Path::new(path), InodeType::Symlink, &tx, |ip| {
+                let written = ip.write(
+                    KVAddr::new(target.as_ptr() as usize),
+                    0,
+                    target.len() as u32,
+                    &tx,
+                )?;
+                if written == target.len() {
+                    Ok(())
+                } else {
+                    Err(())
+                }
+            }),
+            return usize::MAX
+        );
+        0
+    }
+
+    /// Copy the target of the symbolic link path into buf, which holds n bytes.
+    /// Returns the number of bytes copied; the target is not nul-terminated.
+    pub unsafe fn sys_readlink(&self) -> usize {
+        let mut path: [u8; MAXPATH] = [0; MAXPATH];
+        let path = ok_or!(argstr(0, &mut path), return usize::MAX);
+        let buf = ok_or!(argaddr(1), return usize::MAX);
+        let n = ok_or!(argint(2), return usize::MAX);
+        if n < 0 {
+            return usize::MAX;
+        }
+        let _tx = self.file_system.begin_transaction();
+        let ptr = ok_or!(Path::new(path).namei_nofollow(), return usize::MAX);
+        let mut ip = ptr.lock();
+        if ip.deref_inner().typ != InodeType::Symlink {
+            return usize::MAX;
+        }
+        ok_or!(ip.read(UVAddr::new(buf), 0, n as u32), usize::MAX)
+    }
+
     pub unsafe fn sys_chdir(&self) -> usize {
         let mut path: [u8; MAXPATH] = [0; MAXPATH];
-        let p: *mut Proc = myproc();
-        let mut data = &mut *(*p).data.get();
         let path = ok_or!(argstr(0, &mut path), return usize::MAX);
         // TODO(rv6)
         // The method namei can drop inodes. If namei succeeds, its return
@@ -310,11 +663,11 @@ impl Kernel {
         let _tx = self.file_system.begin_transaction();
         let ptr = ok_or!(Path::new(path).namei(), return usize::MAX);
         let ip = ptr.lock();
-        if ip.deref_inner().typ != InodeType::Dir {
+        if ip.deref_inner().typ != InodeType::Dir || ip.permission(Access::EXEC).is_err() {
             return usize::MAX;
         }
         mem::drop(ip);
-        data.cwd = Some(ptr);
+        let _ = (*myproc()).set_cwd(Some(ptr));
         0
     }
 
@@ -365,16 +718,186 @@ impl Kernel {
         ret
     }
 
+    /// Change the permission bits of a file.
+    /// Only the owner of the file or the superuser may do this.
+    pub unsafe fn sys_chmod(&self) -> usize {
+        let mut path: [u8; MAXPATH] = [0; MAXPATH];
+        let path = ok_or!(argstr(0, &mut path), return usize::MAX);
+        let mode = ok_or!(argint(1), return usize::MAX) as u32;
+        let uid = (*(*myproc()).data.get()).euid;
+        let tx = self.file_system.begin_transaction();
+        let ptr = ok_or!(Path::new(path).namei(), return usize::MAX);
+        let mut ip = ptr.lock();
+        if (uid != 0 && uid != ip.deref_inner().uid) || ip.fs().read_only() {
+            return usize::MAX;
+        }
+        ip.deref_inner_mut().mode = mode & S_IALLUGO;
+        ip.touch(InodeTimes::CHANGE);
+        ip.update(&tx);
+        0
+    }
+
+    /// Change the owner and group of a file.
+    /// Only the superuser may do this. The setuid and setgid bits are cleared.
+    pub unsafe fn sys_chown(&self) -> usize {
+        let mut path: [u8; MAXPATH] = [0; MAXPATH];
+        let path = ok_or!(argstr(0, &mut path), return usize::MAX);
+        let owner = ok_or!(argint(1), return usize::MAX) as u32;
+        let group = ok_or!(argint(2), return usize::MAX) as u32;
+        if (*(*myproc()).data.get()).euid != 0 {
+            return usize::MAX;
+        }
+        let tx = self.file_system.begin_transaction();
+        let ptr = ok_or!(Path::new(path).namei(), return usize::MAX);
+        let mut ip = ptr.lock();
+        if ip.fs().read_only() {
+            return usize::MAX;
+        }
+        ip.deref_inner_mut().uid = owner;
+        ip.deref_inner_mut().gid = group;
+        ip.deref_inner_mut().mode &= !(S_ISUID | S_ISGID);
+        ip.touch(InodeTimes::CHANGE);
+        ip.update(&tx);
+        0
+    }
+
+    /// Set the size of a regular file to len, freeing the blocks past it or leaving a hole.
+    pub unsafe fn sys_truncate(&self) -> usize {
+        let mut path: [u8; MAXPATH] = [0; MAXPATH];
+        let path = ok_or!(argstr(0, &mut path), return usize::MAX);
+        let len = ok_or!(argint(1), return usize::MAX);
+        let len = ok_or!(u32::try_from(len), return usize::MAX);
+        let ptr = {
+            let _tx = self.file_system.begin_transaction();
+            let ptr = ok_or!(Path::new(path).namei(), return usize::MAX);
+            ok_or!(ptr.lock().permission(Access::WRITE), return usize::MAX);
+            ptr
+        };
+        let ret = ptr.truncate(len);
+        // Dropping the inode may free it.
+        let _tx = self.file_system.begin_transaction();
+        drop(ptr);
+        ok_or!(ret, return usize::MAX);
+        0
+    }
+
+    /// ftruncate() for a file descriptor open for writing.
+    pub unsafe fn sys_ftruncate(&self) -> usize {
+        let (_, f) = ok_or!(argfd(0), return usize::MAX);
+        let len = ok_or!(argint(1), return usize::MAX);
+        let len = ok_or!(u32::try_from(len), return usize::MAX);
+        ok_or!(f.truncate(len), return usize::MAX);
+        0
+    }
+
+    /// Wait until the updates to an open file are on disk.
+    pub unsafe fn sys_fsync(&self) -> usize {
+        let (_, f) = ok_or!(argfd(0), return usize::MAX);
+        ok_or!(f.fsync(), return usize::MAX);
+        0
+    }
+
+    /// Check the root file system, printing each problem found on the console, and return the
+    /// number of problems. Only the superuser may do this, since no other file system call can
+    /// run during the check.
+    pub unsafe fn sys_fsck(&self) -> usize {
+        if (*(*myproc()).data.get()).euid != 0 {
+            return usize::MAX;
+        }
+        self.file_system.check(ROOTDEV).unwrap_or(usize::MAX)
+    }
+
+    /// Mount the file system of device dev, a disk, TMPFSDEV or PROCFSDEV, on the directory path.
+    /// Only the superuser may do this.
+    pub unsafe fn sys_mount(&self) -> usize {
+        let mut path: [u8; MAXPATH] = [0; MAXPATH];
+        let dev = ok_or!(argint(0), return usize::MAX);
+        let dev = ok_or!(u32::try_from(dev), return usize::MAX);
+        let path = ok_or!(argstr(1, &mut path), return usize::MAX);
+        if (*(*myproc()).data.get()).euid != 0 {
+            return usize::MAX;
+        }
+        let point = {
+            let _tx = self.file_system.begin_transaction();
+            let ptr = ok_or!(Path::new(path).namei(), return usize::MAX);
+            if ptr.lock().deref_inner().typ != InodeType::Dir {
+                return usize::MAX;
+            }
+            ptr
+        };
+        ok_or!(self.file_system.mount(dev, point), return usize::MAX);
+        0
+    }
+
+    /// Unmount the file system whose root is path, which fails if any of its files is in use.
+    /// Only the superuser may do this.
+    pub unsafe fn sys_umount(&self) -> usize {
+        let mut path: [u8; MAXPATH] = [0; MAXPATH];
+        let path = ok_or!(argstr(0, &mut path), return usize::MAX);
+        if (*(*myproc()).data.get()).euid != 0 {
+            return usize::MAX;
+        }
+        let dev = {
+            let _tx = self.file_system.begin_transaction();
+            let ptr = ok_or!(Path::new(path).namei(), return usize::MAX);
+            if self.file_system.mount_point(&ptr).is_none() {
+                return usize::MAX;
+            }
+            ptr.dev
+        };
+        ok_or!(self.file_system.umount(dev), return usize::MAX);
+        0
+    }
+
+    /// Set the access and modification times of a file to times[0] and times[1], which only
+    /// the owner or the superuser may do. If times is null, set both to the current time, which
+    /// also anyone who may write the file may do.
+    pub unsafe fn sys_utimes(&self) -> usize {
+        let mut path: [u8; MAXPATH] = [0; MAXPATH];
+        let path = ok_or!(argstr(0, &mut path), return usize::MAX);
+        let times = ok_or!(argaddr(1), return usize::MAX);
+        let mut buf = [0u8; 8];
+        if times != 0 {
+            ok_or!(
+                VAddr::copyin(&mut buf[..], UVAddr::new(times)),
+                return usize::MAX
+            );
+        }
+        let uid = (*(*myproc()).data.get()).euid;
+        let tx = self.file_system.begin_transaction();
+        let ptr = ok_or!(Path::new(path).namei(), return usize::MAX);
+        let mut ip = ptr.lock();
+        if ip.fs().read_only() {
+            return usize::MAX;
+        }
+        let owner = uid == 0 || uid == ip.deref_inner().uid;
+        if times == 0 {
+            if !owner && ip.permission(Access::WRITE).is_err() {
+                return usize::MAX;
+            }
+            ip.touch(InodeTimes::ACCESS | InodeTimes::MODIFY | InodeTimes::CHANGE);
+        } else {
+            if !owner {
+                return usize::MAX;
+            }
+            ip.deref_inner_mut().atime = u32::from_ne_bytes(buf[..4].try_into().unwrap());
+            ip.deref_inner_mut().mtime = u32::from_ne_bytes(buf[4..].try_into().unwrap());
+            ip.touch(InodeTimes::CHANGE);
+        }
+        ip.update(&tx);
+        0
+    }
+
     pub unsafe fn sys_pipe(&self) -> usize {
         let p: *mut Proc = myproc();
-        let mut data = &mut *(*p).data.get();
+        let data = &mut *(*p).data.get();
         // user pointer to array of two integers
         let fdarray = ok_or!(argaddr(0), return usize::MAX);
         let (pipereader, pipewriter) = ok_or!(AllocatedPipe::alloc(), return usize::MAX);
 
         let mut fd0 = ok_or!(pipereader.fdalloc(), return usize::MAX);
         let mut fd1 = ok_or!(pipewriter.fdalloc(), {
-            data.open_files[fd0 as usize] = None;
+            let _ = (*p).set_open_file(fd0 as usize, None);
             return usize::MAX;
         });
 
@@ -396,8 +919,8 @@ impl Kernel {
                 )
                 .is_err()
         {
-            data.open_files[fd0 as usize] = None;
-            data.open_files[fd1 as usize] = None;
+            let _ = (*p).set_open_file(fd0 as usize, None);
+            let _ = (*p).set_open_file(fd1 as usize, None);
             return usize::MAX;
         }
         0
@@ -1,8 +1,16 @@
+use arrayvec::ArrayVec;
+use core::{mem, slice};
+
 use crate::{
+    fs::Path,
     kernel::Kernel,
-    ok_or, poweroff,
+    ok_or,
+    param::{MAXPATH, NSANDBOXPATH},
+    poweroff,
     proc::{myproc, resizeproc},
-    syscall::{argaddr, argint},
+    some_or,
+    syscall::{argaddr, argint, fetchaddr, fetchstr},
+    time::{ClockId, Timespec, Timeval},
     vm::{UVAddr, VAddr},
 };
 
@@ -58,8 +66,130 @@ impl Kernel {
         *self.ticks.lock() as usize
     }
 
+    /// Write the time of clock clockid (0: realtime, 1: monotonic) to the timespec at addr.
+    pub unsafe fn sys_clock_gettime(&self) -> usize {
+        let id = ok_or!(argint(0), return usize::MAX);
+        let addr = ok_or!(argaddr(1), return usize::MAX);
+        let id = some_or!(ClockId::from_i32(id), return usize::MAX);
+        let ts = self.clock.timespec(id);
+        ok_or!(
+            VAddr::copyout(
+                UVAddr::new(addr),
+                slice::from_raw_parts(&ts as *const _ as *const u8, mem::size_of::<Timespec>()),
+            ),
+            return usize::MAX
+        );
+        0
+    }
+
+    /// Write the wall-clock time to the timeval at addr. The second argument, a timezone, is
+    /// ignored.
+    pub unsafe fn sys_gettimeofday(&self) -> usize {
+        let addr = ok_or!(argaddr(0), return usize::MAX);
+        let ts = self.clock.timespec(ClockId::Realtime);
+        let tv = Timeval {
+            tv_sec: ts.tv_sec,
+            tv_usec: ts.tv_nsec / 1000,
+        };
+        ok_or!(
+            VAddr::copyout(
+                UVAddr::new(addr),
+                slice::from_raw_parts(&tv as *const _ as *const u8, mem::size_of::<Timeval>()),
+            ),
+            return usize::MAX
+        );
+        0
+    }
+
+    pub unsafe fn sys_getuid(&self) -> usize {
+        (*(*myproc()).data.get()).uid as usize
+    }
+
+    pub unsafe fn sys_geteuid(&self) -> usize {
+        (*(*myproc()).data.get()).euid as usize
+    }
+
+    pub unsafe fn sys_getgid(&self) -> usize {
+        (*(*myproc()).data.get()).gid as usize
+    }
+
+    /// Set the user ID of the current process.
+    /// If the effective user is the superuser, both the real and effective user IDs are set.
+    /// Otherwise, only the effective user ID may be set, and only back to the real user ID.
+    pub unsafe fn sys_setuid(&self) -> usize {
+        let uid = ok_or!(argint(0), return usize::MAX) as u32;
+        let data = &mut *(*myproc()).data.get();
+        if data.euid == 0 {
+            data.uid = uid;
+        } else if data.uid != uid {
+            return usize::MAX;
+        }
+        data.euid = uid;
+        0
+    }
+
+    /// Set the group ID of the current process, in the same way as setuid().
+    pub unsafe fn sys_setgid(&self) -> usize {
+        let gid = ok_or!(argint(0), return usize::MAX) as u32;
+        let data = &mut *(*myproc()).data.get();
+        if data.euid == 0 {
+            data.gid = gid;
+        } else if data.gid != gid {
+            return usize::MAX;
+        }
+        data.egid = gid;
+        0
+    }
+
+    /// Irrevocably restrict the current process and its future children to the system calls
+    /// whose bits are set in the first argument. If the second argument is not null, it is a
+    /// null-terminated array of absolute path prefixes, and only paths under one of them may
+    /// be looked up from then on.
+    pub unsafe fn sys_sandbox(&self) -> usize {
+        let syscalls = ok_or!(argaddr(0), return usize::MAX) as u64;
+        let uprefixes = ok_or!(argaddr(1), return usize::MAX);
+        let data = &mut *(*myproc()).data.get();
+
+        if uprefixes != 0 {
+            let mut bufs = [[0u8; MAXPATH]; NSANDBOXPATH];
+            let mut bufs = bufs.iter_mut();
+            let mut prefixes: ArrayVec<[&Path; NSANDBOXPATH]> = ArrayVec::new();
+            loop {
+                let mut uprefix = 0;
+                if fetchaddr(
+                    UVAddr::new(uprefixes + mem::size_of::<usize>() * prefixes.len()),
+                    &mut uprefix as *mut usize,
+                ) < 0
+                {
+                    return usize::MAX;
+                }
+                if uprefix == 0 {
+                    break;
+                }
+                let buf = some_or!(bufs.next(), return usize::MAX);
+                let prefix = ok_or!(fetchstr(UVAddr::new(uprefix), buf), return usize::MAX);
+                prefixes.push(Path::new(prefix));
+            }
+            ok_or!(data.sandbox.restrict_paths(&prefixes), return usize::MAX);
+        }
+        data.sandbox.restrict_syscalls(syscalls);
+        0
+    }
+
     pub unsafe fn sys_poweroff(&self) -> usize {
         let exitcode = ok_or!(argint(0), return usize::MAX);
+        // Commit the updates still in the logs' open transaction groups.
+        self.file_system.sync_all();
+        poweroff::machine_poweroff(exitcode as _);
+    }
+
+    /// Power off without committing the log, as a power failure would. Only the superuser may
+    /// do this; it is meant for crash tests.
+    pub unsafe fn sys_crash(&self) -> usize {
+        let exitcode = ok_or!(argint(0), return usize::MAX);
+        if (*(*myproc()).data.get()).euid != 0 {
+            return usize::MAX;
+        }
         poweroff::machine_poweroff(exitcode as _);
     }
 }
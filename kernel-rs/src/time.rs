@@ -0,0 +1,96 @@
+//! Timekeeping.
+//!
+//! The real-time clock is read once at boot. Afterwards, time is counted with the CLINT's
+//! mtime register, which is much cheaper to read and never goes backwards.
+
+use core::ptr;
+
+use crate::{kernel::kernel, memlayout::CLINT_MTIME};
+
+/// Nanoseconds per tick of mtime, which runs at 10 MHz in qemu.
+const NS_PER_MTIME: u64 = 100;
+
+const NS_PER_SEC: u64 = 1_000_000_000;
+
+/// Seconds and nanoseconds, as in clock_gettime().
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct Timespec {
+    pub tv_sec: u64,
+    pub tv_nsec: u64,
+}
+
+/// Seconds and microseconds, as in gettimeofday().
+#[derive(Copy, Clone, Default)]
+#[repr(C)]
+pub struct Timeval {
+    pub tv_sec: u64,
+    pub tv_usec: u64,
+}
+
+/// Clocks of clock_gettime().
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum ClockId {
+    /// Wall-clock time since the Unix epoch.
+    Realtime,
+    /// Time since boot.
+    Monotonic,
+}
+
+impl ClockId {
+    pub fn from_i32(id: i32) -> Option<Self> {
+        match id {
+            0 => Some(Self::Realtime),
+            1 => Some(Self::Monotonic),
+            _ => None,
+        }
+    }
+}
+
+pub struct Clock {
+    /// Nanoseconds since the Unix epoch when mtime was `boot_mtime`.
+    boot_ns: u64,
+    boot_mtime: u64,
+}
+
+impl Clock {
+    pub const fn zero() -> Self {
+        Self {
+            boot_ns: 0,
+            boot_mtime: 0,
+        }
+    }
+
+    /// Read the real-time clock. Must be called after the kernel page table is turned on.
+    pub fn init(&mut self) {
+        self.boot_mtime = mtime();
+        self.boot_ns = crate::rtc::read_ns();
+    }
+
+    /// Nanoseconds elapsed on the clock `id`.
+    pub fn ns(&self, id: ClockId) -> u64 {
+        let elapsed = (mtime() - self.boot_mtime) * NS_PER_MTIME;
+        match id {
+            ClockId::Realtime => self.boot_ns + elapsed,
+            ClockId::Monotonic => elapsed,
+        }
+    }
+
+    pub fn timespec(&self, id: ClockId) -> Timespec {
+        let ns = self.ns(id);
+        Timespec {
+            tv_sec: ns / NS_PER_SEC,
+            tv_nsec: ns % NS_PER_SEC,
+        }
+    }
+}
+
+fn mtime() -> u64 {
+    // SAFETY: the CLINT is identically mapped from physical address by kvmmake().
+    unsafe { ptr::read_volatile(CLINT_MTIME as *const u64) }
+}
+
+/// The current time in seconds since the Unix epoch, as stored in inodes.
+pub fn now() -> u32 {
+    kernel().clock.timespec(ClockId::Realtime).tv_sec as u32
+}
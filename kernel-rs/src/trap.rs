@@ -1,6 +1,7 @@
 use crate::{
     kernel::kernel,
-    memlayout::{TRAMPOLINE, TRAPFRAME, UART0_IRQ, VIRTIO0_IRQ},
+    memlayout::{virtio_irq, TRAMPOLINE, TRAPFRAME, UART0_IRQ, VIRTIO0_IRQ},
+    param::{COMMIT_TICKS, NDISK},
     plic::{plic_claim, plic_complete},
     println,
     proc::{cpuid, myproc, proc_yield, Proc, Procstate},
@@ -190,6 +191,12 @@ pub unsafe fn clockintr() {
     let mut ticks = kernel().ticks.lock();
     *ticks = ticks.wrapping_add(1);
     ticks.wakeup();
+    let commit = *ticks % COMMIT_TICKS == 0;
+    drop(ticks);
+
+    if commit {
+        kernel().file_system.request_commit();
+    }
 }
 
 /// Check if it's an external interrupt or software interrupt,
@@ -208,8 +215,8 @@ pub unsafe fn devintr() -> i32 {
 
         if irq as usize == UART0_IRQ {
             kernel().uart.intr();
-        } else if irq as usize == VIRTIO0_IRQ {
-            kernel().file_system.disk.lock().virtio_intr();
+        } else if (VIRTIO0_IRQ..virtio_irq(NDISK)).contains(&(irq as usize)) {
+            kernel().file_system.disk.intr(irq as usize - VIRTIO0_IRQ);
         } else if irq != 0 {
             println!("unexpected interrupt irq={}\n", irq);
         }
@@ -6,10 +6,9 @@
 //! the virtio spec:
 //! https:///docs.oasis-open.org/virtio/virtio/v1.1/virtio-v1.1.pdf
 
-// virtio mmio control registers, mapped starting at 0x10001000.
+// virtio mmio control registers, mapped starting at 0x10001000 for the first device.
 // from qemu virtio_mmio.h
 
-use crate::memlayout::VIRTIO0;
 use core::ptr;
 
 #[repr(usize)]
@@ -47,12 +46,14 @@ pub enum MmioRegs {
 }
 
 impl MmioRegs {
-    pub unsafe fn read(self) -> u32 {
-        ptr::read_volatile((VIRTIO0 as *mut u8).add(self as _) as _)
+    /// Read the register of the device whose registers start at `base`.
+    pub unsafe fn read(self, base: usize) -> u32 {
+        ptr::read_volatile((base as *mut u8).add(self as _) as _)
     }
 
-    pub unsafe fn write(self, src: u32) {
-        ptr::write_volatile((VIRTIO0 as *mut u8).add(self as _) as _, src)
+    /// Write the register of the device whose registers start at `base`.
+    pub unsafe fn write(self, base: usize, src: u32) {
+        ptr::write_volatile((base as *mut u8).add(self as _) as _, src)
     }
 }
 
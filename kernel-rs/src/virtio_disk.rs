@@ -7,13 +7,17 @@ use crate::{
     bio::Buf,
     kernel::kernel,
     page::RawPage,
-    param::BSIZE,
+    param::{BSIZE, NDISK},
     riscv::{PGSHIFT, PGSIZE},
     sleepablelock::{Sleepablelock, SleepablelockGuard},
     virtio::*,
 };
 
+#[cfg(feature = "disktrace")]
+use crate::{param::ROOTDEV, println};
 use core::array::IntoIter;
+#[cfg(feature = "disktrace")]
+use core::fmt;
 use core::mem;
 use core::ops::{Deref, DerefMut};
 use core::ptr;
@@ -21,7 +25,15 @@ use core::sync::atomic::{fence, Ordering};
 
 use arrayvec::ArrayVec;
 
+/// The virtio disks, by device number.
+pub struct Disks {
+    disks: [Sleepablelock<Disk>; NDISK],
+}
+
 pub struct Disk {
+    /// Start of the device's mmio registers, or 0 if there is no such disk.
+    base: usize,
+
     desc: DescriptorPool,
     avail: *mut VirtqAvail,
     used: *mut [VirtqUsed; NUM],
@@ -208,14 +220,36 @@ impl DescriptorPool {
     }
 }
 
-impl Sleepablelock<Disk> {
+impl Disks {
+    pub const fn zero() -> Self {
+        Self {
+            disks: array![_ => Sleepablelock::new("virtio_disk", Disk::zero()); NDISK],
+        }
+    }
+
+    /// The disk with device number dev.
+    fn disk(&self, dev: u32) -> &Sleepablelock<Disk> {
+        assert!(self.exists(dev), "no such disk");
+        &self.disks[dev as usize - 1]
+    }
+
+    /// Disk n, for virtio_disk_init().
+    pub fn get_mut(&mut self, n: usize) -> &mut Disk {
+        self.disks[n].get_mut()
+    }
+
+    /// Is there a disk with device number dev?
+    pub fn exists(&self, dev: u32) -> bool {
+        dev >= 1 && dev as usize <= NDISK && self.disks[dev as usize - 1].lock().base != 0
+    }
+
     /// Return a locked Buf with the `latest` contents of the indicated block.
     /// If buf.valid is true, we don't need to access Disk.
     pub fn read(&self, dev: u32, blockno: u32) -> Buf<'static> {
         let mut buf = kernel().bcache.get_buf(dev, blockno).lock();
         if !buf.deref_inner().valid {
             unsafe {
-                Disk::virtio_rw(&mut self.lock(), &mut buf, false);
+                Disk::virtio_rw(&mut self.disk(dev).lock(), &mut buf, false);
             }
             buf.deref_mut_inner().valid = true;
         }
@@ -223,13 +257,41 @@ impl Sleepablelock<Disk> {
     }
 
     pub fn write(&self, b: &mut Buf<'static>) {
-        unsafe { Disk::virtio_rw(&mut self.lock(), b, true) }
+        unsafe { Disk::virtio_rw(&mut self.disk(b.dev).lock(), b, true) }
     }
+
+    /// Handle an interrupt from disk n.
+    pub unsafe fn intr(&self, n: usize) {
+        self.disks[n].lock().virtio_intr();
+    }
+}
+
+/// Print a completed write to the root disk for mkfs/crashcheck.c as
+/// "disktrace: W <blockno> <data in hex>".
+#[cfg(feature = "disktrace")]
+fn trace_write(b: &Buf<'static>) {
+    struct Hex<'a>(&'a [u8]);
+
+    impl fmt::Display for Hex<'_> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            for byte in self.0 {
+                write!(f, "{:02x}", byte)?;
+            }
+            Ok(())
+        }
+    }
+
+    println!(
+        "disktrace: W {} {}",
+        b.blockno,
+        Hex(&b.deref_inner().data[..])
+    );
 }
 
 impl Disk {
     pub const fn zero() -> Self {
         Self {
+            base: 0,
             desc: DescriptorPool::zero(),
             avail: ptr::null_mut(),
             used: ptr::null_mut(),
@@ -313,7 +375,7 @@ impl Disk {
         fence(Ordering::SeqCst);
 
         // Value is queue number.
-        MmioRegs::QueueNotify.write(0);
+        MmioRegs::QueueNotify.write(this.base, 0);
 
         // Wait for virtio_disk_intr() to say request has finished.
         while b.deref_mut_inner().disk {
@@ -321,6 +383,12 @@ impl Disk {
         }
         this.info[desc[0].idx].b = ptr::null_mut();
         IntoIter::new(desc).for_each(|desc| this.desc.free(desc));
+
+        // The device has completed the write, so a crash from now on keeps it.
+        #[cfg(feature = "disktrace")]
+        if write && b.dev == ROOTDEV {
+            trace_write(b);
+        }
         this.wakeup();
     }
 
@@ -331,7 +399,7 @@ impl Disk {
         // the "used" ring, in which case we may process the new
         // completion entries in this interrupt, and have nothing to do
         // in the next interrupt, which is harmless.
-        MmioRegs::InterruptAck.write(MmioRegs::InterruptStatus.read() & 0x3);
+        MmioRegs::InterruptAck.write(self.base, MmioRegs::InterruptStatus.read(self.base) & 0x3);
 
         fence(Ordering::SeqCst);
 
@@ -364,22 +432,27 @@ impl InflightInfo {
     }
 }
 
-pub unsafe fn virtio_disk_init(virtqueue: &mut [RawPage; 2], disk: &mut Disk) {
+/// Initialize the disk whose mmio registers start at `base`. Fails if there is no disk there.
+pub unsafe fn virtio_disk_init(
+    base: usize,
+    virtqueue: &mut [RawPage; 2],
+    disk: &mut Disk,
+) -> Result<(), ()> {
     let mut status: VirtIOStatus = VirtIOStatus::empty();
-    assert!(
-        MmioRegs::MagicValue.read() == 0x74726976
-            && MmioRegs::Version.read() == 1
-            && MmioRegs::DeviceId.read() == 2
-            && MmioRegs::VendorId.read() == 0x554d4551,
-        "could not find virtio disk"
-    );
+    if MmioRegs::MagicValue.read(base) != 0x74726976
+        || MmioRegs::Version.read(base) != 1
+        || MmioRegs::DeviceId.read(base) != 2
+        || MmioRegs::VendorId.read(base) != 0x554d4551
+    {
+        return Err(());
+    }
     status.insert(VirtIOStatus::ACKNOWLEDGE);
-    MmioRegs::Status.write(status.bits());
+    MmioRegs::Status.write(base, status.bits());
     status.insert(VirtIOStatus::DRIVER);
-    MmioRegs::Status.write(status.bits());
+    MmioRegs::Status.write(base, status.bits());
 
     // Negotiate features
-    let features = VirtIOFeatures::from_bits_unchecked(MmioRegs::DeviceFeatures.read())
+    let features = VirtIOFeatures::from_bits_unchecked(MmioRegs::DeviceFeatures.read(base))
         - (VirtIOFeatures::BLK_F_RO
             | VirtIOFeatures::BLK_F_SCSI
             | VirtIOFeatures::BLK_F_CONFIG_WCE
@@ -388,25 +461,25 @@ pub unsafe fn virtio_disk_init(virtqueue: &mut [RawPage; 2], disk: &mut Disk) {
             | VirtIOFeatures::RING_F_EVENT_IDX
             | VirtIOFeatures::RING_F_INDIRECT_DESC);
 
-    MmioRegs::DriverFeatures.write(features.bits());
+    MmioRegs::DriverFeatures.write(base, features.bits());
 
     // Tell device that feature negotiation is complete.
     status.insert(VirtIOStatus::FEATURES_OK);
-    MmioRegs::Status.write(status.bits());
+    MmioRegs::Status.write(base, status.bits());
 
     // Tell device we're completely ready.
     status.insert(VirtIOStatus::DRIVER_OK);
-    MmioRegs::Status.write(status.bits());
-    MmioRegs::GuestPageSize.write(PGSIZE as _);
+    MmioRegs::Status.write(base, status.bits());
+    MmioRegs::GuestPageSize.write(base, PGSIZE as _);
 
     // Initialize queue 0.
-    MmioRegs::QueueSel.write(0);
-    let max = MmioRegs::QueueNumMax.read();
+    MmioRegs::QueueSel.write(base, 0);
+    let max = MmioRegs::QueueNumMax.read(base);
     assert!(max != 0, "virtio disk has no queue 0");
     assert!(max >= NUM as u32, "virtio disk max queue too short");
-    MmioRegs::QueueNum.write(NUM as _);
+    MmioRegs::QueueNum.write(base, NUM as _);
     ptr::write_bytes(virtqueue, 0, 1);
-    MmioRegs::QueuePfn.write((virtqueue.as_mut_ptr() as usize >> PGSHIFT) as _);
+    MmioRegs::QueuePfn.write(base, (virtqueue.as_mut_ptr() as usize >> PGSHIFT) as _);
 
     // desc = pages -- num * VirtqDesc
     // avail = pages + 0x40 -- 2 * u16, then num * u16
@@ -415,6 +488,8 @@ pub unsafe fn virtio_disk_init(virtqueue: &mut [RawPage; 2], disk: &mut Disk) {
     disk.desc = DescriptorPool::new(&mut virtqueue[0]);
     disk.avail = (virtqueue[0].as_mut_ptr() as *mut VirtqDesc).add(NUM) as _;
     disk.used = virtqueue[1].as_mut_ptr() as _;
+    disk.base = base;
 
-    // plic.c and trap.c arrange for interrupts from VIRTIO0_IRQ.
+    // plic.c and trap.c arrange for interrupts from the disk's IRQ.
+    Ok(())
 }
@@ -1,7 +1,10 @@
 use crate::{
     kernel::kernel,
-    memlayout::{FINISHER, KERNBASE, PHYSTOP, PLIC, TRAMPOLINE, UART0, VIRTIO0},
+    memlayout::{
+        virtio, CLINT, CLINT_SIZE, FINISHER, KERNBASE, PHYSTOP, PLIC, RTC, TRAMPOLINE, UART0,
+    },
     page::{Page, RawPage},
+    param::NDISK,
     proc::{myproc, proc_mapstacks},
     riscv::{
         make_satp, pa2pte, pgrounddown, pgroundup, pte2pa, pte_flags, px, sfence_vma, w_satp, PteT,
@@ -603,14 +606,20 @@ impl PageTable<KVAddr> {
             PTE_R | PTE_W,
         );
 
+        // Goldfish real-time clock
+        self.kvmmap(KVAddr::new(RTC), PAddr::new(RTC), PGSIZE, PTE_R);
+
+        // CLINT, read-only since only machine mode programs the timer.
+        self.kvmmap(KVAddr::new(CLINT), PAddr::new(CLINT), CLINT_SIZE, PTE_R);
+
         // Uart registers
         self.kvmmap(KVAddr::new(UART0), PAddr::new(UART0), PGSIZE, PTE_R | PTE_W);
 
-        // Virtio mmio disk interface
+        // Virtio mmio disk interfaces
         self.kvmmap(
-            KVAddr::new(VIRTIO0),
-            PAddr::new(VIRTIO0),
-            PGSIZE,
+            KVAddr::new(virtio(0)),
+            PAddr::new(virtio(0)),
+            NDISK * PGSIZE,
             PTE_R | PTE_W,
         );
 
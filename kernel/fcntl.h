@@ -3,3 +3,10 @@
 #define O_RDWR    0x002
 #define O_CREATE  0x200
 #define O_TRUNC   0x400
+#define O_NOFOLLOW 0x800
+#define O_APPEND  0x1000
+
+// whence of lseek()
+#define SEEK_SET  0
+#define SEEK_CUR  1
+#define SEEK_END  2
@@ -20,13 +20,35 @@ struct superblock {
   uint logstart;     // Block number of first log block
   uint inodestart;   // Block number of first inode block
   uint bmapstart;    // Block number of first free map block
+  uint features;     // Optional features (FS_*), or 0 for none
+  uint nbuf;         // Number of buffers in the disk block cache
 };
 
-#define FSMAGIC 0x10203040
+// Changed whenever the layout of inodes or directory records changes,
+// so that a disk in an older format is not misread.
+#define FSMAGIC 0x10203041
+
+#define FS_EXTENTS 1  // Files are mapped by extents instead of block addresses
+#define FS_ORDERED 2  // File data is written in place, and only metadata is logged
 
 #define NDIRECT 12
 #define NINDIRECT (BSIZE / sizeof(uint))
-#define MAXFILE (NDIRECT + NINDIRECT)
+#define NDINDIRECT (NINDIRECT * NINDIRECT)
+#define NTINDIRECT (NDINDIRECT * NINDIRECT)
+#define MAXFILE (NDIRECT + NINDIRECT + NDINDIRECT + NTINDIRECT)
+
+// A run of len consecutive blocks starting at block start, which
+// hold the blocks of a file starting at its block lbn. The extents
+// of a file are sorted by lbn and do not overlap; the first extent
+// of length 0 ends the list. A block no extent holds is a hole.
+struct extent {
+  uint lbn;
+  uint start;
+  uint len;
+};
+
+#define NEXTENT 4
+#define NEXTENTBLOCK (BSIZE / sizeof(struct extent))
 
 // On-disk inode structure
 struct dinode {
@@ -35,9 +57,34 @@ struct dinode {
   ushort minor;         // Minor device number (T_DEVICE only)
   short nlink;          // Number of links to inode in file system
   uint size;            // Size of file (bytes)
-  uint addrs[NDIRECT+1];   // Data block addresses
+  uint mode;            // Permission bits
+  uint uid;             // Owner's user ID
+  uint gid;             // Owner's group ID
+  union {
+    uint addrs[NDIRECT+3];   // Data block addresses: direct, indirect,
+                             // doubly-indirect and triply-indirect
+    struct {                 // With FS_EXTENTS:
+      struct extent extents[NEXTENT];
+      uint extentblock;      // Block holding NEXTENTBLOCK more extents
+      uint extentspare[2];
+    };
+  };
+  uint flags;           // I_INDEX
+  uint atime;           // Time of last access
+  uint mtime;           // Time of last modification of the contents
+  uint ctime;           // Time of last change of the inode
+  uint crtime;          // Time of creation
+  uint blocks;          // Number of blocks allocated, including indirect
+                        // and extent blocks
+  uint spare[5];        // Pads dinode to a whole fraction of a block
 };
 
+// The directory has a hash index. Block 0 then holds ".", "..", and a
+// free record whose body is a uint count and count (hash, block) pairs
+// sorted by hash. Block b holds the names whose FNV-1a hashes lie
+// between its hash and the next. mkfs only makes unindexed directories.
+#define I_INDEX 1
+
 // Inodes per block.
 #define IPB           (BSIZE / sizeof(struct dinode))
 
@@ -50,11 +97,21 @@ struct dinode {
 // Block of free map containing bit for block b
 #define BBLOCK(b, sb) ((b)/BPB + sb.bmapstart)
 
-// Directory is a file containing a sequence of dirent structures.
-#define DIRSIZ 14
+// Directory is a file containing a sequence of variable-length dirent
+// records. Records do not cross block boundaries, and the last record of
+// each block extends to its end. Only the first reclen bytes of a dirent
+// are stored, and the name is not nul-terminated on disk.
+#define DIRSIZ 255  // maximum length of a file name
 
 struct dirent {
-  ushort inum;
-  char name[DIRSIZ];
+  uint inum;           // Inode number, or 0 if the record is free
+  ushort reclen;       // Length of the record, including header and padding
+  uchar namelen;       // Length of name
+  uchar pad;
+  char name[DIRSIZ+1]; // Room for a nul terminator in memory
 };
 
+// Size of the header of a dirent, and of the smallest record for a name.
+#define DIRENT_HDRSIZE 8
+#define DIRENT_RECLEN(namelen) ((DIRENT_HDRSIZE + (namelen) + 3) & ~3)
+
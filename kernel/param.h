@@ -5,9 +5,12 @@
 #define NINODE       50  // maximum number of active i-nodes
 #define NDEV         10  // maximum major device number
 #define ROOTDEV       1  // device number of file system root disk
+#define TMPFSDEV      3  // device number of the in-memory file system
+#define PROCFSDEV     4  // device number of the process file system
 #define MAXARG       32  // max exec arguments
-#define MAXOPBLOCKS  10  // max # of blocks any FS op writes
-#define LOGSIZE      (MAXOPBLOCKS*3)  // max data blocks in on-disk log
-#define NBUF         (MAXOPBLOCKS*3)  // size of disk block cache
-#define FSSIZE       1000  // size of file system in blocks
-#define MAXPATH      128   // maximum file path name
+#define MAXOPBLOCKS  10  // default # of log blocks an FS op reserves
+#define MAXATOMICAPPEND 2048 // O_APPEND writes of up to this many bytes are atomic
+#define LOGSIZE     128  // blocks in on-disk log, including the header
+#define NBUF        256  // size of disk block cache
+#define FSSIZE       10000 // size of file system in blocks
+#define MAXPATH      256   // maximum file path name
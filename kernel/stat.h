@@ -1,6 +1,7 @@
 #define T_DIR     1   // Directory
 #define T_FILE    2   // File
 #define T_DEVICE  3   // Device
+#define T_SYMLINK 4   // Symbolic link
 
 struct stat {
   int dev;     // File system's disk device
@@ -8,4 +9,22 @@ struct stat {
   short type;  // Type of file
   short nlink; // Number of links to file
   uint64 size; // Size of file in bytes
+  uint mode;   // Permission bits
+  uint uid;    // Owner's user ID
+  uint gid;    // Owner's group ID
+  uint atime;  // Time of last access
+  uint mtime;  // Time of last modification of the contents
+  uint ctime;  // Time of last change of the inode
+  uint crtime; // Time of creation
+  uint blocks; // Number of disk blocks allocated
 };
+
+// Directory entry returned by getdents(). Entries are packed one after
+// another; each is reclen bytes long, including its nul-terminated name.
+struct direntry {
+  uint ino;       // Inode number
+  ushort reclen;  // Length of this entry
+  ushort namelen; // Length of name, not counting the nul
+  char name[];
+};
+
@@ -21,3 +21,29 @@
 #define SYS_mkdir  20
 #define SYS_close  21
 #define SYS_poweroff    22
+#define SYS_getuid 23
+#define SYS_setuid 24
+#define SYS_chmod  25
+#define SYS_chown  26
+#define SYS_geteuid 27
+#define SYS_getgid 28
+#define SYS_setgid 29
+#define SYS_sandbox 30
+#define SYS_symlink 31
+#define SYS_readlink 32
+#define SYS_rename 33
+#define SYS_getdents 34
+#define SYS_rmdir 35
+#define SYS_utimes 36
+#define SYS_clock_gettime 37
+#define SYS_gettimeofday 38
+#define SYS_lseek 39
+#define SYS_pread 40
+#define SYS_pwrite 41
+#define SYS_truncate 42
+#define SYS_ftruncate 43
+#define SYS_fsync 44
+#define SYS_crash 45
+#define SYS_fsck 46
+#define SYS_mount 47
+#define SYS_umount 48
@@ -0,0 +1,12 @@
+#define CLOCK_REALTIME  0  // Wall-clock time since the Unix epoch
+#define CLOCK_MONOTONIC 1  // Time since boot
+
+struct timespec {
+  uint64 tv_sec;   // Seconds
+  uint64 tv_nsec;  // Nanoseconds
+};
+
+struct timeval {
+  uint64 tv_sec;   // Seconds
+  uint64 tv_usec;  // Microseconds
+};
@@ -0,0 +1,216 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#define stat xv6_stat  // avoid clash with host struct stat
+#include "kernel/types.h"
+#include "kernel/fs.h"
+#include "kernel/stat.h"
+#include "kernel/param.h"
+#include "mkfs/fscheck.h"
+
+// crashcheck: check that a crash at any point of a boot leaves a file
+// system that recovers to a consistent one.
+//
+// The kernel built with DISKTRACE=yes prints each block it writes to
+// the disk. crashcheck applies the writes of such a console log one at
+// a time to the image the boot started from, and after each one does
+// what the kernel's log recovery would do and runs fscheck() over the
+// result. "make crashcheck DISKTRACE=yes" boots once and runs it.
+
+#define TRACE "disktrace: W "
+
+struct write {
+  uint b;
+  uchar data[BSIZE];
+};
+
+uchar *disk;        // The image, with a prefix of the writes applied
+uint nblock;        // Blocks in the image
+struct write *writes;
+int nwrite;
+
+// Blocks overwritten by recovery, to undo it before the next write.
+struct write undo[LOGSIZE + 1];
+int nundo;
+
+void
+rblock(uint b, void *buf)
+{
+  memmove(buf, disk + b * BSIZE, BSIZE);
+}
+
+void
+wblock(uint b, void *buf)
+{
+  memmove(undo[nundo].data, disk + b * BSIZE, BSIZE);
+  undo[nundo++].b = b;
+  memmove(disk + b * BSIZE, buf, BSIZE);
+}
+
+int
+hexdigit(char c)
+{
+  if(c >= '0' && c <= '9')
+    return c - '0';
+  if(c >= 'a' && c <= 'f')
+    return c - 'a' + 10;
+  return -1;
+}
+
+// Parse the block number and data after TRACE in line into w.
+int
+parsewrite(char *line, struct write *w)
+{
+  char *p;
+  int i, hi, lo;
+
+  w->b = strtoul(line, &p, 10);
+  if(p == line || *p++ != ' ')
+    return -1;
+  for(i = 0; i < BSIZE; i++){
+    if((hi = hexdigit(p[2*i])) < 0 || (lo = hexdigit(p[2*i+1])) < 0)
+      return -1;
+    w->data[i] = hi << 4 | lo;
+  }
+  return 0;
+}
+
+void
+readtrace(char *path)
+{
+  FILE *f;
+  char *line, *p;
+  size_t cap;
+  int n, bad;
+
+  if((f = fopen(path, "r")) == 0){
+    perror(path);
+    exit(1);
+  }
+  line = 0;
+  cap = 0;
+  n = 0;
+  bad = 0;
+  while(getline(&line, &cap, f) >= 0){
+    n++;
+    if((p = strstr(line, TRACE)) == 0)
+      continue;
+    if(bad){
+      fprintf(stderr, "crashcheck: %s:%d: bad write\n", path, bad);
+      exit(1);
+    }
+    if((writes = realloc(writes, (nwrite + 1) * sizeof(*writes))) == 0){
+      perror("crashcheck");
+      exit(1);
+    }
+    if(parsewrite(p + strlen(TRACE), &writes[nwrite]) < 0 || writes[nwrite].b >= nblock){
+      // The machine may have stopped in the middle of the last write, but
+      // no write may follow a bad one.
+      bad = n;
+      continue;
+    }
+    nwrite++;
+  }
+  if(bad)
+    fprintf(stderr, "crashcheck: %s:%d: truncated last write, ignoring it\n", path, bad);
+  free(line);
+  fclose(f);
+}
+
+// Install the committed log, as the kernel's log recovery does.
+int
+recover(int verbose)
+{
+  struct superblock sb;
+  struct {
+    uint n;
+    uint block[LOGSIZE];
+  } lh;
+  char buf[BSIZE];
+  uint i;
+
+  rblock(1, buf);
+  memmove(&sb, buf, sizeof(sb));
+  if(sb.logstart + sb.nlog > nblock)
+    return -1;
+  rblock(sb.logstart, buf);
+  memmove(&lh, buf, sizeof(lh));
+  if(lh.n >= sb.nlog || lh.n >= LOGSIZE){
+    if(verbose)
+      printf("crashcheck: log header holds %d blocks\n", lh.n);
+    return -1;
+  }
+  for(i = 0; i < lh.n; i++){
+    if(lh.block[i] >= nblock){
+      if(verbose)
+        printf("crashcheck: log block %d goes to block %d\n", i, lh.block[i]);
+      return -1;
+    }
+    rblock(sb.logstart + 1 + i, buf);
+    wblock(lh.block[i], buf);
+  }
+  if(lh.n > 0){
+    memset(buf, 0, sizeof(buf));
+    wblock(sb.logstart, buf);
+  }
+  return 0;
+}
+
+// Check the file system a crash now would leave.
+int
+check(int verbose)
+{
+  int bad;
+
+  nundo = 0;
+  bad = recover(verbose) < 0 ||
+        fscheck(nblock, rblock, 0, verbose ? FSCHECK_VERBOSE : 0) > 0;
+  while(nundo > 0){
+    nundo--;
+    memmove(disk + undo[nundo].b * BSIZE, undo[nundo].data, BSIZE);
+  }
+  return bad;
+}
+
+int
+main(int argc, char *argv[])
+{
+  FILE *f;
+  long len;
+  int i;
+
+  if(argc != 3){
+    fprintf(stderr, "Usage: crashcheck fs.img trace\n");
+    exit(1);
+  }
+
+  if((f = fopen(argv[1], "r")) == 0){
+    perror(argv[1]);
+    exit(1);
+  }
+  fseek(f, 0, SEEK_END);
+  len = ftell(f);
+  rewind(f);
+  nblock = len / BSIZE;
+  if((disk = malloc(len)) == 0 || fread(disk, BSIZE, nblock, f) != nblock){
+    perror(argv[1]);
+    exit(1);
+  }
+  fclose(f);
+
+  readtrace(argv[2]);
+
+  // A crash after the first i writes.
+  for(i = 0; i <= nwrite; i++){
+    if(i > 0)
+      memmove(disk + writes[i-1].b * BSIZE, writes[i-1].data, BSIZE);
+    if(check(0)){
+      printf("crashcheck: a crash after write %d of %d leaves:\n", i, nwrite);
+      check(1);
+      exit(1);
+    }
+  }
+  printf("crashcheck: %d crash points OK\n", nwrite + 1);
+  exit(0);
+}
@@ -0,0 +1,616 @@
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <stdarg.h>
+
+#define stat xv6_stat  // avoid clash with host struct stat
+#include "kernel/types.h"
+#include "kernel/fs.h"
+#include "kernel/stat.h"
+#include "mkfs/fscheck.h"
+
+static void (*rblock)(uint, void*);
+static void (*wblock)(uint, void*);
+static int verbose;
+static int repair;
+static int nproblem;
+
+static struct superblock sb;
+static uint datastart;        // First data block
+static struct dinode *inodes; // All inodes, by inode number
+static int inodesdirty;       // Have inodes been repaired?
+static uint *owner;           // Inode using each block, or 0
+static short *refs;           // Number of directory entries naming each inode
+static uint nused;            // Blocks used by the inode being walked
+
+// Block 0 of an indexed directory holds "." and "..", then a free
+// record holding the index root: the number of index entries and
+// the (hash, block) entries. See kernel-rs/src/fs/dirindex.rs.
+#define DXROOT (DIRENT_RECLEN(1) + DIRENT_RECLEN(2))
+#define DXCOUNT (DXROOT + DIRENT_HDRSIZE)
+#define DXENTRIES (DXCOUNT + 4)
+#define MAXLEAVES ((BSIZE - DXENTRIES) / 8)
+
+static void
+problem(char *fmt, ...)
+{
+  va_list ap;
+
+  nproblem++;
+  if(!verbose)
+    return;
+  va_start(ap, fmt);
+  printf("fscheck: ");
+  vprintf(fmt, ap);
+  printf(repair ? ": repaired\n" : "\n");
+  va_end(ap);
+}
+
+// Check the superblock against the layout mkfs makes.
+static int
+checksb(uint nblock)
+{
+  char buf[BSIZE];
+
+  rblock(1, buf);
+  memmove(&sb, buf, sizeof(sb));
+  if(sb.magic != FSMAGIC){
+    problem("bad magic number %x", sb.magic);
+    return -1;
+  }
+  if(sb.features & ~(FS_EXTENTS | FS_ORDERED)){
+    problem("unknown features %x", sb.features);
+    return -1;
+  }
+  if(sb.size > nblock || sb.ninodes == 0 || sb.nlog == 0){
+    problem("bad size %d, ninodes %d, or nlog %d", sb.size, sb.ninodes, sb.nlog);
+    return -1;
+  }
+  datastart = sb.bmapstart + sb.size / BPB + 1;
+  if(sb.logstart != 2 || sb.inodestart != sb.logstart + sb.nlog ||
+     sb.bmapstart != sb.inodestart + sb.ninodes / IPB + 1 ||
+     datastart >= sb.size || sb.nblocks != sb.size - datastart){
+    problem("bad layout: log %d inodes %d bitmap %d size %d nblocks %d",
+            sb.logstart, sb.inodestart, sb.bmapstart, sb.size, sb.nblocks);
+    return -1;
+  }
+  return 0;
+}
+
+// Note that inode inum uses block b. Returns -1 if b is out of range
+// or already used, in which case a repair drops it.
+static int
+use(uint inum, uint b)
+{
+  if(b < datastart || b >= sb.size){
+    problem("inode %d: block %d out of range", inum, b);
+  } else if(owner[b]){
+    problem("inode %d: block %d is also used by inode %d", inum, b, owner[b]);
+  } else {
+    owner[b] = inum;
+    nused++;
+    return 0;
+  }
+  if(!repair)
+    nused++;
+  return -1;
+}
+
+// Walk an indirect block; at level 1, it holds data block addresses.
+static void
+walkindirect(uint inum, uint addr, int level)
+{
+  uint a[NINDIRECT];
+  int i, dirty;
+
+  rblock(addr, a);
+  dirty = 0;
+  for(i = 0; i < NINDIRECT; i++){
+    if(a[i] == 0)
+      continue;
+    if(use(inum, a[i]) < 0){
+      if(repair){
+        a[i] = 0;
+        dirty = 1;
+      }
+    } else if(level > 1){
+      walkindirect(inum, a[i], level - 1);
+    }
+  }
+  if(dirty)
+    wblock(addr, a);
+}
+
+static void
+walkblocks(uint inum, struct dinode *din)
+{
+  int i;
+
+  for(i = 0; i < NDIRECT + 3; i++){
+    if(din->addrs[i] == 0)
+      continue;
+    if(use(inum, din->addrs[i]) < 0){
+      if(repair){
+        din->addrs[i] = 0;
+        inodesdirty = 1;
+      }
+    } else if(i >= NDIRECT){
+      walkindirect(inum, din->addrs[i], i - NDIRECT + 1);
+    }
+  }
+}
+
+// Read the extents of din after the first NEXTENT into block.
+static int
+readextents(struct dinode *din, struct extent *block)
+{
+  memset(block, 0, NEXTENTBLOCK * sizeof(struct extent));
+  if(din->extentblock == 0)
+    return 0;
+  if(din->extentblock < datastart || din->extentblock >= sb.size)
+    return -1;
+  rblock(din->extentblock, block);
+  return 0;
+}
+
+// A repair ends the extents of a file at a bad block or extent.
+static void
+walkextents(uint inum, struct dinode *din)
+{
+  struct extent block[NEXTENTBLOCK];
+  struct extent *e;
+  uint i, j, next;
+  int end;
+
+  if(din->extentblock != 0 && use(inum, din->extentblock) < 0 && repair){
+    din->extentblock = 0;
+    inodesdirty = 1;
+  }
+  readextents(din, block);
+  end = 0;
+  next = 0;
+  for(i = 0; i < NEXTENT + NEXTENTBLOCK; i++){
+    e = i < NEXTENT ? &din->extents[i] : &block[i - NEXTENT];
+    if(e->len == 0)
+      break;
+    if(end){
+      e->len = 0;
+      continue;
+    }
+    if(e->lbn < next){
+      problem("inode %d: extent at block %d out of order", inum, e->lbn);
+      if(repair){
+        e->len = 0;
+        end = 1;
+        continue;
+      }
+    }
+    next = e->lbn + e->len;
+    if(e->start < datastart || e->start >= sb.size || e->len > sb.size - e->start){
+      problem("inode %d: extent %d+%d out of range", inum, e->start, e->len);
+      if(!repair){
+        nused += e->len;
+        continue;
+      }
+      e->len = 0;
+      end = 1;
+      continue;
+    }
+    for(j = 0; j < e->len; j++){
+      if(use(inum, e->start + j) < 0 && repair){
+        // Give back the blocks after j, which are not yet used.
+        e->len = j;
+        end = 1;
+        break;
+      }
+    }
+  }
+  if(end){
+    inodesdirty = 1;
+    if(din->extentblock != 0)
+      wblock(din->extentblock, block);
+  }
+}
+
+// Return the disk block of block fbn of din, or 0 for a hole or a
+// block that cannot be read.
+static uint
+fbnaddr(struct dinode *din, uint fbn)
+{
+  struct extent block[NEXTENTBLOCK];
+  struct extent *e;
+  uint a[NINDIRECT];
+  uint i, addr, level, nblocks;
+
+  if(sb.features & FS_EXTENTS){
+    if(readextents(din, block) < 0)
+      return 0;
+    for(i = 0; i < NEXTENT + NEXTENTBLOCK; i++){
+      e = i < NEXTENT ? &din->extents[i] : &block[i - NEXTENT];
+      if(e->len == 0 || fbn < e->lbn)
+        break;
+      if(fbn - e->lbn < e->len)
+        return e->start + fbn - e->lbn;
+    }
+    return 0;
+  }
+
+  if(fbn < NDIRECT)
+    return din->addrs[fbn];
+  fbn -= NDIRECT;
+  level = 1;
+  nblocks = NINDIRECT;
+  while(fbn >= nblocks){
+    fbn -= nblocks;
+    level++;
+    nblocks *= NINDIRECT;
+    if(level > 3)
+      return 0;
+  }
+  addr = din->addrs[NDIRECT + level - 1];
+  while(nblocks > 1){
+    if(addr < datastart || addr >= sb.size)
+      return 0;
+    rblock(addr, a);
+    nblocks /= NINDIRECT;
+    addr = a[fbn / nblocks];
+    fbn %= nblocks;
+  }
+  return addr;
+}
+
+// Find the record named name in block 0 of directory din, where mkdir
+// puts "." and "..". Returns its offset, or -1 if there is none.
+static int
+findentry(struct dinode *din, char *block, char *name)
+{
+  struct dirent *de;
+  uint addr, off, namelen;
+
+  namelen = strlen(name);
+  addr = fbnaddr(din, 0);
+  if(din->size < BSIZE || addr < datastart || addr >= sb.size)
+    return -1;
+  rblock(addr, block);
+  for(off = 0; off < BSIZE; off += de->reclen){
+    de = (struct dirent*)(block + off);
+    if(de->reclen < DIRENT_HDRSIZE || off + de->reclen > BSIZE)
+      return -1;
+    if(de->inum != 0 && de->namelen == namelen && memcmp(de->name, name, namelen) == 0)
+      return off;
+  }
+  return -1;
+}
+
+// Check that the ".." of directory inum names its parent dir.
+static void
+checkdotdot(uint inum, uint dir)
+{
+  char block[BSIZE];
+  struct dirent *de;
+  int off;
+
+  if((off = findentry(&inodes[inum], block, "..")) < 0){
+    problem("directory %d: no \"..\"", inum);
+    return;
+  }
+  de = (struct dirent*)(block + off);
+  if(de->inum != dir){
+    problem("directory %d: \"..\" names inode %d, not %d", inum, de->inum, dir);
+    de->inum = dir;
+    if(repair)
+      wblock(fbnaddr(&inodes[inum], 0), block);
+  }
+}
+
+// Check the records of directory inum, and count the entries naming
+// each inode in refs. The ".." of a directory counts for the directory
+// that names it, whether or not it names that directory.
+static void
+checkdir(uint inum, struct dinode *din)
+{
+  char block[BSIZE];
+  struct dirent *de;
+  uint fbn, addr, off;
+  int dirty, dot;
+
+  if(din->size % BSIZE){
+    problem("directory %d: size %d is not a whole number of blocks", inum, din->size);
+    din->size -= din->size % BSIZE;
+    inodesdirty = repair;
+  }
+  dot = 0;
+  for(fbn = 0; fbn < din->size / BSIZE; fbn++){
+    addr = fbnaddr(din, fbn);
+    if(addr < datastart || addr >= sb.size){
+      problem("directory %d: block %d is missing", inum, fbn);
+      continue;
+    }
+    rblock(addr, block);
+    dirty = 0;
+    for(off = 0; off < BSIZE; off += de->reclen){
+      de = (struct dirent*)(block + off);
+      if(de->reclen < DIRENT_HDRSIZE || de->reclen % 4 || off + de->reclen > BSIZE){
+        problem("directory %d: bad record length %d at %d", inum, de->reclen, fbn * BSIZE + off);
+        // Free the rest of the block.
+        de->inum = 0;
+        de->reclen = BSIZE - off;
+        dirty = 1;
+        break;
+      }
+      if(de->inum == 0)
+        continue;
+      if(de->namelen == 0 || DIRENT_RECLEN(de->namelen) > de->reclen){
+        problem("directory %d: bad name length %d at %d", inum, de->namelen, fbn * BSIZE + off);
+        de->inum = 0;
+        dirty = 1;
+        continue;
+      }
+      if(de->inum >= sb.ninodes || inodes[de->inum].type == 0){
+        problem("directory %d: entry %.*s names free inode %d",
+                inum, de->namelen, de->name, de->inum);
+        de->inum = 0;
+        dirty = 1;
+        continue;
+      }
+      if(de->namelen == 1 && de->name[0] == '.'){
+        if(de->inum != inum){
+          problem("directory %d: \".\" names inode %d", inum, de->inum);
+          de->inum = inum;
+          dirty = 1;
+        }
+        dot = 1;
+        continue;
+      }
+      if(de->namelen == 2 && de->name[0] == '.' && de->name[1] == '.')
+        continue;
+      refs[de->inum]++;
+      if(inodes[de->inum].type == T_DIR){
+        checkdotdot(de->inum, inum);
+        refs[inum]++;
+      }
+    }
+    if(dirty && repair)
+      wblock(addr, block);
+  }
+  if(!dot)
+    problem("directory %d: no \".\"", inum);
+}
+
+// FNV-1a hash of a file name, as the directory index uses.
+static uint
+namehash(char *name, int len)
+{
+  uint hash;
+  int i;
+
+  hash = 0x811c9dc5;
+  for(i = 0; i < len; i++)
+    hash = (hash ^ (uchar)name[i]) * 0x01000193;
+  return hash;
+}
+
+// Check the index of indexed directory inum: every block past block 0
+// is a leaf, the hashes start at 0 and increase, and each entry is in
+// the leaf of its name's hash. A bad index is dropped, since the
+// directory can still be scanned without it.
+static void
+checkindex(uint inum, struct dinode *din)
+{
+  char block[BSIZE];
+  struct dirent *de;
+  uint hash[MAXLEAVES], slot[MAXLEAVES + 1];
+  uint *entries, i, b, h, fbn, addr, off, count, nblocks;
+
+  if(!(din->flags & I_INDEX))
+    return;
+  nblocks = din->size / BSIZE;
+  addr = fbnaddr(din, 0);
+  if(nblocks < 2 || addr < datastart || addr >= sb.size){
+    problem("directory %d: index root is missing", inum);
+    goto bad;
+  }
+  rblock(addr, block);
+  de = (struct dirent*)(block + DXROOT);
+  count = *(uint*)(block + DXCOUNT);
+  if(de->inum != 0 || de->reclen != BSIZE - DXROOT || count < 1 || count > MAXLEAVES){
+    problem("directory %d: bad index root", inum);
+    goto bad;
+  }
+  if(count != nblocks - 1){
+    problem("directory %d: index has %d leaves for %d blocks", inum, count, nblocks - 1);
+    goto bad;
+  }
+  entries = (uint*)(block + DXENTRIES);
+  memset(slot, 0, sizeof(slot));
+  for(i = 0; i < count; i++){
+    hash[i] = entries[2*i];
+    b = entries[2*i + 1];
+    if(i == 0 ? hash[i] != 0 : hash[i] <= hash[i-1]){
+      problem("directory %d: index hash %x out of order", inum, hash[i]);
+      goto bad;
+    }
+    if(b < 1 || b >= nblocks || slot[b]){
+      problem("directory %d: index leaf %d out of range or repeated", inum, b);
+      goto bad;
+    }
+    slot[b] = i + 1;
+  }
+
+  for(fbn = 0; fbn < nblocks; fbn++){
+    addr = fbnaddr(din, fbn);
+    if(addr < datastart || addr >= sb.size)
+      continue;
+    rblock(addr, block);
+    for(off = 0; off < BSIZE; off += de->reclen){
+      de = (struct dirent*)(block + off);
+      if(de->reclen < DIRENT_HDRSIZE || de->reclen % 4 || off + de->reclen > BSIZE)
+        break;
+      if(de->inum == 0 || de->namelen == 0 || DIRENT_RECLEN(de->namelen) > de->reclen)
+        continue;
+      if(fbn == 0){
+        if((de->namelen == 1 && de->name[0] == '.') ||
+           (de->namelen == 2 && de->name[0] == '.' && de->name[1] == '.'))
+          continue;
+        problem("directory %d: entry %.*s is in the index root block",
+                inum, de->namelen, de->name);
+        goto bad;
+      }
+      h = namehash(de->name, de->namelen);
+      i = slot[fbn] - 1;
+      if(h < hash[i] || (i + 1 < count && h >= hash[i+1])){
+        problem("directory %d: entry %.*s is not in the leaf of its hash",
+                inum, de->namelen, de->name);
+        goto bad;
+      }
+    }
+  }
+  return;
+
+bad:
+  din->flags &= ~I_INDEX;
+  inodesdirty = repair;
+}
+
+// Free inode inum and the blocks it uses.
+static void
+freeinode(uint inum)
+{
+  uint b;
+
+  memset(&inodes[inum], 0, sizeof(struct dinode));
+  inodesdirty = 1;
+  for(b = 0; b < sb.size; b++)
+    if(owner[b] == inum)
+      owner[b] = 0;
+}
+
+// Check that a block is marked allocated in the bitmap if and only if
+// it is a metadata block or used by an inode.
+static void
+checkbitmap(void)
+{
+  uchar buf[BSIZE];
+  uint b, bit;
+  int used, dirty;
+
+  dirty = 0;
+  for(b = 0; b < sb.size; b++){
+    if(b % BPB == 0)
+      rblock(BBLOCK(b, sb), buf);
+    bit = b % BPB;
+    used = b < datastart || owner[b];
+    if(((buf[bit / 8] >> (bit % 8)) & 1) != used){
+      problem("block %d is %s but marked %s", b,
+              used ? "used" : "unused", used ? "free" : "allocated");
+      buf[bit / 8] ^= 1 << (bit % 8);
+      dirty = 1;
+    }
+    if(dirty && repair && (b % BPB == BPB - 1 || b == sb.size - 1)){
+      wblock(BBLOCK(b, sb), buf);
+      dirty = 0;
+    }
+  }
+}
+
+int
+fscheck(uint nblock, void (*rb)(uint, void*), void (*wb)(uint, void*), int flags)
+{
+  struct dinode *din;
+  uint inum, i;
+
+  rblock = rb;
+  wblock = wb;
+  verbose = flags & FSCHECK_VERBOSE;
+  repair = flags & FSCHECK_REPAIR;
+  nproblem = 0;
+  if(checksb(nblock) < 0)
+    return nproblem;
+
+  inodes = calloc(sb.ninodes / IPB + 1, BSIZE);
+  owner = calloc(sb.size, sizeof(uint));
+  refs = calloc(sb.ninodes, sizeof(short));
+  if(inodes == 0 || owner == 0 || refs == 0){
+    perror("fscheck");
+    exit(1);
+  }
+  for(i = 0; i <= sb.ninodes / IPB; i++)
+    rblock(sb.inodestart + i, (char*)inodes + i * BSIZE);
+  inodesdirty = 0;
+
+  // The blocks of each inode.
+  for(inum = 1; inum < sb.ninodes; inum++){
+    din = &inodes[inum];
+    if(din->type == 0)
+      continue;
+    if(din->type < T_DIR || din->type > T_SYMLINK){
+      problem("inode %d: bad type %d", inum, din->type);
+      memset(din, 0, sizeof(*din));
+      inodesdirty = repair;
+      continue;
+    }
+    nused = 0;
+    if(sb.features & FS_EXTENTS)
+      walkextents(inum, din);
+    else
+      walkblocks(inum, din);
+    if(nused != din->blocks){
+      problem("inode %d: uses %d blocks but counts %d", inum, nused, din->blocks);
+      din->blocks = nused;
+      inodesdirty = repair;
+    }
+  }
+
+  if(inodes[ROOTINO].type != T_DIR){
+    problem("root inode is not a directory");
+    goto out;
+  }
+
+  // The entries of each directory, starting from the root's "..".
+  // Those of a directory that has been removed but is still open do
+  // not count.
+  checkdotdot(ROOTINO, ROOTINO);
+  refs[ROOTINO]++;
+  for(inum = 1; inum < sb.ninodes; inum++){
+    din = &inodes[inum];
+    if(din->type == T_DIR && din->nlink > 0){
+      checkdir(inum, din);
+      checkindex(inum, din);
+    }
+  }
+
+  // An inode that was removed while open and not freed before a crash
+  // has no links and no entries. It stays allocated, since nothing
+  // records it for recovery to free, and a repair frees it.
+  for(inum = 1; inum < sb.ninodes; inum++){
+    din = &inodes[inum];
+    if(din->type == 0 || inum == ROOTINO)
+      continue;
+    if(din->nlink != refs[inum])
+      problem("inode %d: nlink %d but %d entries", inum, din->nlink, refs[inum]);
+    if(repair && refs[inum] == 0){
+      if(verbose && din->nlink == 0)
+        printf("fscheck: inode %d: freed, since it has no links\n", inum);
+      freeinode(inum);
+    } else if(repair && din->nlink != refs[inum]){
+      din->nlink = refs[inum];
+      inodesdirty = 1;
+    }
+  }
+  if(inodes[ROOTINO].nlink != refs[ROOTINO]){
+    problem("root: nlink %d but %d entries", inodes[ROOTINO].nlink, refs[ROOTINO]);
+    inodes[ROOTINO].nlink = refs[ROOTINO];
+    inodesdirty = repair;
+  }
+
+  checkbitmap();
+
+out:
+  if(inodesdirty && repair)
+    for(i = 0; i <= sb.ninodes / IPB; i++)
+      wblock(sb.inodestart + i, (char*)inodes + i * BSIZE);
+  free(inodes);
+  free(owner);
+  free(refs);
+  return nproblem;
+}
@@ -0,0 +1,16 @@
+// Consistency check of a file system image, for the host tools in mkfs/.
+//
+// fscheck() reads the image through rblock, which must fill buf with
+// block b of an image of nblock blocks, and returns the number of
+// problems found. Unlike mkfs, it assumes a little-endian host.
+//
+// With FSCHECK_REPAIR, it also repairs what it finds through wblock,
+// freeing what it cannot make sense of. Repairs can leave problems
+// that only a later check finds, such as the files of a freed
+// directory, so a repair should be repeated until it finds nothing.
+
+#define FSCHECK_VERBOSE 1  // Print each problem
+#define FSCHECK_REPAIR  2  // Repair each problem
+
+int fscheck(uint nblock, void (*rblock)(uint b, void *buf),
+            void (*wblock)(uint b, void *buf), int flags);
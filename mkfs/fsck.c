@@ -0,0 +1,80 @@
+#include <stdio.h>
+#include <unistd.h>
+#include <stdlib.h>
+#include <string.h>
+#include <fcntl.h>
+#include <sys/stat.h>
+
+#define stat xv6_stat  // avoid clash with host struct stat
+#include "kernel/types.h"
+#include "kernel/fs.h"
+#include "mkfs/fscheck.h"
+#undef stat
+
+// fsck: check a file system image, and with -r repair it. The image
+// must not be in use. Exits with status 0 if the image is consistent,
+// after any repairs.
+
+#define NPASS 10  // Most repair passes
+
+int fsfd;
+
+void
+rblock(uint b, void *buf)
+{
+  if(pread(fsfd, buf, BSIZE, (off_t)b * BSIZE) != BSIZE){
+    perror("read");
+    exit(1);
+  }
+}
+
+void
+wblock(uint b, void *buf)
+{
+  if(pwrite(fsfd, buf, BSIZE, (off_t)b * BSIZE) != BSIZE){
+    perror("write");
+    exit(1);
+  }
+}
+
+int
+main(int argc, char *argv[])
+{
+  struct stat st;
+  int repair, flags, pass, n, nrepaired;
+
+  repair = 0;
+  if(argc == 3 && strcmp(argv[1], "-r") == 0){
+    repair = 1;
+    argc--;
+    argv++;
+  }
+  if(argc != 2){
+    fprintf(stderr, "Usage: fsck [-r] fs.img\n");
+    exit(1);
+  }
+
+  if((fsfd = open(argv[1], repair ? O_RDWR : O_RDONLY)) < 0 || fstat(fsfd, &st) < 0){
+    perror(argv[1]);
+    exit(1);
+  }
+
+  flags = FSCHECK_VERBOSE | (repair ? FSCHECK_REPAIR : 0);
+  nrepaired = 0;
+  n = fscheck(st.st_size / BSIZE, rblock, wblock, flags);
+  // A repair is done when a pass finds nothing more.
+  for(pass = 1; repair && n > 0 && pass < NPASS; pass++){
+    nrepaired += n;
+    n = fscheck(st.st_size / BSIZE, rblock, wblock, flags);
+  }
+
+  if(n > 0){
+    printf("fsck: %s: %d problems\n", argv[1], n);
+    exit(1);
+  }
+  if(nrepaired > 0)
+    printf("fsck: %s: repaired %d problems\n", argv[1], nrepaired);
+  else
+    printf("fsck: %s: clean\n", argv[1]);
+  exit(0);
+}
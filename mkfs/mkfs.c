@@ -4,6 +4,7 @@
 #include <string.h>
 #include <fcntl.h>
 #include <assert.h>
+#include <time.h>
 
 #define stat xv6_stat  // avoid clash with host struct stat
 #include "kernel/types.h"
@@ -31,6 +32,10 @@ struct superblock sb;
 char zeroes[BSIZE];
 uint freeinode = 1;
 uint freeblock;
+int extents;  // Map files by extents?
+int ordered;  // Write file data in place rather than through the log?
+char *passwords[NINODES];  // name:password pairs given with -p
+int npasswords;
 
 
 void balloc(int);
@@ -38,8 +43,10 @@ void wsect(uint, void*);
 void winode(uint, struct dinode*);
 void rinode(uint inum, struct dinode *ip);
 void rsect(uint sec, void *buf);
-uint ialloc(ushort type);
+uint ialloc(ushort type, uint mode);
 void iappend(uint inum, void *p, int n);
+void dirlink(uint dir, char *name, uint inum);
+void addpasswd(int fd, uint inum);
 
 // convert to intel byte order
 ushort
@@ -68,21 +75,32 @@ int
 main(int argc, char *argv[])
 {
   int i, cc, fd;
-  uint rootino, inum, off;
-  struct dirent de;
+  uint rootino, inum;
   char buf[BSIZE];
-  struct dinode din;
 
 
   static_assert(sizeof(int) == 4, "Integers must be 4 bytes!");
 
-  if(argc < 2){
-    fprintf(stderr, "Usage: mkfs fs.img files...\n");
+  for(; argc > 1 && argv[1][0] == '-'; argc--, argv++){
+    if(strcmp(argv[1], "-e") == 0)
+      extents = 1;
+    else if(strcmp(argv[1], "-o") == 0)
+      ordered = 1;
+    else if(strcmp(argv[1], "-p") == 0 && argc > 2 && npasswords < NINODES &&
+            strchr(argv[2], ':') != 0 && strchr(argv[2], '\n') == 0 &&
+            strchr(strchr(argv[2], ':') + 1, ':') == 0){
+      passwords[npasswords++] = argv[2];
+      argc--, argv++;
+    } else
+      break;
+  }
+  if(argc < 2 || argv[1][0] == '-'){
+    fprintf(stderr, "Usage: mkfs [-e] [-o] [-p name:password]... fs.img files...\n");
     exit(1);
   }
 
   assert((BSIZE % sizeof(struct dinode)) == 0);
-  assert((BSIZE % sizeof(struct dirent)) == 0);
+  assert(DIRENT_RECLEN(DIRSIZ) == sizeof(struct dirent));
 
   fsfd = open(argv[1], O_RDWR|O_CREAT|O_TRUNC, 0666);
   if(fsfd < 0){
@@ -102,6 +120,8 @@ main(int argc, char *argv[])
   sb.logstart = xint(2);
   sb.inodestart = xint(2+nlog);
   sb.bmapstart = xint(2+nlog+ninodeblocks);
+  sb.features = xint((extents ? FS_EXTENTS : 0) | (ordered ? FS_ORDERED : 0));
+  sb.nbuf = xint(NBUF);
 
   printf("nmeta %d (boot, super, log blocks %u inode blocks %u, bitmap blocks %u) blocks %d total %d\n",
          nmeta, nlog, ninodeblocks, nbitmap, nblocks, FSSIZE);
@@ -115,18 +135,11 @@ main(int argc, char *argv[])
   memmove(buf, &sb, sizeof(sb));
   wsect(1, buf);
 
-  rootino = ialloc(T_DIR);
+  rootino = ialloc(T_DIR, 0755);
   assert(rootino == ROOTINO);
 
-  bzero(&de, sizeof(de));
-  de.inum = xshort(rootino);
-  strcpy(de.name, ".");
-  iappend(rootino, &de, sizeof(de));
-
-  bzero(&de, sizeof(de));
-  de.inum = xshort(rootino);
-  strcpy(de.name, "..");
-  iappend(rootino, &de, sizeof(de));
+  dirlink(rootino, ".", rootino);
+  dirlink(rootino, "..", rootino);
 
   for(i = 2; i < argc; i++){
     // get rid of "user/"
@@ -147,34 +160,92 @@ main(int argc, char *argv[])
     // The binaries are named _rm, _cat, etc. to keep the
     // build operating system from trying to execute them
     // in place of system binaries like rm and cat.
-    if(shortname[0] == '_')
+    // They are also the only executable files.
+    if(shortname[0] == '_'){
       shortname += 1;
+      inum = ialloc(T_FILE, 0755);
+    } else if(strcmp(shortname, "passwd") == 0){
+      // Only root (i.e., login) may read the password file.
+      inum = ialloc(T_FILE, 0600);
+    } else {
+      inum = ialloc(T_FILE, 0644);
+    }
 
-    inum = ialloc(T_FILE);
-
-    bzero(&de, sizeof(de));
-    de.inum = xshort(inum);
-    strncpy(de.name, shortname, DIRSIZ);
-    iappend(rootino, &de, sizeof(de));
+    dirlink(rootino, shortname, inum);
 
+    if(strcmp(shortname, "passwd") == 0){
+      addpasswd(fd, inum);
+      npasswords = 0;
+    }
     while((cc = read(fd, buf, sizeof(buf))) > 0)
       iappend(inum, buf, cc);
 
     close(fd);
   }
 
-  // fix size of root inode dir
-  rinode(rootino, &din);
-  off = xint(din.size);
-  off = ((off/BSIZE) + 1) * BSIZE;
-  din.size = xint(off);
-  winode(rootino, &din);
+  if(npasswords > 0){
+    fprintf(stderr, "mkfs: -p given without a passwd file\n");
+    exit(1);
+  }
 
   balloc(freeblock);
 
   exit(0);
 }
 
+// Copy the password file fd to inode inum, setting the password
+// of each user named with -p. Only accounts whose password is
+// empty may be given one, and every named user must exist.
+void
+addpasswd(int fd, uint inum)
+{
+  static char in[8*BSIZE], out[16*BSIZE];
+  char *line, *next, *pw, *rest;
+  int i, m, n, len, found;
+
+  m = 0;
+  while((n = read(fd, in+m, sizeof(in)-m-1)) > 0)
+    m += n;
+  if(n < 0 || m == sizeof(in)-1){
+    fprintf(stderr, "mkfs: cannot read passwd\n");
+    exit(1);
+  }
+  in[m] = 0;
+
+  len = 0;
+  for(line = in; *line; line = next){
+    if((next = strchr(line, '\n')) != 0)
+      *next++ = 0;
+    else
+      next = line + strlen(line);
+    pw = strchr(line, ':');
+    found = -1;
+    for(i = 0; pw && i < npasswords; i++)
+      if(passwords[i] && strncmp(passwords[i], line, pw-line) == 0 &&
+         passwords[i][pw-line] == ':')
+        found = i;
+    if(found < 0){
+      len += snprintf(out+len, sizeof(out)-len, "%s\n", line);
+    } else if((rest = strchr(pw+1, ':')) == 0 || rest != pw+1){
+      fprintf(stderr, "mkfs: %s already has a password\n", passwords[found]);
+      exit(1);
+    } else {
+      *pw = 0;
+      len += snprintf(out+len, sizeof(out)-len, "%s:%s%s\n",
+                      line, strchr(passwords[found], ':') + 1, rest);
+      passwords[found] = 0;
+    }
+    assert(len < sizeof(out));
+  }
+  for(i = 0; i < npasswords; i++){
+    if(passwords[i] != 0){
+      fprintf(stderr, "mkfs: -p %s: no such user, or user given twice\n", passwords[i]);
+      exit(1);
+    }
+  }
+  iappend(inum, out, len);
+}
+
 void
 wsect(uint sec, void *buf)
 {
@@ -228,16 +299,23 @@ rsect(uint sec, void *buf)
   }
 }
 
+// All files belong to the superuser (uid 0, gid 0), and are stamped
+// with the time mkfs runs.
 uint
-ialloc(ushort type)
+ialloc(ushort type, uint mode)
 {
   uint inum = freeinode++;
+  uint now = time(0);
   struct dinode din;
 
   bzero(&din, sizeof(din));
   din.type = xshort(type);
   din.nlink = xshort(1);
   din.size = xint(0);
+  din.mode = xint(mode);
+  din.uid = xint(0);
+  din.gid = xint(0);
+  din.atime = din.mtime = din.ctime = din.crtime = xint(now);
   winode(inum, &din);
   return inum;
 }
@@ -260,6 +338,93 @@ balloc(int used)
 
 #define min(a, b) ((a) < (b) ? (a) : (b))
 
+// Return the disk block address of block fbn of din,
+// allocating it and any indirect blocks on the way if necessary.
+uint
+bmap(struct dinode *din, uint fbn)
+{
+  uint indirect[NINDIRECT];
+  uint addr, level, nblocks, index;
+
+  if(fbn < NDIRECT){
+    if(xint(din->addrs[fbn]) == 0)
+      din->addrs[fbn] = xint(freeblock++);
+    return xint(din->addrs[fbn]);
+  }
+
+  // Find the level of indirection and the index of the block within it.
+  fbn -= NDIRECT;
+  level = 1;
+  nblocks = NINDIRECT;
+  while(fbn >= nblocks){
+    fbn -= nblocks;
+    level++;
+    nblocks *= NINDIRECT;
+  }
+
+  if(xint(din->addrs[NDIRECT+level-1]) == 0)
+    din->addrs[NDIRECT+level-1] = xint(freeblock++);
+  addr = xint(din->addrs[NDIRECT+level-1]);
+
+  // Walk down the indirect blocks.
+  while(nblocks > 1){
+    nblocks /= NINDIRECT;
+    index = fbn / nblocks;
+    fbn %= nblocks;
+    rsect(addr, (char*)indirect);
+    if(indirect[index] == 0){
+      indirect[index] = xint(freeblock++);
+      wsect(addr, (char*)indirect);
+    }
+    addr = xint(indirect[index]);
+  }
+  return addr;
+}
+
+// Return the disk block address of block fbn of din in a file system
+// with extents, allocating it if necessary. Blocks are only ever
+// appended, so fbn is at most the number of blocks of din.
+uint
+emap(struct dinode *din, uint fbn)
+{
+  struct extent block[NEXTENTBLOCK];
+  struct extent *e, *last;
+  uint i, end;
+
+  memset(block, 0, sizeof(block));
+  if(xint(din->extentblock) != 0)
+    rsect(xint(din->extentblock), (char*)block);
+
+  // Find the extent holding block fbn.
+  end = 0;
+  last = 0;
+  for(i = 0; i < NEXTENT + NEXTENTBLOCK; i++){
+    e = i < NEXTENT ? &din->extents[i] : &block[i - NEXTENT];
+    if(xint(e->len) == 0)
+      break;
+    end = xint(e->lbn) + xint(e->len);
+    if(fbn >= xint(e->lbn) && fbn < end)
+      return xint(e->start) + fbn - xint(e->lbn);
+    last = e;
+  }
+  assert(fbn == end);
+
+  if(last && xint(last->start) + xint(last->len) == freeblock){
+    last->len = xint(xint(last->len) + 1);
+  } else {
+    assert(i < NEXTENT + NEXTENTBLOCK);
+    if(i >= NEXTENT && xint(din->extentblock) == 0)
+      din->extentblock = xint(freeblock++);
+    e = i < NEXTENT ? &din->extents[i] : &block[i - NEXTENT];
+    e->lbn = xint(fbn);
+    e->start = xint(freeblock);
+    e->len = xint(1);
+  }
+  if(xint(din->extentblock) != 0)
+    wsect(xint(din->extentblock), (char*)block);
+  return freeblock++;
+}
+
 void
 iappend(uint inum, void *xp, int n)
 {
@@ -267,31 +432,16 @@ iappend(uint inum, void *xp, int n)
   uint fbn, off, n1;
   struct dinode din;
   char buf[BSIZE];
-  uint indirect[NINDIRECT];
-  uint x;
+  uint x, first;
 
   rinode(inum, &din);
+  first = freeblock;
   off = xint(din.size);
   // printf("append inum %d at off %d sz %d\n", inum, off, n);
   while(n > 0){
     fbn = off / BSIZE;
     assert(fbn < MAXFILE);
-    if(fbn < NDIRECT){
-      if(xint(din.addrs[fbn]) == 0){
-        din.addrs[fbn] = xint(freeblock++);
-      }
-      x = xint(din.addrs[fbn]);
-    } else {
-      if(xint(din.addrs[NDIRECT]) == 0){
-        din.addrs[NDIRECT] = xint(freeblock++);
-      }
-      rsect(xint(din.addrs[NDIRECT]), (char*)indirect);
-      if(indirect[fbn - NDIRECT] == 0){
-        indirect[fbn - NDIRECT] = xint(freeblock++);
-        wsect(xint(din.addrs[NDIRECT]), (char*)indirect);
-      }
-      x = xint(indirect[fbn-NDIRECT]);
-    }
+    x = extents ? emap(&din, fbn) : bmap(&din, fbn);
     n1 = min(n, (fbn + 1) * BSIZE - off);
     rsect(x, buf);
     bcopy(p, buf + off - (fbn * BSIZE), n1);
@@ -301,5 +451,55 @@ iappend(uint inum, void *xp, int n)
     p += n1;
   }
   din.size = xint(off);
+  din.blocks = xint(xint(din.blocks) + freeblock - first);
   winode(inum, &din);
 }
+
+// Add the entry (name, inum) to directory dir, in the last record of
+// its last block if there is room, or else in a new block.
+void
+dirlink(uint dir, char *name, uint inum)
+{
+  struct dinode din;
+  char block[BSIZE];
+  struct dirent *de;
+  uint size, x, off, used, namelen;
+
+  namelen = strlen(name);
+  assert(namelen <= DIRSIZ);
+
+  rinode(dir, &din);
+  size = xint(din.size);
+  assert(size % BSIZE == 0);
+  if(size > 0){
+    // Find the last record of the last block.
+    x = extents ? emap(&din, size/BSIZE - 1) : bmap(&din, size/BSIZE - 1);
+    rsect(x, block);
+    off = 0;
+    de = (struct dirent*)block;
+    while(off + xshort(de->reclen) < BSIZE){
+      off += xshort(de->reclen);
+      de = (struct dirent*)(block + off);
+    }
+    used = xint(de->inum) ? DIRENT_RECLEN(de->namelen) : 0;
+    if(BSIZE - off - used >= DIRENT_RECLEN(namelen)){
+      if(used)
+        de->reclen = xshort(used);
+      de = (struct dirent*)(block + off + used);
+      de->inum = xint(inum);
+      de->reclen = xshort(BSIZE - off - used);
+      de->namelen = namelen;
+      memmove(de->name, name, namelen);
+      wsect(x, block);
+      return;
+    }
+  }
+
+  memset(block, 0, sizeof(block));
+  de = (struct dirent*)block;
+  de->inum = xint(inum);
+  de->reclen = xshort(BSIZE);
+  de->namelen = namelen;
+  memmove(de->name, name, namelen);
+  iappend(dir, block, BSIZE);
+}
@@ -0,0 +1,224 @@
+#include "kernel/types.h"
+#include "kernel/stat.h"
+#include "kernel/fcntl.h"
+#include "kernel/fs.h"
+#include "user/user.h"
+
+// crashtest: check that a crash leaves files with only data that was
+// written to them. Each boot checks the file system left by the
+// previous boot's crash, sets up the next scenario, and crashes.
+// "make crashtest CRASHTEST=yes" boots until crashtest exits. The
+// scenarios are meant for an ordered file system (MKFSFLAGS=-o),
+// whose file data does not go through the log, but any must pass.
+
+#define CRASHED 3     // exit status of a boot that crashed
+#define NBLOCK  40    // blocks of each test file
+#define PHASE   "crashtest.phase"
+
+char buf[BSIZE];
+
+// create name with NBLOCK blocks of byte c, and fsync it if sync is set.
+void
+writefile(char *name, char c, int sync)
+{
+  int fd, i;
+
+  fd = open(name, O_CREATE | O_WRONLY);
+  if(fd < 0){
+    printf("crashtest: cannot create %s\n", name);
+    exit(1);
+  }
+  memset(buf, c, sizeof(buf));
+  for(i = 0; i < NBLOCK; i++){
+    if(write(fd, buf, sizeof(buf)) != sizeof(buf)){
+      printf("crashtest: write %s failed\n", name);
+      exit(1);
+    }
+  }
+  if(sync && fsync(fd) != 0){
+    printf("crashtest: fsync %s failed\n", name);
+    exit(1);
+  }
+  close(fd);
+}
+
+// commit the updates to the current directory.
+void
+syncdir(void)
+{
+  int fd;
+
+  fd = open(".", O_RDONLY);
+  if(fd < 0 || fsync(fd) != 0){
+    printf("crashtest: fsync . failed\n");
+    exit(1);
+  }
+  close(fd);
+}
+
+// return 1 if every byte of name is one of the bytes in ok. if must
+// is set, name must exist and have NBLOCK blocks; otherwise a missing
+// or short file is fine.
+int
+checkfile(char *name, char *ok, int must)
+{
+  int fd, n, i, tot;
+
+  fd = open(name, O_RDONLY);
+  if(fd < 0){
+    if(must)
+      printf("crashtest: %s is missing\n", name);
+    return !must;
+  }
+  tot = 0;
+  while((n = read(fd, buf, sizeof(buf))) > 0){
+    for(i = 0; i < n; i++){
+      if(strchr(ok, buf[i]) == 0){
+        printf("crashtest: %s has byte 0x%x at %d\n", name, buf[i] & 0xff, tot + i);
+        close(fd);
+        return 0;
+      }
+    }
+    tot += n;
+  }
+  close(fd);
+  if(must && tot != NBLOCK * BSIZE){
+    printf("crashtest: %s has %d bytes\n", name, tot);
+    return 0;
+  }
+  return 1;
+}
+
+// a file gets the blocks of a deleted file, whose deletion has
+// committed, and the machine crashes before the new file is synced.
+void
+reuse_setup(void)
+{
+  writefile("secret", 'S', 1);
+  unlink("secret");
+  syncdir();
+  writefile("victim", 'V', 0);
+}
+
+int
+reuse_check(void)
+{
+  return checkfile("victim", "V", 0);
+}
+
+// as above, but the deletion has not committed either, so the
+// deleted file may come back and must keep its own data.
+void
+samegroup_setup(void)
+{
+  writefile("secret", 'S', 1);
+  unlink("secret");
+  writefile("victim", 'V', 0);
+}
+
+int
+samegroup_check(void)
+{
+  return checkfile("secret", "S", 0) && checkfile("victim", "V", 0);
+}
+
+// a synced file survives the crash.
+void
+fsync_setup(void)
+{
+  writefile("victim", 'V', 1);
+}
+
+int
+fsync_check(void)
+{
+  return checkfile("victim", "V", 1);
+}
+
+// a synced file is overwritten, and may hold old and new data.
+void
+overwrite_setup(void)
+{
+  writefile("victim", 'A', 1);
+  writefile("victim", 'B', 0);
+}
+
+int
+overwrite_check(void)
+{
+  return checkfile("victim", "AB", 1);
+}
+
+struct scenario {
+  char *name;
+  void (*setup)(void);
+  int (*check)(void);
+} scenarios[] = {
+  { "reuse", reuse_setup, reuse_check },
+  { "samegroup", samegroup_setup, samegroup_check },
+  { "fsync", fsync_setup, fsync_check },
+  { "overwrite", overwrite_setup, overwrite_check },
+};
+
+#define NSCENARIO (sizeof(scenarios) / sizeof(scenarios[0]))
+
+// the number of scenarios run so far.
+int
+readphase(void)
+{
+  int fd, phase;
+
+  fd = open(PHASE, O_RDONLY);
+  if(fd < 0)
+    return 0;
+  if(read(fd, &phase, sizeof(phase)) != sizeof(phase))
+    phase = 0;
+  close(fd);
+  return phase;
+}
+
+void
+writephase(int phase)
+{
+  int fd;
+
+  fd = open(PHASE, O_CREATE | O_WRONLY);
+  if(fd < 0 || write(fd, &phase, sizeof(phase)) != sizeof(phase) || fsync(fd) != 0){
+    printf("crashtest: cannot write %s\n", PHASE);
+    exit(1);
+  }
+  close(fd);
+}
+
+int
+main(void)
+{
+  struct scenario *s;
+  int phase;
+
+  phase = readphase();
+  if(phase > 0){
+    s = &scenarios[phase - 1];
+    if(!s->check()){
+      printf("crashtest: %s FAILED\n", s->name);
+      exit(1);
+    }
+    printf("crashtest: %s OK\n", s->name);
+  }
+  unlink("secret");
+  unlink("victim");
+  if(phase == NSCENARIO){
+    unlink(PHASE);
+    printf("crashtest: ALL TESTS PASSED\n");
+    exit(0);
+  }
+
+  // the unlinks above commit with the phase.
+  writephase(phase + 1);
+  s = &scenarios[phase];
+  printf("crashtest: %s crashing\n", s->name);
+  s->setup();
+  crash(CRASHED);
+  printf("crashtest: crash failed\n");
+  exit(1);
+}
@@ -0,0 +1,41 @@
+#include "kernel/types.h"
+#include "kernel/time.h"
+#include "user/user.h"
+
+// date: print the current time in UTC.
+
+int
+isleap(int y)
+{
+  return (y % 4 == 0 && y % 100 != 0) || y % 400 == 0;
+}
+
+int
+main(int argc, char *argv[])
+{
+  static int mdays[] = { 31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31 };
+  struct timeval tv;
+  uint64 days, secs;
+  int year, mon, n;
+
+  if(gettimeofday(&tv, 0) < 0){
+    fprintf(2, "date: gettimeofday failed\n");
+    exit(1);
+  }
+  days = tv.tv_sec / 86400;
+  secs = tv.tv_sec % 86400;
+  for(year = 1970; days >= (n = isleap(year) ? 366 : 365); year++)
+    days -= n;
+  for(mon = 0; mon < 12; mon++){
+    n = mdays[mon] + (mon == 1 && isleap(year));
+    if(days < n)
+      break;
+    days -= n;
+  }
+  printf("%d-%s%d-%s%d %s%d:%s%d:%s%d UTC\n", year,
+         mon < 9 ? "0" : "", mon + 1, days < 9 ? "0" : "", (int)days + 1,
+         secs < 36000 ? "0" : "", (int)(secs / 3600),
+         secs % 3600 < 600 ? "0" : "", (int)(secs % 3600 / 60),
+         secs % 60 < 10 ? "0" : "", (int)(secs % 60));
+  exit(0);
+}
@@ -0,0 +1,28 @@
+#include "kernel/types.h"
+#include "kernel/stat.h"
+#include "user/user.h"
+
+// fsck: check the root file system. The kernel prints each problem
+// on the console. Repairs are made on the host with mkfs/fsck, on an
+// image that is not in use.
+
+int
+main(int argc, char *argv[])
+{
+  int n;
+
+  if(argc != 1){
+    fprintf(2, "Usage: fsck\n");
+    exit(1);
+  }
+  if((n = fsck()) < 0){
+    fprintf(2, "fsck: cannot check the file system\n");
+    exit(1);
+  }
+  if(n > 0){
+    printf("fsck: %d problems\n", n);
+    exit(1);
+  }
+  printf("fsck: clean\n");
+  exit(0);
+}
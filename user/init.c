@@ -1,5 +1,6 @@
 // init: The initial user-level program
 
+#include "kernel/param.h"
 #include "kernel/types.h"
 #include "kernel/stat.h"
 #include "kernel/spinlock.h"
@@ -11,8 +12,10 @@
 
 #ifdef USERTEST
 char *argv[] = { "usertests", 0 };
+#elif defined(CRASHTEST)
+char *argv[] = { "crashtest", 0 };
 #else
-char *argv[] = { "sh", 0 };
+char *argv[] = { "login", 0 };
 #endif
 
 int
@@ -29,6 +32,16 @@ main(void)
   dup(0);  // stdout
   dup(0);  // stderr
 
+  // Temporary files are kept in memory.
+  mkdir("/tmp");
+  if(mount(TMPFSDEV, "/tmp") < 0)
+    printf("init: cannot mount tmpfs on /tmp\n");
+
+  // Processes can be looked at under /proc.
+  mkdir("/proc");
+  if(mount(PROCFSDEV, "/proc") < 0)
+    printf("init: cannot mount procfs on /proc\n");
+
   for(;;){
     printf("init: starting %s\n", argv[0]);
     pid = fork();
@@ -47,7 +60,7 @@ main(void)
       // or if a parentless process exits.
       wpid = wait(&xstate);
       if(wpid == pid){
-        // the shell exited; restart it.
+        // the shell exited; log in again.
         break;
       } else if(wpid < 0){
         printf("init: wait returned an error\n");
@@ -56,7 +69,7 @@ main(void)
         // it was a parentless process; do nothing.
       }
     }
-#ifdef USERTEST
+#if defined(USERTEST) || defined(CRASHTEST)
     poweroff(xstate);
 #endif
   }
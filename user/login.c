@@ -0,0 +1,105 @@
+// login: authenticate a user against the password file
+// and start a shell with the user's identity.
+//
+// Each line of the password file is name:password:uid:gid.
+// An account with an empty password cannot log in; mkfs -p
+// sets passwords when the file system is made.
+
+#include "kernel/types.h"
+#include "kernel/stat.h"
+#include "kernel/fcntl.h"
+#include "user/user.h"
+
+#define PASSWD "/passwd"
+
+char *argv[] = { "sh", 0 };
+char buf[1024];
+
+// Strip the trailing newline that gets() leaves in s.
+void
+chomp(char *s)
+{
+  char *p;
+
+  if((p = strchr(s, '\n')) != 0)
+    *p = 0;
+  if((p = strchr(s, '\r')) != 0)
+    *p = 0;
+}
+
+// Split line at the next ':' and return the remainder,
+// or 0 if there is none.
+char*
+field(char *line)
+{
+  char *p;
+
+  if((p = strchr(line, ':')) == 0)
+    return 0;
+  *p = 0;
+  return p + 1;
+}
+
+// Look up name in the password file. If the user has a password
+// and it matches, store the user's IDs in *uid and *gid and
+// return 0.
+int
+authenticate(char *name, char *password, int *uid, int *gid)
+{
+  int fd, n, m;
+  char *line, *next, *pw, *u, *g;
+
+  if((fd = open(PASSWD, O_RDONLY)) < 0){
+    fprintf(2, "login: cannot open %s\n", PASSWD);
+    return -1;
+  }
+  m = 0;
+  while((n = read(fd, buf+m, sizeof(buf)-m-1)) > 0)
+    m += n;
+  close(fd);
+  buf[m] = 0;
+
+  for(line = buf; line && *line; line = next){
+    if((next = strchr(line, '\n')) != 0)
+      *next++ = 0;
+    if((pw = field(line)) == 0 || (u = field(pw)) == 0 || (g = field(u)) == 0)
+      continue;
+    if(strcmp(line, name) == 0 && pw[0] != 0 && strcmp(pw, password) == 0){
+      *uid = atoi(u);
+      *gid = atoi(g);
+      return 0;
+    }
+  }
+  return -1;
+}
+
+int
+main(void)
+{
+  char name[32], password[32];
+  int uid, gid;
+
+  for(;;){
+    printf("login: ");
+    gets(name, sizeof(name));
+    if(name[0] == 0)
+      exit(1);  // end of input
+    chomp(name);
+    printf("password: ");
+    gets(password, sizeof(password));
+    chomp(password);
+
+    if(authenticate(name, password, &uid, &gid) < 0){
+      printf("login incorrect\n");
+      continue;
+    }
+    // Drop the group first; afterwards we may no longer be allowed to.
+    if(setgid(gid) < 0 || setuid(uid) < 0){
+      fprintf(2, "login: cannot switch to user %s\n", name);
+      exit(1);
+    }
+    exec(argv[0], argv);
+    fprintf(2, "login: exec %s failed\n", argv[0]);
+    exit(1);
+  }
+}
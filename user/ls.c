@@ -3,10 +3,12 @@
 #include "user/user.h"
 #include "kernel/fs.h"
 
+#define NAMEWIDTH 14  // names are padded to this width
+
 char*
 fmtname(char *path)
 {
-  static char buf[DIRSIZ+1];
+  static char buf[NAMEWIDTH+1];
   char *p;
 
   // Find first character after last slash.
@@ -15,10 +17,10 @@ fmtname(char *path)
   p++;
 
   // Return blank-padded name.
-  if(strlen(p) >= DIRSIZ)
+  if(strlen(p) >= NAMEWIDTH)
     return p;
   memmove(buf, p, strlen(p));
-  memset(buf+strlen(p), ' ', DIRSIZ-strlen(p));
+  memset(buf+strlen(p), ' ', NAMEWIDTH-strlen(p));
   return buf;
 }
 
@@ -27,7 +29,9 @@ ls(char *path)
 {
   char buf[512], *p;
   int fd;
-  struct dirent de;
+  struct direntry *de;
+  char dbuf[512];
+  int dn, doff;
   struct stat st;
 
   if((fd = open(path, 0)) < 0){
@@ -54,16 +58,16 @@ ls(char *path)
     strcpy(buf, path);
     p = buf+strlen(buf);
     *p++ = '/';
-    while(read(fd, &de, sizeof(de)) == sizeof(de)){
-      if(de.inum == 0)
-        continue;
-      memmove(p, de.name, DIRSIZ);
-      p[DIRSIZ] = 0;
-      if(stat(buf, &st) < 0){
-        printf("ls: cannot stat %s\n", buf);
-        continue;
+    while((dn = getdents(fd, dbuf, sizeof(dbuf))) > 0){
+      for(doff = 0; doff < dn; doff += de->reclen){
+        de = (struct direntry*)(dbuf + doff);
+        memmove(p, de->name, de->namelen + 1);
+        if(stat(buf, &st) < 0){
+          printf("ls: cannot stat %s\n", buf);
+          continue;
+        }
+        printf("%s %d %d %d\n", fmtname(buf), st.type, st.ino, st.size);
       }
-      printf("%s %d %d %d\n", fmtname(buf), st.type, st.ino, st.size);
     }
     break;
   }
@@ -0,0 +1,31 @@
+#include "kernel/param.h"
+#include "kernel/types.h"
+#include "kernel/stat.h"
+#include "user/user.h"
+
+// mount: mount the file system of a disk on a directory. Disk 1 is
+// the root; the Makefile attaches fs2.img as disk 2. "mount tmpfs dir"
+// mounts the in-memory file system, which starts out empty, instead, and
+// "mount proc dir" the process file system.
+
+int
+main(int argc, char *argv[])
+{
+  int dev;
+
+  if(argc != 3){
+    fprintf(2, "Usage: mount dev|tmpfs|proc dir\n");
+    exit(1);
+  }
+  if(strcmp(argv[1], "tmpfs") == 0)
+    dev = TMPFSDEV;
+  else if(strcmp(argv[1], "proc") == 0)
+    dev = PROCFSDEV;
+  else
+    dev = atoi(argv[1]);
+  if(mount(dev, argv[2]) < 0){
+    fprintf(2, "mount: cannot mount %s on %s\n", argv[1], argv[2]);
+    exit(1);
+  }
+  exit(0);
+}
@@ -0,0 +1,41 @@
+#include "kernel/types.h"
+#include "kernel/stat.h"
+#include "user/user.h"
+
+// ps: list processes by reading /proc/<pid>/status.
+
+char buf[512];
+
+int
+main(int argc, char *argv[])
+{
+  int fd, sfd, n;
+  struct direntry *de;
+  char dbuf[512];
+  int dn, doff;
+  char path[32];
+
+  if((fd = open("/proc", 0)) < 0){
+    fprintf(2, "ps: cannot open /proc\n");
+    exit(1);
+  }
+
+  while((dn = getdents(fd, dbuf, sizeof(dbuf))) > 0){
+    for(doff = 0; doff < dn; doff += de->reclen){
+      de = (struct direntry*)(dbuf + doff);
+      if(de->name[0] < '0' || de->name[0] > '9' || de->namelen > 10)
+        continue;
+      strcpy(path, "/proc/");
+      strcpy(path + 6, de->name);
+      strcpy(path + strlen(path), "/status");
+      if((sfd = open(path, 0)) < 0)
+        continue;  // the process exited meanwhile.
+      while((n = read(sfd, buf, sizeof(buf))) > 0)
+        write(1, buf, n);
+      close(sfd);
+      printf("\n");
+    }
+  }
+  close(fd);
+  exit(0);
+}
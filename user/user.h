@@ -1,5 +1,7 @@
 struct stat;
 struct rtcdate;
+struct timespec;
+struct timeval;
 
 // system calls
 int fork(void);
@@ -24,6 +26,32 @@ char* sbrk(int);
 int sleep(int);
 int uptime(void);
 int poweroff(int) __attribute__((noreturn));
+int getuid(void);
+int setuid(int);
+int chmod(const char*, int);
+int chown(const char*, int, int);
+int geteuid(void);
+int getgid(void);
+int setgid(int);
+int sandbox(uint64, char**);
+int symlink(const char*, const char*);
+int readlink(const char*, char*, int);
+int rename(const char*, const char*);
+int getdents(int, void*, int);
+int rmdir(const char*);
+int utimes(const char*, const uint*);
+int clock_gettime(int, struct timespec*);
+int gettimeofday(struct timeval*, void*);
+int lseek(int, int, int);
+int pread(int, void*, int, int);
+int pwrite(int, const void*, int, int);
+int truncate(const char*, int);
+int ftruncate(int, int);
+int fsync(int);
+int crash(int);
+int fsck(void);
+int mount(int, const char*);
+int umount(const char*);
 
 // ulib.c
 int stat(const char*, struct stat*);
@@ -4,6 +4,7 @@
 #include "user/user.h"
 #include "kernel/fs.h"
 #include "kernel/fcntl.h"
+#include "kernel/time.h"
 #include "kernel/syscall.h"
 #include "kernel/memlayout.h"
 #include "kernel/riscv.h"
@@ -580,7 +581,7 @@ writebig(char *s)
     exit(1);
   }
 
-  for(i = 0; i < MAXFILE; i++){
+  for(i = 0; i < NDIRECT + NINDIRECT; i++){
     ((int*)buf)[0] = i;
     if(write(fd, buf, BSIZE) != BSIZE){
       printf("%s: error: write big file failed\n", s, i);
@@ -600,7 +601,7 @@ writebig(char *s)
   for(;;){
     i = read(fd, buf, BSIZE);
     if(i == 0){
-      if(n == MAXFILE - 1){
+      if(n != NDIRECT + NINDIRECT){
         printf("%s: read only %d blocks from big", s, n);
         exit(1);
       }
@@ -1390,10 +1391,9 @@ concreate(char *s)
   char file[3];
   int i, pid, n, fd;
   char fa[N];
-  struct {
-    ushort inum;
-    char name[DIRSIZ];
-  } de;
+  struct direntry *de;
+  char dbuf[512];
+  int dn, doff;
 
   file[0] = 'C';
   file[2] = '\0';
@@ -1426,21 +1426,22 @@ concreate(char *s)
   memset(fa, 0, sizeof(fa));
   fd = open(".", 0);
   n = 0;
-  while(read(fd, &de, sizeof(de)) > 0){
-    if(de.inum == 0)
-      continue;
-    if(de.name[0] == 'C' && de.name[2] == '\0'){
-      i = de.name[1] - '0';
-      if(i < 0 || i >= sizeof(fa)){
-        printf("%s: concreate weird file %s\n", s, de.name);
-        exit(1);
-      }
-      if(fa[i]){
-        printf("%s: concreate duplicate file %s\n", s, de.name);
-        exit(1);
+  while((dn = getdents(fd, dbuf, sizeof(dbuf))) > 0){
+    for(doff = 0; doff < dn; doff += de->reclen){
+      de = (struct direntry*)(dbuf + doff);
+      if(de->name[0] == 'C' && de->name[2] == '\0'){
+        i = de->name[1] - '0';
+        if(i < 0 || i >= sizeof(fa)){
+          printf("%s: concreate weird file %s\n", s, de->name);
+          exit(1);
+        }
+        if(fa[i]){
+          printf("%s: concreate duplicate file %s\n", s, de->name);
+          exit(1);
+        }
+        fa[i] = 1;
+        n++;
       }
-      fa[i] = 1;
-      n++;
     }
   }
   close(fd);
@@ -1865,12 +1866,17 @@ bigfile(char *s)
   unlink("bigfile.dat");
 }
 
+// names longer than the old 14-byte limit are kept intact,
+// and names longer than DIRSIZ are rejected rather than truncated.
 void
-fourteen(char *s)
+longname(char *s)
 {
-  int fd;
-
-  // DIRSIZ is 14.
+  enum { N = 20 };
+  int fd, i, n;
+  char name[DIRSIZ+2];
+  struct direntry *de;
+  char dbuf[512];
+  int dn, doff;
 
   if(mkdir("12345678901234") != 0){
     printf("%s: mkdir 12345678901234 failed\n", s);
@@ -1880,35 +1886,117 @@ fourteen(char *s)
     printf("%s: mkdir 12345678901234/123456789012345 failed\n", s);
     exit(1);
   }
-  fd = open("123456789012345/123456789012345/123456789012345", O_CREATE);
-  if(fd < 0){
-    printf("%s: create 123456789012345/123456789012345/123456789012345 failed\n", s);
+  // a 15-byte name is not the same as its 14-byte prefix.
+  if(open("12345678901234/12345678901234", 0) >= 0){
+    printf("%s: 12345678901234/12345678901234 exists\n", s);
     exit(1);
   }
-  close(fd);
-  fd = open("12345678901234/12345678901234/12345678901234", 0);
+  if(mkdir("12345678901234/12345678901234") != 0){
+    printf("%s: mkdir 12345678901234/12345678901234 failed\n", s);
+    exit(1);
+  }
+  if(unlink("12345678901234/12345678901234") != 0 ||
+     unlink("12345678901234/123456789012345") != 0 ||
+     unlink("12345678901234") != 0){
+    printf("%s: unlink failed\n", s);
+    exit(1);
+  }
+
+  if(mkdir("longdir") != 0 || chdir("longdir") != 0){
+    printf("%s: mkdir longdir failed\n", s);
+    exit(1);
+  }
+
+  // the longest possible name.
+  memset(name, 'a', DIRSIZ);
+  name[DIRSIZ] = '\0';
+  fd = open(name, O_CREATE | O_RDWR);
   if(fd < 0){
-    printf("%s: open 12345678901234/12345678901234/12345678901234 failed\n", s);
+    printf("%s: create %d-byte name failed\n", s, DIRSIZ);
+    exit(1);
+  }
+  if(write(fd, "x", 1) != 1){
+    printf("%s: write failed\n", s);
     exit(1);
   }
   close(fd);
 
-  if(mkdir("12345678901234/12345678901234") == 0){
-    printf("%s: mkdir 12345678901234/12345678901234 succeeded!\n", s);
+  // one byte too long.
+  name[DIRSIZ] = 'a';
+  name[DIRSIZ+1] = '\0';
+  if(open(name, O_CREATE | O_RDWR) >= 0){
+    printf("%s: create %d-byte name succeeded!\n", s, DIRSIZ+1);
+    exit(1);
+  }
+  if(open(name, 0) >= 0){
+    printf("%s: open %d-byte name succeeded!\n", s, DIRSIZ+1);
     exit(1);
   }
-  if(mkdir("123456789012345/12345678901234") == 0){
-    printf("%s: mkdir 12345678901234/123456789012345 succeeded!\n", s);
+
+  // names of many lengths, some removed and re-added so that
+  // freed directory space gets reused.
+  for(i = 0; i < N; i++){
+    memset(name, 'a' + i, 10 * i + 1);
+    name[10 * i + 1] = '\0';
+    fd = open(name, O_CREATE | O_RDWR);
+    if(fd < 0){
+      printf("%s: create %s failed\n", s, name);
+      exit(1);
+    }
+    close(fd);
+  }
+  for(i = 0; i < N; i += 2){
+    memset(name, 'a' + i, 10 * i + 1);
+    name[10 * i + 1] = '\0';
+    if(unlink(name) != 0){
+      printf("%s: unlink %s failed\n", s, name);
+      exit(1);
+    }
+  }
+  for(i = 0; i < N; i += 4){
+    memset(name, 'a' + i, 10 * i + 1);
+    name[10 * i + 1] = '\0';
+    fd = open(name, O_CREATE | O_RDWR);
+    if(fd < 0){
+      printf("%s: re-create %s failed\n", s, name);
+      exit(1);
+    }
+    close(fd);
+  }
+
+  fd = open(".", 0);
+  n = 0;
+  while((dn = getdents(fd, dbuf, sizeof(dbuf))) > 0){
+    for(doff = 0; doff < dn; doff += de->reclen){
+      de = (struct direntry*)(dbuf + doff);
+      if(strcmp(de->name, ".") == 0 || strcmp(de->name, "..") == 0)
+        continue;
+      if(de->namelen != strlen(de->name)){
+        printf("%s: bad name length for %s\n", s, de->name);
+        exit(1);
+      }
+      n++;
+    }
+  }
+  close(fd);
+  if(n != 1 + N / 2 + N / 4){
+    printf("%s: found %d entries, expected %d\n", s, n, 1 + N / 2 + N / 4);
     exit(1);
   }
 
   // clean up
-  unlink("123456789012345/12345678901234");
-  unlink("12345678901234/12345678901234");
-  unlink("12345678901234/12345678901234/12345678901234");
-  unlink("123456789012345/123456789012345/123456789012345");
-  unlink("12345678901234/123456789012345");
-  unlink("12345678901234");
+  memset(name, 'a', DIRSIZ);
+  name[DIRSIZ] = '\0';
+  unlink(name);
+  for(i = 0; i < N; i++){
+    memset(name, 'a' + i, 10 * i + 1);
+    name[10 * i + 1] = '\0';
+    unlink(name);
+  }
+  if(chdir("..") != 0 || unlink("longdir") != 0){
+    printf("%s: unlink longdir failed\n", s);
+    exit(1);
+  }
 }
 
 void
@@ -2655,6 +2743,1931 @@ execout(char *s)
 }
 
 //
+// can a process find itself in /proc?
+void
+procfs(char *s)
+{
+  int fd, n, pid, found;
+  char path[32], buf[64];
+  struct direntry *de;
+  char dbuf[512];
+  int dn, doff;
+  struct stat st, st2;
+
+  pid = getpid();
+  found = 0;
+  fd = open("/proc", 0);
+  if(fd < 0){
+    printf("%s: open /proc failed\n", s);
+    exit(1);
+  }
+  while((dn = getdents(fd, dbuf, sizeof(dbuf))) > 0){
+    for(doff = 0; doff < dn; doff += de->reclen){
+      de = (struct direntry*)(dbuf + doff);
+      if(atoi(de->name) == pid)
+        found = 1;
+    }
+  }
+  close(fd);
+  if(!found){
+    printf("%s: pid %d not in /proc\n", s, pid);
+    exit(1);
+  }
+
+  // build "/proc/<pid>/name".
+  n = 0;
+  for(int x = pid; x > 0; x /= 10)
+    buf[n++] = '0' + x % 10;
+  strcpy(path, "/proc/");
+  for(int i = 0; i < n; i++)
+    path[6 + i] = buf[n - 1 - i];
+  strcpy(path + 6 + n, "/name");
+  fd = open(path, 0);
+  if(fd < 0){
+    printf("%s: open %s failed\n", s, path);
+    exit(1);
+  }
+  if(fstat(fd, &st) < 0 || st.type != T_FILE){
+    printf("%s: fstat %s failed\n", s, path);
+    exit(1);
+  }
+  n = read(fd, buf, sizeof(buf) - 1);
+  close(fd);
+  if(n != 10 || memcmp(buf, "usertests\n", 10) != 0){
+    printf("%s: wrong name in %s\n", s, path);
+    exit(1);
+  }
+
+  if(open("/proc/meminfo", O_RDWR) >= 0){
+    printf("%s: open /proc/meminfo for writing succeeded!\n", s);
+    exit(1);
+  }
+  if(open("/proc/0/status", 0) >= 0){
+    printf("%s: open /proc/0/status succeeded!\n", s);
+    exit(1);
+  }
+  if(chmod("/proc/meminfo", 0666) == 0 || unlink("/proc/uptime") == 0 ||
+     mkdir("/proc/x") == 0){
+    printf("%s: changed /proc\n", s);
+    exit(1);
+  }
+
+  // /proc is a file system mounted on /proc, so it can be reached like
+  // any directory.
+  if(stat("/proc", &st) < 0 || stat("/", &st2) < 0 || st.dev == st2.dev){
+    printf("%s: no procfs on /proc\n", s);
+    exit(1);
+  }
+  if(chdir("/proc") < 0){
+    printf("%s: chdir /proc failed\n", s);
+    exit(1);
+  }
+  fd = open("meminfo", O_RDONLY);
+  n = fd < 0 ? -1 : read(fd, buf, 6);
+  close(fd);
+  if(n != 6 || memcmp(buf, "total:", 6) != 0 || (fd = open("../proc/uptime", O_RDONLY)) < 0 ||
+     stat("..", &st) < 0 || st.ino != st2.ino || chdir("/") < 0){
+    printf("%s: relative paths in /proc failed\n", s);
+    exit(1);
+  }
+  close(fd);
+}
+
+// do file permissions keep an ordinary user out?
+void
+permtest(char *s)
+{
+  int fd, pid, xstatus;
+  struct stat st;
+
+  fd = open("permfile", O_CREATE|O_RDWR);
+  if(fd < 0){
+    printf("%s: create permfile failed\n", s);
+    exit(1);
+  }
+  close(fd);
+  if(chmod("permfile", 0600) != 0){
+    printf("%s: chmod permfile failed\n", s);
+    exit(1);
+  }
+  if(stat("permfile", &st) < 0 || st.mode != 0600 || st.uid != 0){
+    printf("%s: wrong mode or owner of permfile\n", s);
+    exit(1);
+  }
+
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    if(setuid(1) != 0 || getuid() != 1){
+      printf("%s: setuid(1) failed\n", s);
+      exit(1);
+    }
+    if(setuid(0) == 0){
+      printf("%s: setuid(0) by an ordinary user succeeded!\n", s);
+      exit(1);
+    }
+    if(open("permfile", O_RDONLY) >= 0){
+      printf("%s: open of a 0600 file succeeded!\n", s);
+      exit(1);
+    }
+    if(chmod("permfile", 0666) == 0){
+      printf("%s: chmod by a non-owner succeeded!\n", s);
+      exit(1);
+    }
+    if(unlink("permfile") == 0){
+      printf("%s: unlink in a 0755 directory succeeded!\n", s);
+      exit(1);
+    }
+    if(open("permfile2", O_CREATE|O_RDWR) >= 0){
+      printf("%s: create in a 0755 directory succeeded!\n", s);
+      exit(1);
+    }
+    exit(0);
+  }
+  wait(&xstatus);
+
+  if(chown("permfile", 1, 1) != 0){
+    printf("%s: chown permfile failed\n", s);
+    exit(1);
+  }
+  pid = fork();
+  if(pid == 0){
+    setuid(1);
+    fd = open("permfile", O_RDWR);
+    if(fd < 0){
+      printf("%s: owner cannot open permfile\n", s);
+      exit(1);
+    }
+    close(fd);
+    exit(0);
+  }
+  wait(&xstatus);
+  unlink("permfile");
+  exit(xstatus);
+}
+
+// does exec of a setuid executable run with its owner's privileges?
+void
+setuidtest(char *s)
+{
+  int fd, fd2, n, pid, xstatus, fds[2];
+  struct stat st;
+  char *args[] = { "suidcat", "secret", 0 };
+
+  // make a setuid-root copy of cat.
+  fd = open("cat", O_RDONLY);
+  fd2 = open("suidcat", O_CREATE|O_WRONLY);
+  if(fd < 0 || fd2 < 0){
+    printf("%s: cannot copy cat\n", s);
+    exit(1);
+  }
+  while((n = read(fd, buf, sizeof(buf))) > 0){
+    if(write(fd2, buf, n) != n){
+      printf("%s: write suidcat failed\n", s);
+      exit(1);
+    }
+  }
+  close(fd);
+  close(fd2);
+  if(chmod("suidcat", 04755) != 0){
+    printf("%s: chmod suidcat failed\n", s);
+    exit(1);
+  }
+
+  fd = open("secret", O_CREATE|O_WRONLY);
+  if(fd < 0 || write(fd, "xyzzy", 5) != 5){
+    printf("%s: create secret failed\n", s);
+    exit(1);
+  }
+  close(fd);
+  chmod("secret", 0600);
+
+  if(pipe(fds) != 0){
+    printf("%s: pipe failed\n", s);
+    exit(1);
+  }
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    close(fds[0]);
+    if(setgid(1) != 0 || setuid(1) != 0 || geteuid() != 1 || getgid() != 1){
+      printf("%s: dropping privileges failed\n", s);
+      exit(1);
+    }
+    if(open("secret", O_RDONLY) >= 0){
+      printf("%s: open of a 0600 file succeeded!\n", s);
+      exit(1);
+    }
+    close(1);
+    dup(fds[1]);
+    close(fds[1]);
+    exec("suidcat", args);
+    printf("%s: exec suidcat failed\n", s);
+    exit(1);
+  }
+  close(fds[1]);
+  n = read(fds[0], buf, sizeof(buf));
+  close(fds[0]);
+  wait(&xstatus);
+  if(xstatus != 0 || n != 5 || memcmp(buf, "xyzzy", 5) != 0){
+    printf("%s: setuid cat could not read the secret\n", s);
+    exit(1);
+  }
+
+  // chown clears the setuid bit.
+  chown("suidcat", 1, 1);
+  if(stat("suidcat", &st) < 0 || (st.mode & 04000) != 0){
+    printf("%s: chown kept the setuid bit\n", s);
+    exit(1);
+  }
+
+  unlink("suidcat");
+  unlink("secret");
+}
+
+// can a process confine itself and its children?
+void
+sandboxtest(char *s)
+{
+  int fd, pid, xstatus;
+  uint64 basic = (1ULL << SYS_exit) | (1ULL << SYS_write) | (1ULL << SYS_fork) |
+    (1ULL << SYS_wait) | (1ULL << SYS_open) | (1ULL << SYS_close) |
+    (1ULL << SYS_sandbox);
+  char *prefixes[] = { "/sbox", 0 };
+  char *wider[] = { "/", 0 };
+
+  // a forbidden system call kills the process, and its children inherit the restriction.
+  pid = fork();
+  if(pid == 0){
+    if(sandbox(basic, 0) != 0){
+      printf("%s: sandbox failed\n", s);
+      exit(1);
+    }
+    if(sandbox(~0ULL, 0) != 0){
+      printf("%s: sandbox failed\n", s);
+      exit(1);
+    }
+    pid = fork();
+    if(pid == 0){
+      getpid();
+      exit(0);
+    }
+    wait(&xstatus);
+    if(xstatus != -1)
+      exit(2);
+    getpid();
+    exit(0);
+  }
+  wait(&xstatus);
+  if(xstatus == 2){
+    printf("%s: child of a sandboxed process was not confined\n", s);
+    exit(1);
+  }
+  if(xstatus != -1){
+    printf("%s: forbidden system call was not stopped\n", s);
+    exit(1);
+  }
+
+  // only paths under the given prefixes may be looked up.
+  if(mkdir("sbox") != 0){
+    printf("%s: mkdir sbox failed\n", s);
+    exit(1);
+  }
+  pid = fork();
+  if(pid == 0){
+    if(sandbox(~0ULL, prefixes) != 0){
+      printf("%s: sandbox failed\n", s);
+      exit(1);
+    }
+    if(sandbox(~0ULL, wider) == 0){
+      printf("%s: sandbox widened its paths!\n", s);
+      exit(1);
+    }
+    fd = open("/sbox/./file", O_CREATE|O_RDWR);
+    if(fd < 0){
+      printf("%s: open inside the sandbox failed\n", s);
+      exit(1);
+    }
+    close(fd);
+    if(unlink("/sbox/file") != 0){
+      printf("%s: unlink inside the sandbox failed\n", s);
+      exit(1);
+    }
+    open("/sbox/../README", O_RDONLY);
+    exit(0);
+  }
+  wait(&xstatus);
+  unlink("sbox");
+  if(xstatus != -1){
+    printf("%s: lookup outside the sandbox was not stopped\n", s);
+    exit(1);
+  }
+}
+
+// can exec run #! scripts, and does it reject bad ones?
+void
+scripttest(char *s)
+{
+  int fd, n, pid, xstatus, fds[2];
+  char *args[] = { "script1", "a", "b", 0 };
+  char *expected = "hello script1 a b\n";
+  struct {
+    char *name;
+    char *text;
+  } bad[] = {
+    { "script2", "#!/script2\n" },   // recursion
+    { "script3", "#!/nonexistent\n" },
+    { "script4", "#!\n" },
+  };
+
+  fd = open("script1", O_CREATE|O_WRONLY);
+  if(fd < 0 || write(fd, "#! /echo  hello \n", 17) != 17){
+    printf("%s: create script1 failed\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  // not executable yet.
+  if(exec("script1", args) != -1){
+    printf("%s: exec of a non-executable script succeeded!\n", s);
+    exit(1);
+  }
+  chmod("script1", 0755);
+
+  if(pipe(fds) != 0){
+    printf("%s: pipe failed\n", s);
+    exit(1);
+  }
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  if(pid == 0){
+    close(fds[0]);
+    close(1);
+    dup(fds[1]);
+    close(fds[1]);
+    exec("script1", args);
+    exit(2);
+  }
+  close(fds[1]);
+  n = 0;
+  while(n < sizeof(buf) && read(fds[0], buf+n, 1) == 1)
+    n++;
+  close(fds[0]);
+  wait(&xstatus);
+  unlink("script1");
+  if(xstatus != 0 || n != strlen(expected) || memcmp(buf, expected, n) != 0){
+    printf("%s: script1 did not run echo as expected\n", s);
+    exit(1);
+  }
+
+  for(int i = 0; i < sizeof(bad)/sizeof(bad[0]); i++){
+    fd = open(bad[i].name, O_CREATE|O_WRONLY);
+    if(fd < 0 || write(fd, bad[i].text, strlen(bad[i].text)) != strlen(bad[i].text)){
+      printf("%s: create %s failed\n", s, bad[i].name);
+      exit(1);
+    }
+    close(fd);
+    chmod(bad[i].name, 0755);
+    args[0] = bad[i].name;
+    if(exec(bad[i].name, args) != -1){
+      printf("%s: exec %s succeeded!\n", s, bad[i].name);
+      exit(1);
+    }
+    unlink(bad[i].name);
+  }
+}
+
+// write and read back a file that needs doubly-indirect blocks.
+void
+hugefile(char *s)
+{
+  int fd, i, n, nblocks;
+
+  nblocks = 2 * 1024 * 1024 / BSIZE;
+  fd = open("huge", O_CREATE|O_RDWR);
+  if(fd < 0){
+    printf("%s: create huge failed\n", s);
+    exit(1);
+  }
+  for(i = 0; i < nblocks; i++){
+    memset(buf, i, BSIZE);
+    ((int*)buf)[0] = i;
+    if(write(fd, buf, BSIZE) != BSIZE){
+      printf("%s: write huge block %d failed\n", s, i);
+      exit(1);
+    }
+  }
+  close(fd);
+
+  fd = open("huge", O_RDONLY);
+  if(fd < 0){
+    printf("%s: open huge failed\n", s);
+    exit(1);
+  }
+  for(n = 0; ; n++){
+    i = read(fd, buf, BSIZE);
+    if(i == 0)
+      break;
+    if(i != BSIZE){
+      printf("%s: read huge failed %d\n", s, i);
+      exit(1);
+    }
+    if(((int*)buf)[0] != n || buf[BSIZE-1] != (char)n){
+      printf("%s: wrong content of block %d\n", s, n);
+      exit(1);
+    }
+  }
+  close(fd);
+  if(n != nblocks){
+    printf("%s: read %d blocks of huge, expected %d\n", s, n, nblocks);
+    exit(1);
+  }
+
+  if(unlink("huge") < 0){
+    printf("%s: unlink huge failed\n", s);
+    exit(1);
+  }
+}
+
+// grow two files block by block at the same time, so that
+// neither gets contiguous disk blocks, and read them back.
+void
+interleave(char *s)
+{
+  int fds[2], i, j, n;
+  char *names[] = { "interleave0", "interleave1" };
+
+  for(j = 0; j < 2; j++){
+    fds[j] = open(names[j], O_CREATE|O_RDWR);
+    if(fds[j] < 0){
+      printf("%s: create %s failed\n", s, names[j]);
+      exit(1);
+    }
+  }
+  for(i = 0; i < 100; i++){
+    for(j = 0; j < 2; j++){
+      memset(buf, 2*i + j, BSIZE);
+      if(write(fds[j], buf, BSIZE) != BSIZE){
+        printf("%s: write %s failed\n", s, names[j]);
+        exit(1);
+      }
+    }
+  }
+  for(j = 0; j < 2; j++){
+    close(fds[j]);
+    fds[j] = open(names[j], O_RDONLY);
+    for(i = 0; i < 100; i++){
+      n = read(fds[j], buf, BSIZE);
+      if(n != BSIZE || buf[0] != (char)(2*i + j) || buf[BSIZE-1] != (char)(2*i + j)){
+        printf("%s: wrong content of %s block %d\n", s, names[j], i);
+        exit(1);
+      }
+    }
+    close(fds[j]);
+    unlink(names[j]);
+  }
+}
+
+// a directory large enough to be indexed still finds, lists and
+// removes all of its entries.
+void
+dirindex(char *s)
+{
+  enum { N = 1000 };
+  int i, fd, n;
+  char name[32];
+  struct direntry *de;
+  char dbuf[512];
+  int dn, doff;
+
+  if(mkdir("dx") != 0 || chdir("dx") != 0){
+    printf("%s: mkdir dx failed\n", s);
+    exit(1);
+  }
+
+  for(i = 0; i < N; i++){
+    name[0] = 'f';
+    name[1] = '0' + i / 100;
+    name[2] = '0' + (i / 10) % 10;
+    name[3] = '0' + i % 10;
+    // vary the lengths of the names.
+    memset(name + 4, 'z', i % 20);
+    name[4 + i % 20] = '\0';
+    fd = open(name, O_CREATE | O_RDWR);
+    if(fd < 0){
+      printf("%s: create %s failed\n", s, name);
+      exit(1);
+    }
+    close(fd);
+  }
+
+  for(i = 0; i < N; i += 2){
+    name[0] = 'f';
+    name[1] = '0' + i / 100;
+    name[2] = '0' + (i / 10) % 10;
+    name[3] = '0' + i % 10;
+    memset(name + 4, 'z', i % 20);
+    name[4 + i % 20] = '\0';
+    if(unlink(name) != 0){
+      printf("%s: unlink %s failed\n", s, name);
+      exit(1);
+    }
+  }
+
+  for(i = 0; i < N; i++){
+    name[0] = 'f';
+    name[1] = '0' + i / 100;
+    name[2] = '0' + (i / 10) % 10;
+    name[3] = '0' + i % 10;
+    memset(name + 4, 'z', i % 20);
+    name[4 + i % 20] = '\0';
+    fd = open(name, 0);
+    if((i % 2 == 0 && fd >= 0) || (i % 2 == 1 && fd < 0)){
+      printf("%s: open %s returned %d\n", s, name, fd);
+      exit(1);
+    }
+    if(fd >= 0)
+      close(fd);
+  }
+
+  fd = open(".", 0);
+  n = 0;
+  while((dn = getdents(fd, dbuf, sizeof(dbuf))) > 0){
+    for(doff = 0; doff < dn; doff += de->reclen){
+      de = (struct direntry*)(dbuf + doff);
+      if(strcmp(de->name, ".") != 0 && strcmp(de->name, "..") != 0)
+        n++;
+    }
+  }
+  close(fd);
+  if(n != N / 2){
+    printf("%s: listed %d entries, expected %d\n", s, n, N / 2);
+    exit(1);
+  }
+
+  fd = open("..", 0);
+  if(fd < 0){
+    printf("%s: .. missing\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  for(i = 1; i < N; i += 2){
+    name[0] = 'f';
+    name[1] = '0' + i / 100;
+    name[2] = '0' + (i / 10) % 10;
+    name[3] = '0' + i % 10;
+    memset(name + 4, 'z', i % 20);
+    name[4 + i % 20] = '\0';
+    if(unlink(name) != 0){
+      printf("%s: unlink %s failed\n", s, name);
+      exit(1);
+    }
+  }
+  if(chdir("..") != 0 || unlink("dx") != 0){
+    printf("%s: unlink dx failed\n", s);
+    exit(1);
+  }
+}
+
+// symbolic links to files and directories, dangling links,
+// loops, O_NOFOLLOW, readlink, and links out of a sandbox.
+void
+symlinktest(char *s)
+{
+  int fd, pid, xstatus;
+  char buf[16];
+  char *prefixes[] = { "/sl", 0 };
+
+  if(mkdir("/sl") != 0){
+    printf("%s: mkdir /sl failed\n", s);
+    exit(1);
+  }
+  fd = open("/sl/f", O_CREATE | O_RDWR);
+  if(fd < 0 || write(fd, "hello", 5) != 5){
+    printf("%s: create /sl/f failed\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  // a relative target is looked up in the link's directory.
+  if(symlink("f", "/sl/l") != 0){
+    printf("%s: symlink failed\n", s);
+    exit(1);
+  }
+  if(symlink("g", "/sl/l") == 0){
+    printf("%s: symlink over an existing name succeeded!\n", s);
+    exit(1);
+  }
+  fd = open("/sl/l", O_RDONLY);
+  if(fd < 0 || read(fd, buf, sizeof(buf)) != 5 || memcmp(buf, "hello", 5) != 0){
+    printf("%s: read through symlink failed\n", s);
+    exit(1);
+  }
+  close(fd);
+  if(readlink("/sl/l", buf, sizeof(buf)) != 1 || buf[0] != 'f'){
+    printf("%s: readlink failed\n", s);
+    exit(1);
+  }
+  if(readlink("/sl/f", buf, sizeof(buf)) >= 0){
+    printf("%s: readlink of a file succeeded!\n", s);
+    exit(1);
+  }
+
+  // O_NOFOLLOW refuses to open a symbolic link.
+  if(open("/sl/l", O_RDONLY | O_NOFOLLOW) >= 0){
+    printf("%s: open with O_NOFOLLOW succeeded!\n", s);
+    exit(1);
+  }
+  fd = open("/sl/f", O_RDONLY | O_NOFOLLOW);
+  if(fd < 0){
+    printf("%s: open of a file with O_NOFOLLOW failed\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  // links to directories can be used in the middle of a path.
+  if(symlink("/sl", "/sld") != 0){
+    printf("%s: symlink to a directory failed\n", s);
+    exit(1);
+  }
+  fd = open("/sld/l", O_RDONLY);
+  if(fd < 0){
+    printf("%s: open /sld/l failed\n", s);
+    exit(1);
+  }
+  close(fd);
+  if(chdir("/sld") != 0 || (fd = open("f", O_RDONLY)) < 0 || chdir("/") != 0){
+    printf("%s: chdir through symlink failed\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  // dangling links and loops.
+  if(symlink("nonexistent", "/sl/d") != 0){
+    printf("%s: symlink to a missing file failed\n", s);
+    exit(1);
+  }
+  if(open("/sl/d", O_RDONLY) >= 0 || open("/sl/d", O_CREATE | O_RDWR) >= 0){
+    printf("%s: open of a dangling link succeeded!\n", s);
+    exit(1);
+  }
+  if(symlink("l2", "/sl/l1") != 0 || symlink("l1", "/sl/l2") != 0){
+    printf("%s: symlink failed\n", s);
+    exit(1);
+  }
+  if(open("/sl/l1", O_RDONLY) >= 0){
+    printf("%s: open of a symlink loop succeeded!\n", s);
+    exit(1);
+  }
+
+  // a sandboxed process may not follow a link out of its sandbox.
+  // only links with absolute targets can be checked.
+  if(symlink("/sl/f", "/sl/in") != 0 || symlink("/README", "/sl/out") != 0){
+    printf("%s: symlink failed\n", s);
+    exit(1);
+  }
+  pid = fork();
+  if(pid == 0){
+    if(sandbox(~0ULL, prefixes) != 0){
+      printf("%s: sandbox failed\n", s);
+      exit(1);
+    }
+    fd = open("/sl/in", O_RDONLY);
+    if(fd < 0){
+      printf("%s: open in the sandbox failed\n", s);
+      exit(1);
+    }
+    close(fd);
+    open("/sl/out", O_RDONLY);
+    exit(0);
+  }
+  wait(&xstatus);
+  if(xstatus != -1){
+    printf("%s: symlink escaped the sandbox\n", s);
+    exit(1);
+  }
+
+  // unlink removes the link, not its target.
+  if(unlink("/sl/l") != 0 || open("/sl/l", O_RDONLY) >= 0){
+    printf("%s: unlink of a symlink failed\n", s);
+    exit(1);
+  }
+  fd = open("/sl/f", O_RDONLY);
+  if(fd < 0){
+    printf("%s: unlink of a symlink removed its target\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  unlink("/sl/in");
+  unlink("/sl/out");
+  unlink("/sl/l1");
+  unlink("/sl/l2");
+  unlink("/sl/d");
+  unlink("/sl/f");
+  unlink("/sld");
+  unlink("/sl");
+}
+
+// rename files and directories, within and across directories.
+void
+renametest(char *s)
+{
+  int fd;
+  struct stat st;
+  char buf[8];
+
+  if(mkdir("rn") != 0 || mkdir("rn/a") != 0 || mkdir("rn/b") != 0){
+    printf("%s: mkdir failed\n", s);
+    exit(1);
+  }
+  fd = open("rn/f", O_CREATE | O_RDWR);
+  if(fd < 0 || write(fd, "f", 1) != 1){
+    printf("%s: create rn/f failed\n", s);
+    exit(1);
+  }
+  close(fd);
+  fd = open("rn/g", O_CREATE | O_RDWR);
+  if(fd < 0 || write(fd, "g", 1) != 1){
+    printf("%s: create rn/g failed\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  // within a directory, and over an existing file.
+  if(rename("rn/f", "rn/h") != 0 || open("rn/f", 0) >= 0){
+    printf("%s: rename rn/f rn/h failed\n", s);
+    exit(1);
+  }
+  if(rename("rn/h", "rn/g") != 0 || open("rn/h", 0) >= 0){
+    printf("%s: rename over rn/g failed\n", s);
+    exit(1);
+  }
+  fd = open("rn/g", 0);
+  if(fd < 0 || read(fd, buf, 1) != 1 || buf[0] != 'f' || fstat(fd, &st) < 0 || st.nlink != 1){
+    printf("%s: rn/g has the wrong contents\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  // a directory across directories updates "..".
+  if(rename("rn/a", "rn/b/a") != 0){
+    printf("%s: rename rn/a rn/b/a failed\n", s);
+    exit(1);
+  }
+  if(chdir("rn/b/a/..") != 0 || open("a", 0) < 0 || chdir("/") != 0){
+    printf("%s: .. not updated\n", s);
+    exit(1);
+  }
+  if(stat("rn", &st) < 0 || st.nlink != 2 || stat("rn/b", &st) < 0 || st.nlink != 2){
+    printf("%s: wrong directory link counts\n", s);
+    exit(1);
+  }
+
+  // not below itself.
+  if(rename("rn/b", "rn/b/a/c") == 0 || rename("rn/b", "rn/b/c") == 0){
+    printf("%s: moved a directory below itself!\n", s);
+    exit(1);
+  }
+
+  // a directory may only replace an empty directory, and a file only a file.
+  if(rename("rn/g", "rn/b") == 0 || rename("rn/b", "rn/g") == 0){
+    printf("%s: renamed between file and directory!\n", s);
+    exit(1);
+  }
+  if(mkdir("rn/e") != 0){
+    printf("%s: mkdir rn/e failed\n", s);
+    exit(1);
+  }
+  if(rename("rn/e", "rn/b") == 0){
+    printf("%s: replaced a non-empty directory!\n", s);
+    exit(1);
+  }
+  if(rename("rn/b/a", "rn/e") != 0 || open("rn/b/a", 0) >= 0 || open("rn/e", 0) < 0){
+    printf("%s: replace an empty directory failed\n", s);
+    exit(1);
+  }
+  if(rename("rn/b", "rn") == 0 || rename("rn/b", "rn/b/..") == 0){
+    printf("%s: replaced an ancestor!\n", s);
+    exit(1);
+  }
+
+  // both names of the same file.
+  if(link("rn/g", "rn/g2") != 0 || rename("rn/g", "rn/g2") != 0 || open("rn/g", 0) < 0){
+    printf("%s: rename of a file to itself failed\n", s);
+    exit(1);
+  }
+
+  if(unlink("rn/g") != 0 || unlink("rn/g2") != 0 || unlink("rn/e") != 0 ||
+     unlink("rn/b") != 0 || unlink("rn") != 0){
+    printf("%s: cleanup failed\n", s);
+    exit(1);
+  }
+}
+
+// rmdir only removes empty directories, directories cannot be read(),
+// and getdents() lists their entries.
+void
+getdentstest(char *s)
+{
+  int fd, n, off, found;
+  char buf[512];
+  struct direntry *de;
+
+  if(mkdir("gd") != 0 || mkdir("gd/sub") != 0){
+    printf("%s: mkdir gd failed\n", s);
+    exit(1);
+  }
+  fd = open("gd/file", O_CREATE | O_RDWR);
+  if(fd < 0){
+    printf("%s: create gd/file failed\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  if(rmdir("gd/file") == 0){
+    printf("%s: rmdir of a file succeeded\n", s);
+    exit(1);
+  }
+  if(rmdir("gd") == 0){
+    printf("%s: rmdir of a non-empty directory succeeded\n", s);
+    exit(1);
+  }
+
+  fd = open("gd", 0);
+  if(fd < 0){
+    printf("%s: open gd failed\n", s);
+    exit(1);
+  }
+  if(read(fd, buf, sizeof(buf)) >= 0){
+    printf("%s: read of a directory succeeded\n", s);
+    exit(1);
+  }
+  if(getdents(fd, buf, 8) >= 0){
+    printf("%s: getdents into a tiny buffer succeeded\n", s);
+    exit(1);
+  }
+  found = 0;
+  while((n = getdents(fd, buf, sizeof(buf))) > 0){
+    for(off = 0; off < n; off += de->reclen){
+      de = (struct direntry*)(buf + off);
+      if(de->namelen != strlen(de->name) || de->reclen < sizeof(*de) + de->namelen + 1){
+        printf("%s: bad entry %s\n", s, de->name);
+        exit(1);
+      }
+      if(strcmp(de->name, ".") == 0)
+        found |= 1;
+      else if(strcmp(de->name, "..") == 0)
+        found |= 2;
+      else if(strcmp(de->name, "sub") == 0)
+        found |= 4;
+      else if(strcmp(de->name, "file") == 0)
+        found |= 8;
+      else {
+        printf("%s: unexpected entry %s\n", s, de->name);
+        exit(1);
+      }
+    }
+  }
+  close(fd);
+  if(n < 0 || found != 15){
+    printf("%s: getdents returned %d, found %x\n", s, n, found);
+    exit(1);
+  }
+
+  fd = open("gd/file", O_RDONLY);
+  if(fd < 0 || getdents(fd, buf, sizeof(buf)) >= 0){
+    printf("%s: getdents of a file succeeded\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  if(rmdir("gd/sub") != 0){
+    printf("%s: rmdir gd/sub failed\n", s);
+    exit(1);
+  }
+  if(open("gd/sub", 0) >= 0){
+    printf("%s: gd/sub still exists\n", s);
+    exit(1);
+  }
+  if(unlink("gd/file") != 0 || rmdir("gd") != 0){
+    printf("%s: rmdir gd failed\n", s);
+    exit(1);
+  }
+}
+
+// writes, reads, directory changes and utimes() update timestamps.
+void
+timestamps(char *s)
+{
+  int fd;
+  uint times[2];
+  struct stat st, st2;
+  char buf[8];
+
+  if(mkdir("ts") != 0){
+    printf("%s: mkdir ts failed\n", s);
+    exit(1);
+  }
+  fd = open("ts/f", O_CREATE | O_RDWR);
+  if(fd < 0 || fstat(fd, &st) < 0){
+    printf("%s: create ts/f failed\n", s);
+    exit(1);
+  }
+  if(st.crtime != st.mtime || st.crtime != st.ctime || st.crtime != st.atime){
+    printf("%s: new file has times %d %d %d %d\n", s, st.atime, st.mtime, st.ctime, st.crtime);
+    exit(1);
+  }
+
+  sleep(20);
+  if(write(fd, "x", 1) != 1 || fstat(fd, &st2) < 0){
+    printf("%s: write failed\n", s);
+    exit(1);
+  }
+  close(fd);
+  if(st2.mtime <= st.mtime || st2.ctime < st2.mtime || st2.crtime != st.crtime){
+    printf("%s: write did not update mtime\n", s);
+    exit(1);
+  }
+
+  fd = open("ts/f", O_RDONLY);
+  if(fd < 0 || read(fd, buf, sizeof(buf)) != 1 || fstat(fd, &st) < 0){
+    printf("%s: read failed\n", s);
+    exit(1);
+  }
+  close(fd);
+  if(st.atime < st2.mtime || st.mtime != st2.mtime){
+    printf("%s: read did not update atime\n", s);
+    exit(1);
+  }
+
+  if(stat("ts", &st) < 0 || st.mtime < st2.crtime){
+    printf("%s: creating ts/f did not update the mtime of ts\n", s);
+    exit(1);
+  }
+
+  times[0] = 100;
+  times[1] = 200;
+  if(utimes("ts/f", times) != 0 || stat("ts/f", &st) < 0){
+    printf("%s: utimes failed\n", s);
+    exit(1);
+  }
+  if(st.atime != 100 || st.mtime != 200 || st.ctime < st2.mtime){
+    printf("%s: utimes set %d %d\n", s, st.atime, st.mtime);
+    exit(1);
+  }
+  if(utimes("ts/f", 0) != 0 || stat("ts/f", &st) < 0 || st.mtime < st2.mtime){
+    printf("%s: utimes to now failed\n", s);
+    exit(1);
+  }
+  if(utimes("ts/nonexistent", 0) == 0){
+    printf("%s: utimes of a missing file succeeded\n", s);
+    exit(1);
+  }
+
+  unlink("ts/f");
+  rmdir("ts");
+}
+
+// the clocks tick, and agree with each other and with file timestamps.
+void
+clocktest(char *s)
+{
+  struct timespec ts, ts2;
+  struct timeval tv;
+  struct stat st;
+  int fd;
+
+  if(clock_gettime(CLOCK_REALTIME, &ts) < 0 || gettimeofday(&tv, 0) < 0){
+    printf("%s: reading the time failed\n", s);
+    exit(1);
+  }
+  // after 2020-01-01
+  if(ts.tv_sec < 1577836800 || ts.tv_nsec >= 1000000000 || tv.tv_usec >= 1000000){
+    printf("%s: bad time %l.%l\n", s, ts.tv_sec, ts.tv_nsec);
+    exit(1);
+  }
+  if(tv.tv_sec < ts.tv_sec || tv.tv_sec > ts.tv_sec + 1){
+    printf("%s: gettimeofday %l disagrees with clock_gettime %l\n", s, tv.tv_sec, ts.tv_sec);
+    exit(1);
+  }
+
+  if(clock_gettime(CLOCK_MONOTONIC, &ts) < 0){
+    printf("%s: clock_gettime(CLOCK_MONOTONIC) failed\n", s);
+    exit(1);
+  }
+  sleep(10);
+  if(clock_gettime(CLOCK_MONOTONIC, &ts2) < 0){
+    printf("%s: clock_gettime(CLOCK_MONOTONIC) failed\n", s);
+    exit(1);
+  }
+  if(ts2.tv_sec * 1000000000 + ts2.tv_nsec < ts.tv_sec * 1000000000 + ts.tv_nsec + 500000000){
+    printf("%s: monotonic clock did not advance over sleep(10)\n", s);
+    exit(1);
+  }
+  if(clock_gettime(2, &ts) == 0){
+    printf("%s: clock_gettime of a bad clock succeeded\n", s);
+    exit(1);
+  }
+
+  fd = open("clockfile", O_CREATE | O_RDWR);
+  if(fd < 0 || fstat(fd, &st) < 0 || gettimeofday(&tv, 0) < 0){
+    printf("%s: create clockfile failed\n", s);
+    exit(1);
+  }
+  close(fd);
+  unlink("clockfile");
+  if(st.crtime > tv.tv_sec || st.crtime + 1 < tv.tv_sec){
+    printf("%s: crtime %d, time %l\n", s, st.crtime, tv.tv_sec);
+    exit(1);
+  }
+}
+
+// lseek, pread, pwrite and O_APPEND.
+void
+seektest(char *s)
+{
+  enum { N = 100 };
+  int fd, fds[2], i, pid, xstatus;
+  char buf[16];
+  struct stat st;
+
+  fd = open("seekfile", O_CREATE | O_RDWR);
+  if(fd < 0 || write(fd, "hello world", 11) != 11){
+    printf("%s: create seekfile failed\n", s);
+    exit(1);
+  }
+  if(lseek(fd, 6, SEEK_SET) != 6 || read(fd, buf, 5) != 5 || memcmp(buf, "world", 5) != 0){
+    printf("%s: SEEK_SET failed\n", s);
+    exit(1);
+  }
+  if(lseek(fd, -5, SEEK_CUR) != 6 || lseek(fd, -1, SEEK_END) != 10 ||
+     read(fd, buf, 5) != 1 || buf[0] != 'd'){
+    printf("%s: SEEK_CUR or SEEK_END failed\n", s);
+    exit(1);
+  }
+  if(lseek(fd, -12, SEEK_END) >= 0 || lseek(fd, 0, 3) >= 0){
+    printf("%s: bad lseek succeeded\n", s);
+    exit(1);
+  }
+
+  if(pwrite(fd, "J", 1, 0) != 1 || pread(fd, buf, 5, 0) != 5 || memcmp(buf, "Jello", 5) != 0){
+    printf("%s: pread or pwrite failed\n", s);
+    exit(1);
+  }
+  if(lseek(fd, 0, SEEK_CUR) != 11){
+    printf("%s: pread or pwrite moved the offset\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  // Concurrent appenders don't overwrite each other.
+  fd = open("seekfile", O_RDWR | O_APPEND);
+  if(fd < 0){
+    printf("%s: open with O_APPEND failed\n", s);
+    exit(1);
+  }
+  pid = fork();
+  if(pid < 0){
+    printf("%s: fork failed\n", s);
+    exit(1);
+  }
+  for(i = 0; i < N; i++){
+    lseek(fd, 0, SEEK_SET);
+    if(write(fd, pid == 0 ? "cccc" : "pppp", 4) != 4){
+      printf("%s: append failed\n", s);
+      exit(1);
+    }
+  }
+  if(pid == 0)
+    exit(0);
+  wait(&xstatus);
+  if(xstatus != 0)
+    exit(xstatus);
+  close(fd);
+  if(stat("seekfile", &st) < 0 || st.size != 11 + 2 * N * 4){
+    printf("%s: seekfile has %d bytes, expected %d\n", s, (int)st.size, 11 + 2 * N * 4);
+    exit(1);
+  }
+  fd = open("seekfile", O_RDONLY);
+  if(pread(fd, buf, 5, 0) != 5 || memcmp(buf, "Jello", 5) != 0){
+    printf("%s: appends overwrote the start of seekfile\n", s);
+    exit(1);
+  }
+  close(fd);
+  unlink("seekfile");
+
+  fd = open(".", O_RDONLY);
+  if(lseek(fd, 0, SEEK_SET) != 0 || lseek(fd, 8, SEEK_SET) >= 0 || lseek(fd, 0, SEEK_END) >= 0){
+    printf("%s: lseek of a directory to a non-zero offset succeeded\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  if(pipe(fds) != 0){
+    printf("%s: pipe failed\n", s);
+    exit(1);
+  }
+  if(lseek(fds[0], 0, SEEK_SET) >= 0 || pread(fds[0], buf, 1, 0) >= 0){
+    printf("%s: lseek or pread of a pipe succeeded\n", s);
+    exit(1);
+  }
+  close(fds[0]);
+  close(fds[1]);
+}
+
+// truncate() and ftruncate() shrink files to any length, freeing the
+// blocks past the new end, and extend them with zeros.
+void
+truncatetest(char *s)
+{
+  enum { N = 300 };  // blocks, enough to need the doubly-indirect block
+  int fd, i;
+  char buf[BSIZE];
+  struct stat st;
+
+  fd = open("truncfile", O_CREATE | O_RDWR);
+  if(fd < 0){
+    printf("%s: create truncfile failed\n", s);
+    exit(1);
+  }
+  for(i = 0; i < N; i++){
+    memset(buf, 'a' + i % 26, sizeof(buf));
+    if(write(fd, buf, sizeof(buf)) != sizeof(buf)){
+      printf("%s: write failed\n", s);
+      exit(1);
+    }
+  }
+
+  // Cut in the middle of a block reached through the doubly-indirect block.
+  if(ftruncate(fd, 280 * BSIZE + 10) != 0 || fstat(fd, &st) < 0 || st.size != 280 * BSIZE + 10){
+    printf("%s: ftruncate failed\n", s);
+    exit(1);
+  }
+  if(pread(fd, buf, sizeof(buf), 280 * BSIZE) != 10 || buf[9] != 'a' + 280 % 26){
+    printf("%s: bad data before the new end\n", s);
+    exit(1);
+  }
+
+  // Growing again reads back zeros, also where the old data was.
+  if(truncate("truncfile", 282 * BSIZE) != 0){
+    printf("%s: truncate to grow failed\n", s);
+    exit(1);
+  }
+  if(pread(fd, buf, sizeof(buf), 280 * BSIZE) != BSIZE || buf[9] != 'a' + 280 % 26 || buf[10] != 0){
+    printf("%s: tail of the last block not zeroed\n", s);
+    exit(1);
+  }
+  if(pread(fd, buf, sizeof(buf), 281 * BSIZE) != BSIZE){
+    printf("%s: read of the extension failed\n", s);
+    exit(1);
+  }
+  for(i = 0; i < BSIZE; i++){
+    if(buf[i] != 0){
+      printf("%s: extension is not zero\n", s);
+      exit(1);
+    }
+  }
+
+  // Cut into the direct blocks, then write past the cut.
+  if(ftruncate(fd, 5) != 0 || lseek(fd, 0, SEEK_END) != 5 || write(fd, "xyz", 3) != 3){
+    printf("%s: ftruncate to 5 failed\n", s);
+    exit(1);
+  }
+  if(pread(fd, buf, sizeof(buf), 0) != 8 || memcmp(buf, "aaaaaxyz", 8) != 0){
+    printf("%s: bad data after ftruncate to 5\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  if(truncate("truncfile", -1) == 0 || truncate(".", 0) == 0 || truncate("nonexistent", 0) == 0){
+    printf("%s: bad truncate succeeded\n", s);
+    exit(1);
+  }
+  fd = open("truncfile", O_RDONLY);
+  if(ftruncate(fd, 0) == 0){
+    printf("%s: ftruncate of a read-only fd succeeded\n", s);
+    exit(1);
+  }
+  close(fd);
+  if(truncate("truncfile", 0) != 0 || stat("truncfile", &st) < 0 || st.size != 0){
+    printf("%s: truncate to 0 failed\n", s);
+    exit(1);
+  }
+  unlink("truncfile");
+}
+
+// writing past the end of a file leaves a hole, which reads as zeros
+// and takes no disk blocks.
+void
+sparsetest(char *s)
+{
+  enum { HOLE = 200 };  // blocks
+  int fd, i, meta;
+  char buf[BSIZE];
+  struct stat st;
+
+  fd = open("sparsefile", O_CREATE | O_RDWR);
+  if(fd < 0){
+    printf("%s: create sparsefile failed\n", s);
+    exit(1);
+  }
+  if(write(fd, "start", 5) != 5){
+    printf("%s: write failed\n", s);
+    exit(1);
+  }
+  if(pwrite(fd, "end", 3, HOLE * BSIZE) != 3){
+    printf("%s: write past the end failed\n", s);
+    exit(1);
+  }
+  if(fstat(fd, &st) < 0 || st.size != HOLE * BSIZE + 3){
+    printf("%s: size %d, expected %d\n", s, (int)st.size, HOLE * BSIZE + 3);
+    exit(1);
+  }
+  // The first block, the last block and an indirect block, which a
+  // file system made with mkfs -e does without.
+  meta = st.blocks - 2;
+  if(meta != 0 && meta != 1){
+    printf("%s: %d blocks allocated, expected 2 or 3\n", s, st.blocks);
+    exit(1);
+  }
+
+  for(i = 0; i < HOLE; i += 37){
+    if(pread(fd, buf, sizeof(buf), i * BSIZE) != BSIZE){
+      printf("%s: read of block %d failed\n", s, i);
+      exit(1);
+    }
+    if(i == 0 && memcmp(buf, "start", 5) != 0){
+      printf("%s: bad data in block 0\n", s);
+      exit(1);
+    }
+    for(int j = i == 0 ? 5 : 0; j < BSIZE; j++){
+      if(buf[j] != 0){
+        printf("%s: hole in block %d is not zero\n", s, i);
+        exit(1);
+      }
+    }
+  }
+  if(fstat(fd, &st) < 0 || st.blocks != 2 + meta){
+    printf("%s: reading the hole allocated blocks\n", s);
+    exit(1);
+  }
+
+  // Filling part of the hole allocates just that block.
+  if(pwrite(fd, "middle", 6, 100 * BSIZE) != 6 || fstat(fd, &st) < 0 ||
+     st.blocks != 3 + meta){
+    printf("%s: write into the hole failed\n", s);
+    exit(1);
+  }
+  if(pread(fd, buf, 6, 100 * BSIZE) != 6 || memcmp(buf, "middle", 6) != 0){
+    printf("%s: bad data in the middle\n", s);
+    exit(1);
+  }
+
+  if(ftruncate(fd, 0) != 0 || fstat(fd, &st) < 0 || st.blocks != 0){
+    printf("%s: ftruncate left %d blocks\n", s, st.blocks);
+    exit(1);
+  }
+  close(fd);
+  unlink("sparsefile");
+}
+
+// a single write() larger than the log must be split into
+// several transactions and still write every byte.
+void
+hugewrite(char *s)
+{
+  enum { N = 3 * LOGSIZE * BSIZE };
+  char *p;
+  int fd, i;
+
+  p = malloc(N);
+  if(p == 0){
+    printf("%s: malloc failed\n", s);
+    exit(1);
+  }
+  for(i = 0; i < N; i++)
+    p[i] = i % 251;
+
+  unlink("hugewrite");
+  fd = open("hugewrite", O_CREATE | O_RDWR);
+  if(fd < 0){
+    printf("%s: cannot create hugewrite\n", s);
+    exit(1);
+  }
+  if(write(fd, p, N) != N){
+    printf("%s: write failed\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  memset(p, 0, N);
+  fd = open("hugewrite", O_RDONLY);
+  if(fd < 0 || read(fd, p, N) != N){
+    printf("%s: read back failed\n", s);
+    exit(1);
+  }
+  close(fd);
+  for(i = 0; i < N; i++){
+    if(p[i] != (char)(i % 251)){
+      printf("%s: wrong byte at %d\n", s, i);
+      exit(1);
+    }
+  }
+  free(p);
+  unlink("hugewrite");
+}
+
+void
+fsynctest(char *s)
+{
+  int fd, fds[2];
+
+  unlink("fsyncf");
+  fd = open("fsyncf", O_CREATE | O_RDWR);
+  if(fd < 0){
+    printf("%s: cannot create fsyncf\n", s);
+    exit(1);
+  }
+  if(write(fd, "hello", 5) != 5){
+    printf("%s: write failed\n", s);
+    exit(1);
+  }
+  if(fsync(fd) != 0){
+    printf("%s: fsync failed\n", s);
+    exit(1);
+  }
+  // nothing new to commit.
+  if(fsync(fd) != 0){
+    printf("%s: second fsync failed\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  fd = open(".", O_RDONLY);
+  if(fd < 0 || fsync(fd) != 0){
+    printf("%s: fsync of a directory failed\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  if(pipe(fds) != 0){
+    printf("%s: pipe failed\n", s);
+    exit(1);
+  }
+  if(fsync(fds[0]) != -1){
+    printf("%s: fsync of a pipe succeeded\n", s);
+    exit(1);
+  }
+  close(fds[0]);
+  close(fds[1]);
+
+  if(fsync(-1) != -1){
+    printf("%s: fsync of a bad fd succeeded\n", s);
+    exit(1);
+  }
+  unlink("fsyncf");
+}
+
+// the file system is consistent after the tests before, and with a
+// directory and an open file that has been removed.
+void
+fscktest(char *s)
+{
+  int fd, n;
+
+  mkdir("fsckd");
+  fd = open("fsckd/f", O_CREATE | O_RDWR);
+  if(fd < 0){
+    printf("%s: cannot create fsckd/f\n", s);
+    exit(1);
+  }
+  if(write(fd, "x", 1) != 1 || unlink("fsckd/f") != 0){
+    printf("%s: write or unlink failed\n", s);
+    exit(1);
+  }
+  if((n = fsck()) != 0){
+    printf("%s: fsck found %d problems\n", s, n);
+    exit(1);
+  }
+  close(fd);
+  unlink("fsckd");
+}
+
+// mount the second disk, use a file on it, and check that its files
+// are not visible once it is unmounted, and that a mount point and a
+// file system in use cannot be removed.
+void
+mounttest(char *s)
+{
+  int fd;
+  char buf[8];
+
+  unlink("mnt");
+  if(mkdir("mnt") < 0){
+    printf("%s: mkdir mnt failed\n", s);
+    exit(1);
+  }
+  if(mount(2, "mnt") < 0){
+    printf("%s: mount failed\n", s);
+    exit(1);
+  }
+  if(mount(2, "mnt") == 0 || mount(1, "mnt") == 0){
+    printf("%s: mounted a disk twice\n", s);
+    exit(1);
+  }
+  fd = open("mnt/f", O_CREATE | O_RDWR);
+  if(fd < 0 || write(fd, "hello", 5) != 5){
+    printf("%s: cannot write mnt/f\n", s);
+    exit(1);
+  }
+  if(umount("mnt") == 0){
+    printf("%s: unmounted with an open file\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  // ".." of the mounted root leads back to this directory.
+  if(chdir("mnt") < 0){
+    printf("%s: chdir mnt failed\n", s);
+    exit(1);
+  }
+  fd = open("../mnt/f", O_RDONLY);
+  if(fd < 0 || read(fd, buf, sizeof(buf)) != 5 || memcmp(buf, "hello", 5) != 0){
+    printf("%s: cannot read ../mnt/f\n", s);
+    exit(1);
+  }
+  close(fd);
+  if(umount("/mnt") == 0){
+    printf("%s: unmounted the current directory\n", s);
+    exit(1);
+  }
+  if(chdir("..") < 0){
+    printf("%s: chdir .. failed\n", s);
+    exit(1);
+  }
+
+  if(unlink("mnt") == 0){
+    printf("%s: removed a mount point\n", s);
+    exit(1);
+  }
+  if(link("mnt/f", "mf") == 0 || rename("mnt/f", "mf") == 0){
+    printf("%s: linked across file systems\n", s);
+    exit(1);
+  }
+  if(unlink("mnt/f") < 0){
+    printf("%s: unlink mnt/f failed\n", s);
+    exit(1);
+  }
+  if(umount("mnt") < 0){
+    printf("%s: umount failed\n", s);
+    exit(1);
+  }
+  if(open("mnt/f", O_RDONLY) >= 0){
+    printf("%s: mnt/f visible after umount\n", s);
+    exit(1);
+  }
+  if(unlink("mnt") < 0){
+    printf("%s: unlink mnt failed\n", s);
+    exit(1);
+  }
+}
+
+// init mounts a tmpfs on /tmp, which keeps files and directories in
+// memory, and must release the memory of a file when it is removed.
+void
+tmpfstest(char *s)
+{
+  struct stat st, st2;
+  char name[] = "/tmp/td/big?";
+  int fd, i, nfile, pass, total[2];
+
+  if(stat("/tmp", &st) < 0 || stat("/", &st2) < 0 || st.dev == st2.dev){
+    printf("%s: no tmpfs on /tmp\n", s);
+    exit(1);
+  }
+  if(mkdir("/tmp/td") < 0){
+    printf("%s: mkdir /tmp/td failed\n", s);
+    exit(1);
+  }
+
+  // A page of data followed by a hole.
+  for(i = 0; i < BUFSZ; i++)
+    buf[i] = i % 251;
+  fd = open("/tmp/td/f", O_CREATE | O_RDWR);
+  if(fd < 0 || write(fd, buf, BUFSZ) != BUFSZ || ftruncate(fd, 3 * BUFSZ) < 0){
+    printf("%s: cannot write /tmp/td/f\n", s);
+    exit(1);
+  }
+  close(fd);
+  fd = open("/tmp/td/f", O_RDONLY);
+  for(pass = 0; pass < 3; pass++){
+    if(read(fd, buf, BUFSZ) != BUFSZ){
+      printf("%s: cannot read /tmp/td/f\n", s);
+      exit(1);
+    }
+    for(i = 0; i < BUFSZ; i++){
+      if(buf[i] != (char)(pass == 0 ? i % 251 : 0)){
+        printf("%s: bad data in /tmp/td/f\n", s);
+        exit(1);
+      }
+    }
+  }
+  if(read(fd, buf, 1) != 0){
+    printf("%s: read past the end of /tmp/td/f\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  if(link("/tmp/td/f", "/tmp/td/g") < 0 || stat("/tmp/td/f", &st) < 0 || st.nlink != 2){
+    printf("%s: link in /tmp failed\n", s);
+    exit(1);
+  }
+  if(link("/tmp/td/f", "tmpfslink") == 0){
+    printf("%s: linked across file systems\n", s);
+    exit(1);
+  }
+  if(unlink("/tmp/td/g") < 0 || stat("/tmp/td/f", &st) < 0 || st.nlink != 1){
+    printf("%s: unlink in /tmp failed\n", s);
+    exit(1);
+  }
+
+  if(stat("/tmp/td", &st) < 0 || mkdir("/tmp/td/d") < 0 ||
+     stat("/tmp/td", &st2) < 0 || st2.nlink != st.nlink + 1){
+    printf("%s: mkdir in /tmp failed\n", s);
+    exit(1);
+  }
+  fd = open("/tmp/td/d/x", O_CREATE | O_RDWR);
+  if(fd < 0){
+    printf("%s: create /tmp/td/d/x failed\n", s);
+    exit(1);
+  }
+  close(fd);
+  if(unlink("/tmp/td/d") == 0){
+    printf("%s: removed a non-empty directory\n", s);
+    exit(1);
+  }
+  if(chdir("/tmp/td/d") < 0 || (fd = open("../f", O_RDONLY)) < 0 || chdir("/") < 0){
+    printf("%s: cannot use .. in /tmp\n", s);
+    exit(1);
+  }
+  close(fd);
+  if(unlink("/tmp/td/d/x") < 0 || unlink("/tmp/td/d") < 0 ||
+     stat("/tmp/td", &st2) < 0 || st2.nlink != st.nlink){
+    printf("%s: rmdir in /tmp failed\n", s);
+    exit(1);
+  }
+
+  // Fill the tmpfs twice, removing the files in between. It must fill up,
+  // and hold as much the second time.
+  for(pass = 0; pass < 2; pass++){
+    total[pass] = 0;
+    for(nfile = 0; ; nfile++){
+      if(nfile == 20){
+        printf("%s: tmpfs did not fill up\n", s);
+        exit(1);
+      }
+      name[11] = '0' + nfile;
+      fd = open(name, O_CREATE | O_WRONLY);
+      if(fd < 0)
+        break;
+      while(write(fd, buf, BUFSZ) == BUFSZ)
+        ;
+      if(fstat(fd, &st) < 0){
+        printf("%s: fstat %s failed\n", s, name);
+        exit(1);
+      }
+      close(fd);
+      total[pass] += st.size;
+      if(st.size == 0){
+        nfile++;
+        break;
+      }
+    }
+    for(i = 0; i < nfile; i++){
+      name[11] = '0' + i;
+      if(unlink(name) < 0){
+        printf("%s: unlink %s failed\n", s, name);
+        exit(1);
+      }
+    }
+  }
+  if(total[0] == 0 || total[1] != total[0]){
+    printf("%s: tmpfs held %d bytes, then %d\n", s, total[0], total[1]);
+    exit(1);
+  }
+
+  if(unlink("/tmp/td/f") < 0 || unlink("/tmp/td") < 0){
+    printf("%s: cleanup of /tmp/td failed\n", s);
+    exit(1);
+  }
+}
+
+// kB of memory the tmpfs uses, from /proc/meminfo.
+int
+tmpfskb(char *s)
+{
+  char mbuf[128], *p;
+  int fd, n;
+
+  fd = open("/proc/meminfo", O_RDONLY);
+  if(fd < 0 || (n = read(fd, mbuf, sizeof(mbuf) - 1)) <= 0){
+    printf("%s: cannot read /proc/meminfo\n", s);
+    exit(1);
+  }
+  close(fd);
+  mbuf[n] = '\0';
+  for(p = mbuf; p + 6 <= mbuf + n; p++){
+    if(memcmp(p, "tmpfs:", 6) == 0)
+      return atoi(p + 7);
+  }
+  printf("%s: no tmpfs line in /proc/meminfo\n", s);
+  exit(1);
+}
+
+// unmounting the tmpfs frees all of its memory, and mounting it
+// again gives an empty file system.
+void
+tmpfsumount(char *s)
+{
+  struct stat st;
+  int fd;
+
+  fd = open("/tmp/um", O_CREATE | O_WRONLY);
+  if(fd < 0 || write(fd, buf, BUFSZ) != BUFSZ){
+    printf("%s: cannot write /tmp/um\n", s);
+    exit(1);
+  }
+  close(fd);
+  if(tmpfskb(s) == 0){
+    printf("%s: tmpfs uses no memory\n", s);
+    exit(1);
+  }
+  if(umount("/tmp") < 0){
+    printf("%s: umount /tmp failed\n", s);
+    exit(1);
+  }
+  if(tmpfskb(s) != 0){
+    printf("%s: tmpfs still uses %d kB after umount\n", s, tmpfskb(s));
+    exit(1);
+  }
+  if(mount(TMPFSDEV, "/tmp") < 0){
+    printf("%s: mount /tmp failed\n", s);
+    exit(1);
+  }
+  if(stat("/tmp/um", &st) == 0){
+    printf("%s: /tmp/um survived umount\n", s);
+    exit(1);
+  }
+  fd = open("/tmp/um", O_CREATE | O_WRONLY);
+  if(fd < 0 || write(fd, buf, BUFSZ) != BUFSZ){
+    printf("%s: cannot write /tmp/um after mount\n", s);
+    exit(1);
+  }
+  close(fd);
+  if(unlink("/tmp/um") < 0){
+    printf("%s: unlink /tmp/um failed\n", s);
+    exit(1);
+  }
+}
+
+// fill a file full of holes in a scattered order, so that on a file
+// system made with mkfs -e the extents are inserted between others,
+// spill into the extent block, and merge. Run with MKFSFLAGS=-e too.
+void
+holefill(char *s)
+{
+  enum { N = 60 };  // blocks
+  int fd, i, b, pass;
+  char buf[BSIZE];
+  struct stat st;
+
+  fd = open("holefile", O_CREATE | O_RDWR);
+  if(fd < 0){
+    printf("%s: create holefile failed\n", s);
+    exit(1);
+  }
+  // Every third block, then the rest in a scattered order.
+  for(pass = 0; pass < 2; pass++){
+    for(i = 0; i < N; i++){
+      b = pass == 0 ? 3 * i % N : (i * 37 + 11) % N;
+      if(pass == 0 ? i >= N / 3 : b % 3 == 0)
+        continue;
+      memset(buf, 'a' + b % 26, BSIZE);
+      if(pwrite(fd, buf, BSIZE, b * BSIZE) != BSIZE){
+        printf("%s: write of block %d failed\n", s, b);
+        exit(1);
+      }
+    }
+  }
+  for(b = 0; b < N; b++){
+    if(pread(fd, buf, BSIZE, b * BSIZE) != BSIZE){
+      printf("%s: read of block %d failed\n", s, b);
+      exit(1);
+    }
+    for(i = 0; i < BSIZE; i++){
+      if(buf[i] != 'a' + b % 26){
+        printf("%s: bad data in block %d\n", s, b);
+        exit(1);
+      }
+    }
+  }
+
+  // Growing the file with ftruncate or lseek leaves a hole that a
+  // write after it must not need filled.
+  if(ftruncate(fd, (N + 10) * BSIZE) < 0 || lseek(fd, 0, SEEK_END) != (N + 10) * BSIZE ||
+     write(fd, "x", 1) != 1 || lseek(fd, 10 * BSIZE, SEEK_CUR) < 0 || write(fd, "y", 1) != 1){
+    printf("%s: write past the end failed\n", s);
+    exit(1);
+  }
+  if(fstat(fd, &st) < 0 || st.size != (N + 20) * BSIZE + 2 ||
+     pread(fd, buf, 2, (N + 5) * BSIZE) != 2 || buf[0] != 0 || buf[1] != 0){
+    printf("%s: bad hole past block %d\n", s, N);
+    exit(1);
+  }
+  close(fd);
+  unlink("holefile");
+}
+
+// O_APPEND writes of up to MAXATOMICAPPEND bytes from concurrent
+// writers are never interleaved, even when they cross block boundaries.
+void
+appendatomic(char *s)
+{
+  enum { NCHILD = 4, N = 20, HDR = 100 };
+  static char buf[MAXATOMICAPPEND];
+  int fd, i, j, pid, xstatus, counts[NCHILD];
+
+  unlink("appendfile");
+  fd = open("appendfile", O_CREATE | O_WRONLY);
+  memset(buf, 'x', HDR);
+  if(fd < 0 || write(fd, buf, HDR) != HDR){
+    printf("%s: create appendfile failed\n", s);
+    exit(1);
+  }
+  close(fd);
+
+  for(i = 0; i < NCHILD; i++){
+    pid = fork();
+    if(pid < 0){
+      printf("%s: fork failed\n", s);
+      exit(1);
+    }
+    if(pid == 0){
+      fd = open("appendfile", O_WRONLY | O_APPEND);
+      if(fd < 0){
+        printf("%s: open with O_APPEND failed\n", s);
+        exit(1);
+      }
+      memset(buf, 'a' + i, sizeof(buf));
+      for(j = 0; j < N; j++){
+        if(write(fd, buf, sizeof(buf)) != sizeof(buf)){
+          printf("%s: append failed\n", s);
+          exit(1);
+        }
+      }
+      exit(0);
+    }
+  }
+  for(i = 0; i < NCHILD; i++){
+    wait(&xstatus);
+    if(xstatus != 0)
+      exit(xstatus);
+  }
+
+  fd = open("appendfile", O_RDONLY);
+  if(fd < 0 || read(fd, buf, HDR) != HDR){
+    printf("%s: open appendfile failed\n", s);
+    exit(1);
+  }
+  memset(counts, 0, sizeof(counts));
+  for(i = 0; i < NCHILD * N; i++){
+    if(read(fd, buf, sizeof(buf)) != sizeof(buf)){
+      printf("%s: appendfile too short\n", s);
+      exit(1);
+    }
+    if(buf[0] < 'a' || buf[0] >= 'a' + NCHILD){
+      printf("%s: bad record %d\n", s, i);
+      exit(1);
+    }
+    for(j = 1; j < sizeof(buf); j++){
+      if(buf[j] != buf[0]){
+        printf("%s: record %d interleaved at byte %d\n", s, i, j);
+        exit(1);
+      }
+    }
+    counts[buf[0] - 'a']++;
+  }
+  if(read(fd, buf, 1) != 0){
+    printf("%s: appendfile too long\n", s);
+    exit(1);
+  }
+  close(fd);
+  for(i = 0; i < NCHILD; i++){
+    if(counts[i] != N){
+      printf("%s: writer %d appended %d records\n", s, i, counts[i]);
+      exit(1);
+    }
+  }
+  unlink("appendfile");
+}
+
+// getdents() on an open directory lists every entry that exists
+// throughout, while other entries added in between convert the
+// directory to an index and split its leaves.
+void
+dirindexlist(char *s)
+{
+  enum { N0 = 40, N1 = 300, N = N0 + 2 * N1 };
+  static char seen[N];
+  char name[8], dbuf[64];
+  struct direntry *de;
+  int i, fd, ffd, dn, doff, round, first, last, k;
+
+  if(mkdir("dxl") != 0 || chdir("dxl") != 0){
+    printf("%s: mkdir dxl failed\n", s);
+    exit(1);
+  }
+  name[0] = 'g';
+  name[4] = '\0';
+  last = 0;
+  for(round = 0; round < 2; round++){
+    // Files 0..first exist while the directory is listed.
+    first = round == 0 ? N0 : N0 + N1;
+    for(i = last; i < first; i++){
+      name[1] = '0' + i / 100;
+      name[2] = '0' + (i / 10) % 10;
+      name[3] = '0' + i % 10;
+      if((fd = open(name, O_CREATE | O_RDWR)) < 0){
+        printf("%s: create %s failed\n", s, name);
+        exit(1);
+      }
+      close(fd);
+    }
+    memset(seen, 0, sizeof(seen));
+    if((fd = open(".", 0)) < 0){
+      printf("%s: open dxl failed\n", s);
+      exit(1);
+    }
+    for(k = 0; (dn = getdents(fd, dbuf, sizeof(dbuf))) > 0; k++){
+      for(doff = 0; doff < dn; doff += de->reclen){
+        de = (struct direntry*)(dbuf + doff);
+        if(de->name[0] == 'g')
+          seen[atoi(de->name + 1)] = 1;
+      }
+      if(k == 1){
+        // Add more entries after the first few are listed.
+        for(i = first; i < first + N1; i++){
+          name[1] = '0' + i / 100;
+          name[2] = '0' + (i / 10) % 10;
+          name[3] = '0' + i % 10;
+          if((ffd = open(name, O_CREATE | O_RDWR)) < 0){
+            printf("%s: create %s failed\n", s, name);
+            exit(1);
+          }
+          close(ffd);
+        }
+      }
+    }
+    if(dn < 0){
+      printf("%s: getdents failed in round %d\n", s, round);
+      exit(1);
+    }
+    close(fd);
+    for(i = 0; i < first; i++){
+      if(!seen[i]){
+        printf("%s: round %d did not list g%d\n", s, round, i);
+        exit(1);
+      }
+    }
+    last = first + N1;
+  }
+
+  for(i = 0; i < N; i++){
+    name[1] = '0' + i / 100;
+    name[2] = '0' + (i / 10) % 10;
+    name[3] = '0' + i % 10;
+    if(unlink(name) != 0){
+      printf("%s: unlink %s failed\n", s, name);
+      exit(1);
+    }
+  }
+  if(chdir("..") != 0 || rmdir("dxl") != 0){
+    printf("%s: rmdir dxl failed\n", s);
+    exit(1);
+  }
+}
+
 // use sbrk() to count how many free physical memory pages there are.
 // touches the pages to force allocation.
 // because out of memory with lazy allocation results in the process
@@ -2820,11 +4833,36 @@ main(int argc, char *argv[])
     {preempt, "preempt"},
     {exitwait, "exitwait"},
     {rmdot, "rmdot"},
-    {fourteen, "fourteen"},
+    {longname, "longname"},
     {bigfile, "bigfile"},
     {dirfile, "dirfile"},
     {iref, "iref"},
     {forktest, "forktest"},
+    {procfs, "procfs"},
+    {permtest, "permtest"},
+    {setuidtest, "setuidtest"},
+    {sandboxtest, "sandboxtest"},
+    {scripttest, "scripttest"},
+    {hugefile, "hugefile"},
+    {interleave, "interleave"},
+    {dirindex, "dirindex"},
+    {symlinktest, "symlinktest"},
+    {renametest, "renametest"},
+    {getdentstest, "getdentstest"},
+    {timestamps, "timestamps"},
+    {clocktest, "clocktest"},
+    {seektest, "seektest"},
+    {truncatetest, "truncatetest"},
+    {sparsetest, "sparsetest"},
+    {hugewrite, "hugewrite"},
+    {fsynctest, "fsynctest"},
+    {fscktest, "fscktest"},
+    {mounttest, "mounttest"},
+    {tmpfstest, "tmpfstest"},
+    {tmpfsumount, "tmpfsumount"},
+    {holefill, "holefill"},
+    {appendatomic, "appendatomic"},
+    {dirindexlist, "dirindexlist"},
     {bigdir, "bigdir"}, // slow
     { 0, 0},
   };
@@ -37,3 +37,29 @@ entry("sbrk");
 entry("sleep");
 entry("uptime");
 entry("poweroff");
+entry("getuid");
+entry("setuid");
+entry("chmod");
+entry("chown");
+entry("geteuid");
+entry("getgid");
+entry("setgid");
+entry("sandbox");
+entry("symlink");
+entry("readlink");
+entry("rename");
+entry("getdents");
+entry("rmdir");
+entry("utimes");
+entry("clock_gettime");
+entry("gettimeofday");
+entry("lseek");
+entry("pread");
+entry("pwrite");
+entry("truncate");
+entry("ftruncate");
+entry("fsync");
+entry("crash");
+entry("fsck");
+entry("mount");
+entry("umount");